@@ -0,0 +1,737 @@
+// NOTE(erick): Sprite animations. An animation set is an image with named
+// clips in it, each clip being a list of frames (rectangles of the image) that
+// are shown for some time each. Sets come from .anim files, with the sprites:
+//  image = player.png
+//  clip = walk_down
+//  fps = 10
+//  mode = loop
+//  frame = (0, 48, 32, 48)
+//  frame = (32, 48, 32, 48), 0.25
+// 'clip' starts a new clip, the lines after it describe it. fps (8 by default)
+// gives how long frames are shown, unless the frame has its own duration in
+// seconds after the rect. mode is loop (the default), once or ping_pong. The
+// image is looked for in the directory of the .anim file, like atlases do.
+//
+// Clips that play once report when they are done (see AnimationPlayer::update)
+// so the game can react, e.g. removing a sparkle when it faded out.
+//
+// The player sprite sheet has one clip for each thing the player can be doing
+// in each direction. Clip names are <state>_<direction>, with the states idle,
+// walk and push and the directions up, down, left and right. Missing clips
+// fall back to the closest one we have, so a sheet may have only idle clips or
+// only down ones. Levels can use an .anim file or, for simple sheets, declare
+// the rows of the sheet (lanes) in order with how many frames each has:
+//  player_lane = idle_down, 1
+//  player_lane = walk_down, 4
+//  player_lane = push_down, 4
+
+use std::path::Path;
+
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+
+use std::fmt;
+
+use level::*;
+use atlas::*;
+use game::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum PlayerState {
+    Idle,
+    Walk,
+    Push,
+}
+
+impl PlayerState {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PlayerState::Idle   => "idle",
+            PlayerState::Walk   => "walk",
+            PlayerState::Push   => "push",
+        }
+    }
+
+    fn all() -> [PlayerState; 3] {
+        [PlayerState::Idle, PlayerState::Walk, PlayerState::Push]
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up       => "up",
+        Direction::Down     => "down",
+        Direction::Left     => "left",
+        Direction::Right    => "right",
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct LaneDefinition {
+    pub name             : String,
+    pub number_of_frames : u32,
+}
+
+pub fn lane_name(state: PlayerState, direction: Direction) -> String {
+    format!("{}_{}", state.name(), direction_name(direction))
+}
+
+pub fn is_player_lane_name(name: &str) -> bool {
+    PlayerState::all().iter().any(|&state| {
+        Direction::all().iter().any(|&direction| lane_name(state, direction) == name)
+    })
+}
+
+// NOTE(erick): Parses '<lane name>, <number of frames>'.
+pub fn parse_lane_definition(value: &str) -> Option<LaneDefinition> {
+    let comma_index = match value.find(',') {
+        Some(index) => index,
+        None        => { return None; }
+    };
+
+    let name = value[..comma_index].trim();
+    let number_of_frames = match value[comma_index + 1..].trim().parse::<u32>() {
+        Ok(number) if number > 0    => number,
+        _                           => { return None; }
+    };
+
+    if !is_player_lane_name(name) {
+        return None;
+    }
+
+    Some(LaneDefinition {
+        name             : name.to_string(),
+        number_of_frames : number_of_frames,
+    })
+}
+
+// NOTE(erick): The index of the clip to use. Pushing falls back to walking,
+// walking to standing still and any direction to facing down. None when there
+// are no clips at all.
+pub fn find_player_clip(animation_set: &AnimationSet, state: PlayerState, direction: Direction) -> Option<usize> {
+    let mut candidates = Vec::new();
+    if state == PlayerState::Push {
+        candidates.push(lane_name(PlayerState::Push, direction));
+    }
+    if state != PlayerState::Idle {
+        candidates.push(lane_name(PlayerState::Walk, direction));
+    }
+    candidates.push(lane_name(PlayerState::Idle, direction));
+    candidates.push(lane_name(PlayerState::Idle, Direction::Down));
+
+    for candidate in candidates {
+        if let Some(index) = animation_set.clip_index(candidate.as_str()) {
+            return Some(index);
+        }
+    }
+
+    if animation_set.clips.is_empty() { None } else { Some(0) }
+}
+
+const DEFAULT_FPS : f32 = 8.0;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum AnimationMode {
+    Loop,
+    Once,
+    // NOTE(erick): Forwards and then backwards, without repeating the ends.
+    PingPong,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct AnimationFrame {
+    // NOTE(erick): Relative to where the sheet starts in the texture, which is
+    // the image itself unless the sheet is an atlas region.
+    pub rect     : AtlasRect,
+    // NOTE(erick): In seconds.
+    pub duration : f32,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct AnimationClip {
+    pub name   : String,
+    pub mode   : AnimationMode,
+    pub frames : Vec<AnimationFrame>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct AnimationSet {
+    // NOTE(erick): The image file, without the asset path but with the
+    // directory of the .anim file.
+    pub image : String,
+    pub clips : Vec<AnimationClip>,
+}
+
+impl AnimationSet {
+    // NOTE(erick): A sheet where every lane is a row of frames of the same
+    // size, shown at the same rate. This is what levels without an .anim file
+    // use.
+    pub fn from_lanes(image: &str, frame_width: u32, frame_height: u32, fps: u32, lanes: &[LaneDefinition]) -> AnimationSet {
+        let duration = if fps > 0 { 1.0 / fps as f32 } else { 0.0 };
+
+        let clips = lanes.iter().enumerate().map(|(row, lane)| {
+            let frames = (0..lane.number_of_frames).map(|column| {
+                AnimationFrame {
+                    rect     : AtlasRect {
+                        x      : column * frame_width,
+                        y      : row as u32 * frame_height,
+                        width  : frame_width,
+                        height : frame_height,
+                    },
+                    duration : duration,
+                }
+            }).collect();
+
+            AnimationClip {
+                name   : lane.name.clone(),
+                mode   : AnimationMode::Loop,
+                frames : frames,
+            }
+        }).collect();
+
+        AnimationSet {
+            image : image.to_string(),
+            clips : clips,
+        }
+    }
+
+    pub fn clip_index(&self, clip_name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == clip_name)
+    }
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Io(String, io::Error),
+    MissingEqualsSign { line: u32 },
+    UnknownKey { line: u32, key: String },
+    OutsideClip { line: u32, key: String },
+    BadNumber { line: u32, value: String },
+    BadMode { line: u32, value: String },
+    BadFrame { line: u32, value: String },
+    DuplicateClip { line: u32, name: String },
+    EmptyClip(String),
+    MissingImage,
+    NoClips,
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &AnimationError::Io(ref path, ref error)        => write!(f, "Could not read {}: {}", path, error),
+            &AnimationError::MissingEqualsSign { line }     => write!(f, "Line {}: Could not find '=' sign", line),
+            &AnimationError::UnknownKey { line, ref key }   => write!(f, "Line {}: Unknown variable: {}", line, key),
+            &AnimationError::OutsideClip { line, ref key }  => write!(f, "Line {}: {} must come after a clip", line, key),
+            &AnimationError::BadNumber { line, ref value }  => write!(f, "Line {}: Could not parse {} as a positive number", line, value),
+            &AnimationError::BadMode { line, ref value }    => write!(f, "Line {}: mode must be loop, once or ping_pong, not {}", line, value),
+            &AnimationError::BadFrame { line, ref value }   => write!(f, "Line {}: Could not parse {} as (x, y, width, height)[, duration]", line, value),
+            &AnimationError::DuplicateClip { line, ref name } => write!(f, "Line {}: There already is a clip called {}", line, name),
+            &AnimationError::EmptyClip(ref name)            => write!(f, "The clip {} has no frames", name),
+            &AnimationError::MissingImage                   => write!(f, "image must be specified"),
+            &AnimationError::NoClips                        => write!(f, "There must be at least one clip"),
+        }
+    }
+}
+
+fn parse_positive_number(value: &str) -> Option<f32> {
+    match value.parse::<f32>() {
+        Ok(number) if number > 0.0  => Some(number),
+        _                           => None,
+    }
+}
+
+// NOTE(erick): Parses '<rect>[, <duration>]'. The duration is None when the
+// frame doesn't have one.
+fn parse_frame(value: &str) -> Option<(AtlasRect, Option<f32>)> {
+    let rect_end = match value.find(')') {
+        Some(index) => index + 1,
+        None        => { return None; }
+    };
+
+    let rect = match parse_rect(&value[..rect_end]) {
+        Some(rect)  => rect,
+        None        => { return None; }
+    };
+
+    let rest = value[rect_end..].trim();
+    if rest.is_empty() {
+        return Some((rect, None));
+    }
+    if !rest.starts_with(',') {
+        return None;
+    }
+
+    parse_positive_number(rest[1..].trim()).map(|duration| (rect, Some(duration)))
+}
+
+// NOTE(erick): A clip while it is being parsed. Frames without a duration get
+// it from the fps once the whole clip was read.
+struct ClipBuilder {
+    name   : String,
+    mode   : AnimationMode,
+    fps    : f32,
+    frames : Vec<(AtlasRect, Option<f32>)>,
+}
+
+impl ClipBuilder {
+    fn build(self) -> AnimationClip {
+        let fps = self.fps;
+
+        AnimationClip {
+            name   : self.name,
+            mode   : self.mode,
+            frames : self.frames.into_iter().map(|(rect, duration)| {
+                AnimationFrame {
+                    rect     : rect,
+                    duration : duration.unwrap_or(1.0 / fps),
+                }
+            }).collect(),
+        }
+    }
+}
+
+// NOTE(erick): animation_name is relative to the sprites directory, like the
+// texture names in the .lvl files.
+pub fn parse_animation_file(animation_name: &str) -> Result<AnimationSet, Vec<AnimationError>> {
+    let animation_path_string = asset_path_string(AssetType::Sprite, animation_name);
+    let file = match File::open(Path::new(animation_path_string.as_str())) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![AnimationError::Io(animation_path_string, error)]); }
+    };
+
+    let mut errors = Vec::new();
+    let mut image = None;
+    let mut clips : Vec<ClipBuilder> = Vec::new();
+
+    let mut line_number = 0;
+    for line in BufReader::new(file).lines() {
+        line_number += 1;
+
+        let line = match line {
+            Ok(line)    => line,
+            Err(error)  => {
+                errors.push(AnimationError::Io(animation_path_string.clone(), error));
+                break;
+            }
+        };
+        if line == "" || line.starts_with("//") {
+            continue;
+        }
+
+        let attrib_index = match line.find('=') {
+            Some(index) => index,
+            None        => {
+                errors.push(AnimationError::MissingEqualsSign { line: line_number });
+                continue;
+            }
+        };
+
+        let _split = line.split_at(attrib_index);
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        match lhs {
+            "image" => {
+                let animation_directory = Path::new(animation_name).parent().unwrap_or(Path::new(""));
+                image = Some(animation_directory.join(rhs).to_string_lossy().to_string());
+                continue;
+            },
+            "clip"  => {
+                if clips.iter().any(|clip| clip.name == rhs) {
+                    errors.push(AnimationError::DuplicateClip { line: line_number, name: rhs.to_string() });
+                }
+
+                clips.push(ClipBuilder {
+                    name   : rhs.to_string(),
+                    mode   : AnimationMode::Loop,
+                    fps    : DEFAULT_FPS,
+                    frames : Vec::new(),
+                });
+                continue;
+            },
+            "fps" | "mode" | "frame"    => {},
+            _                           => {
+                errors.push(AnimationError::UnknownKey { line: line_number, key: lhs.to_string() });
+                continue;
+            },
+        }
+
+        let clip = match clips.last_mut() {
+            Some(clip)  => clip,
+            None        => {
+                errors.push(AnimationError::OutsideClip { line: line_number, key: lhs.to_string() });
+                continue;
+            }
+        };
+
+        match lhs {
+            "fps"   => {
+                match parse_positive_number(rhs) {
+                    Some(fps)   => { clip.fps = fps; },
+                    None        => { errors.push(AnimationError::BadNumber { line: line_number, value: rhs.to_string() }); },
+                }
+            },
+            "mode"  => {
+                match rhs {
+                    "loop"      => { clip.mode = AnimationMode::Loop; },
+                    "once"      => { clip.mode = AnimationMode::Once; },
+                    "ping_pong" => { clip.mode = AnimationMode::PingPong; },
+                    _           => { errors.push(AnimationError::BadMode { line: line_number, value: rhs.to_string() }); },
+                }
+            },
+            _       => {
+                match parse_frame(rhs) {
+                    Some(frame) => { clip.frames.push(frame); },
+                    None        => { errors.push(AnimationError::BadFrame { line: line_number, value: rhs.to_string() }); },
+                }
+            },
+        }
+    }
+
+    if image.is_none() {
+        errors.push(AnimationError::MissingImage);
+    }
+    if clips.is_empty() {
+        errors.push(AnimationError::NoClips);
+    }
+    for clip in &clips {
+        if clip.frames.is_empty() {
+            errors.push(AnimationError::EmptyClip(clip.name.clone()));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(AnimationSet {
+        image : image.unwrap(),
+        clips : clips.into_iter().map(ClipBuilder::build).collect(),
+    })
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum AnimationEvent {
+    // NOTE(erick): A clip that plays once showed its last frame for as long
+    // as it should. Holds the index of the clip.
+    Finished(usize),
+}
+
+// NOTE(erick): Where an entity is in its animation set. The set itself is not
+// kept here so entities can share it.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct AnimationPlayer {
+    pub clip_index  : usize,
+    pub frame_index : usize,
+    pub is_finished : bool,
+
+    time_in_frame   : f32,
+    going_backwards : bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(clip_index: usize) -> AnimationPlayer {
+        AnimationPlayer {
+            clip_index  : clip_index,
+            frame_index : 0,
+            is_finished : false,
+
+            time_in_frame   : 0.0,
+            going_backwards : false,
+        }
+    }
+
+    // NOTE(erick): Playing the clip that is already playing doesn't restart it.
+    pub fn play(&mut self, clip_index: usize) {
+        if clip_index != self.clip_index {
+            *self = AnimationPlayer::new(clip_index);
+        }
+    }
+
+    pub fn frame<'a>(&self, animation_set: &'a AnimationSet) -> &'a AnimationFrame {
+        &animation_set.clips[self.clip_index].frames[self.frame_index]
+    }
+
+    pub fn update(&mut self, animation_set: &AnimationSet, dt: f32) -> Option<AnimationEvent> {
+        if self.is_finished {
+            return None;
+        }
+
+        let clip = &animation_set.clips[self.clip_index];
+        let n_frames = clip.frames.len();
+
+        self.time_in_frame += dt;
+        loop {
+            let duration = clip.frames[self.frame_index].duration;
+            // NOTE(erick): Frames that last no time (sheets with no fps) never
+            // change.
+            if duration <= 0.0 || self.time_in_frame < duration {
+                return None;
+            }
+            self.time_in_frame -= duration;
+
+            match clip.mode {
+                AnimationMode::Loop     => {
+                    self.frame_index = (self.frame_index + 1) % n_frames;
+                },
+                AnimationMode::Once     => {
+                    if self.frame_index + 1 < n_frames {
+                        self.frame_index += 1;
+                    } else {
+                        self.is_finished = true;
+                        self.time_in_frame = 0.0;
+                        return Some(AnimationEvent::Finished(self.clip_index));
+                    }
+                },
+                AnimationMode::PingPong => {
+                    if n_frames == 1 {
+                        // NOTE(erick): Nowhere to go.
+                    } else if self.going_backwards {
+                        if self.frame_index == 0 {
+                            self.going_backwards = false;
+                            self.frame_index = 1;
+                        } else {
+                            self.frame_index -= 1;
+                        }
+                    } else {
+                        if self.frame_index + 1 == n_frames {
+                            self.going_backwards = true;
+                            self.frame_index -= 1;
+                        } else {
+                            self.frame_index += 1;
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    fn lanes_set(lane_names: &[&str]) -> AnimationSet {
+        let lanes : Vec<LaneDefinition> = lane_names.iter().map(|name| {
+            LaneDefinition {
+                name             : name.to_string(),
+                number_of_frames : 2,
+            }
+        }).collect();
+
+        AnimationSet::from_lanes("player.bmp", 32, 48, 8, &lanes)
+    }
+
+    // NOTE(erick): .anim files are read from the sprites directory, so the
+    // test ones are written there and removed right away.
+    fn parse_animation_text(test_name: &str, text: &str) -> Result<AnimationSet, Vec<AnimationError>> {
+        let animation_name = format!("test-{}-{}.anim", test_name, process::id());
+        let animation_path = asset_path_string(AssetType::Sprite, animation_name.as_str());
+
+        fs::write(&animation_path, text).unwrap();
+        let result = parse_animation_file(animation_name.as_str());
+        fs::remove_file(&animation_path).unwrap();
+
+        result
+    }
+
+    fn error_messages(result: Result<AnimationSet, Vec<AnimationError>>) -> Vec<String> {
+        match result {
+            Ok(_)       => panic!("A broken .anim file was accepted"),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    // NOTE(erick): The frame shown after each update of dt seconds.
+    fn frame_sequence(set: &AnimationSet, clip_index: usize, dt: f32, n_updates: usize) -> Vec<usize> {
+        let mut player = AnimationPlayer::new(clip_index);
+        (0..n_updates).map(|_| {
+            player.update(set, dt);
+            player.frame_index
+        }).collect()
+    }
+
+    const CLIPS : &'static str = "image = sparkle.png\n\
+                                  clip = spin\n\
+                                  fps = 4\n\
+                                  frame = (0, 0, 16, 16)\n\
+                                  frame = (16, 0, 16, 16)\n\
+                                  frame = (32, 0, 16, 16)\n\
+                                  clip = pulse\n\
+                                  fps = 4\n\
+                                  mode = ping_pong\n\
+                                  frame = (0, 16, 16, 16)\n\
+                                  frame = (16, 16, 16, 16)\n\
+                                  frame = (32, 16, 16, 16)\n\
+                                  clip = fade\n\
+                                  fps = 4\n\
+                                  mode = once\n\
+                                  frame = (0, 32, 16, 16), 0.5\n\
+                                  frame = (16, 32, 16, 16)\n";
+
+    #[test]
+    fn from_lanes_makes_a_row_per_lane() {
+        let set = lanes_set(&["idle_down", "walk_down"]);
+
+        assert_eq!(set.clips.len(), 2);
+        assert_eq!(set.clips[1].name, "walk_down");
+        assert_eq!(set.clips[1].frames[1].rect, AtlasRect { x: 32, y: 48, width: 32, height: 48 });
+        assert_eq!(set.clips[1].frames[1].duration, 0.125);
+    }
+
+    #[test]
+    fn uses_the_exact_clip_when_there_is_one() {
+        let set = lanes_set(&["idle_down", "walk_up", "push_up", "idle_up"]);
+
+        assert_eq!(find_player_clip(&set, PlayerState::Push, Direction::Up), Some(2));
+        assert_eq!(find_player_clip(&set, PlayerState::Walk, Direction::Up), Some(1));
+        assert_eq!(find_player_clip(&set, PlayerState::Idle, Direction::Up), Some(3));
+    }
+
+    #[test]
+    fn pushing_falls_back_to_walking_and_then_to_standing() {
+        let set = lanes_set(&["idle_down", "idle_left", "walk_left"]);
+        assert_eq!(find_player_clip(&set, PlayerState::Push, Direction::Left), Some(2));
+
+        let set = lanes_set(&["idle_down", "idle_left"]);
+        assert_eq!(find_player_clip(&set, PlayerState::Push, Direction::Left), Some(1));
+        assert_eq!(find_player_clip(&set, PlayerState::Walk, Direction::Left), Some(1));
+    }
+
+    #[test]
+    fn other_directions_fall_back_to_facing_down() {
+        let set = lanes_set(&["walk_down", "idle_down"]);
+
+        assert_eq!(find_player_clip(&set, PlayerState::Push, Direction::Right), Some(1));
+        assert_eq!(find_player_clip(&set, PlayerState::Idle, Direction::Up), Some(1));
+        // NOTE(erick): Walking right doesn't fall back to walking down.
+        assert_eq!(find_player_clip(&set, PlayerState::Walk, Direction::Right), Some(1));
+    }
+
+    #[test]
+    fn unknown_clips_fall_back_to_the_first_one() {
+        let set = lanes_set(&["run_0", "run_1"]);
+        assert_eq!(find_player_clip(&set, PlayerState::Walk, Direction::Left), Some(0));
+
+        let empty = lanes_set(&[]);
+        assert_eq!(find_player_clip(&empty, PlayerState::Idle, Direction::Down), None);
+    }
+
+    #[test]
+    fn reads_clips_with_their_modes_and_durations() {
+        let set = parse_animation_text("clips", CLIPS).unwrap();
+
+        assert_eq!(set.image, "sparkle.png");
+        assert_eq!(set.clips.len(), 3);
+        assert_eq!(set.clip_index("pulse"), Some(1));
+        assert_eq!(set.clips[0].mode, AnimationMode::Loop);
+        assert_eq!(set.clips[1].mode, AnimationMode::PingPong);
+        assert_eq!(set.clips[2].mode, AnimationMode::Once);
+
+        assert_eq!(set.clips[0].frames[2].rect, AtlasRect { x: 32, y: 0, width: 16, height: 16 });
+        assert_eq!(set.clips[0].frames[2].duration, 0.25);
+        assert_eq!(set.clips[2].frames[0].duration, 0.5);
+        assert_eq!(set.clips[2].frames[1].duration, 0.25);
+    }
+
+    #[test]
+    fn loop_starts_over() {
+        let set = parse_animation_text("loop", CLIPS).unwrap();
+        assert_eq!(frame_sequence(&set, 0, 0.25, 7), vec![1, 2, 0, 1, 2, 0, 1]);
+        // NOTE(erick): Big steps skip frames instead of slowing down.
+        assert_eq!(frame_sequence(&set, 0, 0.5, 3), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn ping_pong_does_not_repeat_the_ends() {
+        let set = parse_animation_text("ping_pong", CLIPS).unwrap();
+        assert_eq!(frame_sequence(&set, 1, 0.25, 8), vec![1, 2, 1, 0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn once_holds_the_last_frame_and_finishes_once() {
+        let set = parse_animation_text("once", CLIPS).unwrap();
+        let mut player = AnimationPlayer::new(2);
+
+        // NOTE(erick): The first frame lasts 0.5s, the second one 0.25s.
+        assert_eq!(player.update(&set, 0.25), None);
+        assert_eq!(player.frame_index, 0);
+        assert_eq!(player.update(&set, 0.25), None);
+        assert_eq!(player.frame_index, 1);
+        assert_eq!(player.update(&set, 0.25), Some(AnimationEvent::Finished(2)));
+        assert!(player.is_finished);
+
+        for _ in 0..4 {
+            assert_eq!(player.update(&set, 0.25), None);
+        }
+        assert_eq!(player.frame_index, 1);
+    }
+
+    #[test]
+    fn playing_another_clip_restarts_the_player() {
+        let set = parse_animation_text("play", CLIPS).unwrap();
+        let mut player = AnimationPlayer::new(0);
+        player.update(&set, 0.25);
+
+        player.play(0);
+        assert_eq!(player.frame_index, 1);
+
+        player.play(2);
+        assert_eq!((player.clip_index, player.frame_index), (2, 0));
+    }
+
+    #[test]
+    fn reports_every_broken_line() {
+        let messages = error_messages(parse_animation_text("broken", "fps = 8\n\
+                                                                       clip = walk_down\n\
+                                                                       fps = fast\n\
+                                                                       mode = backwards\n\
+                                                                       frame = (0, 0, 32)\n\
+                                                                       speed = 2\n\
+                                                                       frame (0, 0, 32, 32)\n\
+                                                                       clip = walk_down\n\
+                                                                       frame = (0, 0, 32, 32), -1\n"));
+
+        assert_eq!(messages, vec![
+            "Line 1: fps must come after a clip",
+            "Line 3: Could not parse fast as a positive number",
+            "Line 4: mode must be loop, once or ping_pong, not backwards",
+            "Line 5: Could not parse (0, 0, 32) as (x, y, width, height)[, duration]",
+            "Line 6: Unknown variable: speed",
+            "Line 7: Could not find '=' sign",
+            "Line 8: There already is a clip called walk_down",
+            "Line 9: Could not parse (0, 0, 32, 32), -1 as (x, y, width, height)[, duration]",
+            "image must be specified",
+            "The clip walk_down has no frames",
+            "The clip walk_down has no frames",
+        ]);
+    }
+
+    #[test]
+    fn files_without_clips_are_rejected() {
+        let messages = error_messages(parse_animation_text("no_clips", "image = sparkle.png\n"));
+        assert_eq!(messages, vec!["There must be at least one clip"]);
+    }
+}
@@ -0,0 +1,247 @@
+// NOTE(erick): A texture atlas is one image with named rectangles in it, so a
+// level can take all of its sprites from a single file. Atlas files live with
+// the sprites and use the same 'key = value' lines as the .lvl files:
+//  image = tiles.png
+//  wall = (0, 0, 32, 32)
+//  box_on_target = (96, 0, 28, 28)
+// The image is looked for in the directory of the atlas. Every other key names
+// a region: (x, y, width, height) in pixels, with y going down like in the
+// image.
+//
+// Levels refer to a region as <atlas file>:<region name>, e.g.
+//  wall_tile = tiles.atlas:wall
+// Anything without the ':' is a plain image file.
+
+extern crate regex;
+
+use self::regex::Regex;
+
+use std::collections::hash_map::HashMap;
+
+use std::path::Path;
+
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+
+use std::fmt;
+
+use level::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct AtlasRect {
+    pub x      : u32,
+    pub y      : u32,
+    pub width  : u32,
+    pub height : u32,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct TextureAtlas {
+    // NOTE(erick): The image file, without the asset path but with the
+    // directory of the atlas.
+    pub image   : String,
+    pub regions : HashMap<String, AtlasRect>,
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(String, io::Error),
+    MissingEqualsSign { line: u32 },
+    BadRect { line: u32, value: String },
+    MissingImage,
+    UnknownRegion(String),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &AtlasError::Io(ref path, ref error)        => write!(f, "Could not read {}: {}", path, error),
+            &AtlasError::MissingEqualsSign { line }     => write!(f, "Line {}: Could not find '=' sign", line),
+            &AtlasError::BadRect { line, ref value }    => write!(f, "Line {}: Could not parse {} as (x, y, width, height)", line, value),
+            &AtlasError::MissingImage                   => write!(f, "image must be specified"),
+            &AtlasError::UnknownRegion(ref name)        => write!(f, "There is no region called {}", name),
+        }
+    }
+}
+
+// NOTE(erick): Splits "tiles.atlas:wall" into ("tiles.atlas", "wall"). None for
+// plain image files.
+pub fn split_atlas_reference(texture_name: &str) -> Option<(&str, &str)> {
+    match texture_name.rfind(':') {
+        Some(index) => Some((&texture_name[..index], &texture_name[index + 1..])),
+        None        => None,
+    }
+}
+
+pub fn parse_rect(value: &str) -> Option<AtlasRect> {
+    // NOTE(erick): Matches:
+    // '(' <x> ',' <y> ',' <width> ',' <height> ')' with any white space between them.
+    let rect_re = Regex::new(r"^\(\s*([0-9]+)\s*,\s*([0-9]+)\s*,\s*([0-9]+)\s*,\s*([0-9]+)\s*\)$").unwrap();
+
+    let captures = match rect_re.captures(value) {
+        Some(cap)   => cap,
+        None        => { return None; }
+    };
+
+    let number = |index: usize| captures.get(index).unwrap().as_str().parse::<u32>().ok();
+    match (number(1), number(2), number(3), number(4)) {
+        (Some(x), Some(y), Some(width), Some(height)) if width > 0 && height > 0 => {
+            Some(AtlasRect {
+                x      : x,
+                y      : y,
+                width  : width,
+                height : height,
+            })
+        },
+        _ => None,
+    }
+}
+
+impl TextureAtlas {
+    pub fn region(&self, region_name: &str) -> Result<AtlasRect, AtlasError> {
+        match self.regions.get(region_name) {
+            Some(rect)  => Ok(*rect),
+            None        => Err(AtlasError::UnknownRegion(region_name.to_string())),
+        }
+    }
+}
+
+// NOTE(erick): atlas_name is relative to the sprites directory, like the
+// texture names in the .lvl files.
+pub fn parse_atlas(atlas_name: &str) -> Result<TextureAtlas, Vec<AtlasError>> {
+    let atlas_path_string = asset_path_string(AssetType::Sprite, atlas_name);
+    let file = match File::open(Path::new(atlas_path_string.as_str())) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![AtlasError::Io(atlas_path_string, error)]); }
+    };
+
+    let mut errors = Vec::new();
+    let mut image = None;
+    let mut regions = HashMap::new();
+
+    let mut line_number = 0;
+    for line in BufReader::new(file).lines() {
+        line_number += 1;
+
+        let line = match line {
+            Ok(line)    => line,
+            Err(error)  => {
+                errors.push(AtlasError::Io(atlas_path_string.clone(), error));
+                break;
+            }
+        };
+        if line == "" || line.starts_with("//") {
+            continue;
+        }
+
+        let attrib_index = match line.find('=') {
+            Some(index) => index,
+            None        => {
+                errors.push(AtlasError::MissingEqualsSign { line: line_number });
+                continue;
+            }
+        };
+
+        let _split = line.split_at(attrib_index);
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        if lhs == "image" {
+            let atlas_directory = Path::new(atlas_name).parent().unwrap_or(Path::new(""));
+            image = Some(atlas_directory.join(rhs).to_string_lossy().to_string());
+            continue;
+        }
+
+        match parse_rect(rhs) {
+            Some(rect)  => { regions.insert(lhs.to_string(), rect); },
+            None        => { errors.push(AtlasError::BadRect { line: line_number, value: rhs.to_string() }); },
+        }
+    }
+
+    if image.is_none() {
+        errors.push(AtlasError::MissingImage);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(TextureAtlas {
+        image   : image.unwrap(),
+        regions : regions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    // NOTE(erick): Atlases are read from the sprites directory, so the test
+    // ones are written there and removed right away.
+    fn parse_atlas_text(test_name: &str, text: &str) -> Result<TextureAtlas, Vec<AtlasError>> {
+        let atlas_name = format!("test-{}-{}.atlas", test_name, process::id());
+        let atlas_path = asset_path_string(AssetType::Sprite, atlas_name.as_str());
+
+        fs::write(&atlas_path, text).unwrap();
+        let result = parse_atlas(atlas_name.as_str());
+        fs::remove_file(&atlas_path).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn reads_a_good_atlas() {
+        let atlas = parse_atlas_text("good", "// The tiles\n\
+                                              image = tiles.png\n\
+                                              wall = (0, 0, 32, 32)\n\
+                                              box_on_target = ( 96 ,0, 28,28 )\n").unwrap();
+
+        assert_eq!(atlas.image, "tiles.png");
+        assert_eq!(atlas.regions.len(), 2);
+        assert_eq!(atlas.region("wall").unwrap(), AtlasRect { x: 0, y: 0, width: 32, height: 32 });
+        assert_eq!(atlas.region("box_on_target").unwrap(), AtlasRect { x: 96, y: 0, width: 28, height: 28 });
+    }
+
+    #[test]
+    fn reports_every_malformed_line() {
+        let errors = match parse_atlas_text("malformed", "wall = (0, 0, 32)\n\
+                                                          floor (32, 0, 32, 32)\n\
+                                                          target = (64, 0, 0, 32)\n") {
+            Ok(_)       => panic!("A malformed atlas was accepted"),
+            Err(errors) => errors,
+        };
+        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+
+        assert_eq!(messages, vec![
+            "Line 1: Could not parse (0, 0, 32) as (x, y, width, height)",
+            "Line 2: Could not find '=' sign",
+            "Line 3: Could not parse (64, 0, 0, 32) as (x, y, width, height)",
+            "image must be specified",
+        ]);
+    }
+
+    #[test]
+    fn unknown_regions_are_errors() {
+        let atlas = parse_atlas_text("unknown", "image = tiles.png\nwall = (0, 0, 32, 32)\n").unwrap();
+
+        match atlas.region("floor") {
+            Err(AtlasError::UnknownRegion(name))    => assert_eq!(name, "floor"),
+            result                                  => panic!("Expected UnknownRegion, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn splits_atlas_references() {
+        assert_eq!(split_atlas_reference("tiles.atlas:wall"), Some(("tiles.atlas", "wall")));
+        assert_eq!(split_atlas_reference("themes/tiles.atlas:wall"), Some(("themes/tiles.atlas", "wall")));
+        assert_eq!(split_atlas_reference("wall.bmp"), None);
+    }
+}
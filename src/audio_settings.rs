@@ -0,0 +1,225 @@
+// NOTE(erick): How loud things are. Volumes are percentages, the master volume
+// scales both the music and the sound effects. They are kept between runs in
+// the data directory (see the progress module), with the same 'key = value'
+// lines as the .lvl files:
+//  master_volume = 100
+//  music_volume = 80
+//  effects_volume = 100
+//  muted = false
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+
+use progress::*;
+
+pub const VOLUME_STEP : u32 = 10;
+const MAX_VOLUME : u32 = 100;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct AudioSettings {
+    pub master_volume  : u32,
+    pub music_volume   : u32,
+    pub effects_volume : u32,
+    pub muted          : bool,
+}
+
+pub fn audio_settings_file_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("audio.txt"))
+}
+
+// NOTE(erick): Moves the volume by steps of VOLUME_STEP, staying between 0 and
+// 100.
+pub fn step_volume(volume: u32, n_steps: i32) -> u32 {
+    let volume = volume as i32 + n_steps * VOLUME_STEP as i32;
+    volume.max(0).min(MAX_VOLUME as i32) as u32
+}
+
+// NOTE(erick): One step up, going back to 0 after 100. For menus that only
+// have a confirm button.
+pub fn cycle_volume(volume: u32) -> u32 {
+    if volume >= MAX_VOLUME { 0 } else { step_volume(volume, 1) }
+}
+
+impl AudioSettings {
+    pub fn new() -> AudioSettings {
+        AudioSettings {
+            master_volume  : 100,
+            music_volume   : 80,
+            effects_volume : 100,
+            muted          : false,
+        }
+    }
+
+    // NOTE(erick): From 0 to 1, with the master volume and mute applied.
+    pub fn music_gain(&self) -> f32 {
+        self.gain(self.music_volume)
+    }
+
+    pub fn effects_gain(&self) -> f32 {
+        self.gain(self.effects_volume)
+    }
+
+    fn gain(&self, volume: u32) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            (self.master_volume * volume) as f32 / (MAX_VOLUME * MAX_VOLUME) as f32
+        }
+    }
+
+    // NOTE(erick): A missing file gives the defaults. Lines we can't understand
+    // are reported and skipped.
+    pub fn load(path: &Path) -> AudioSettings {
+        let mut settings = AudioSettings::new();
+
+        let file = match File::open(path) {
+            Ok(file)    => file,
+            Err(_)      => { return settings; }
+        };
+
+        let mut line_number = 0;
+        for line in BufReader::new(file).lines() {
+            line_number += 1;
+
+            let line = match line {
+                Ok(line)    => line,
+                Err(error)  => {
+                    println!("Could not read {:?}: {}", path, error);
+                    break;
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            if !settings.parse_line(line.as_str()) {
+                println!("{:?} line {}: Could not parse audio setting. Skipping it", path, line_number);
+            }
+        }
+
+        settings
+    }
+
+    fn parse_line(&mut self, line: &str) -> bool {
+        let attrib_index = match line.find('=') {
+            Some(index) => index,
+            None        => { return false; }
+        };
+
+        let _split = line.split_at(attrib_index);
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        let volume = match rhs.parse::<u32>() {
+            Ok(volume) if volume <= MAX_VOLUME  => Some(volume),
+            _                                   => None,
+        };
+
+        match (lhs, volume) {
+            ("master_volume", Some(volume))     => { self.master_volume = volume; true },
+            ("music_volume", Some(volume))      => { self.music_volume = volume; true },
+            ("effects_volume", Some(volume))    => { self.effects_volume = volume; true },
+            ("muted", _)                        => {
+                match rhs.parse::<bool>() {
+                    Ok(muted)   => { self.muted = muted; true },
+                    Err(_)      => false,
+                }
+            },
+            _                                   => false,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = File::create(path)?;
+        write!(file, "master_volume = {}\n", self.master_volume)?;
+        write!(file, "music_volume = {}\n", self.music_volume)?;
+        write!(file, "effects_volume = {}\n", self.effects_volume)?;
+        write!(file, "muted = {}\n", self.muted)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn temp_settings_path(test_name: &str) -> PathBuf {
+        env::temp_dir().join(format!("sokoban-audio-{}-{}", process::id(), test_name)).join("audio.txt")
+    }
+
+    #[test]
+    fn saves_and_loads_the_settings() {
+        let path = temp_settings_path("round_trip");
+        let settings = AudioSettings {
+            master_volume  : 70,
+            music_volume   : 0,
+            effects_volume : 30,
+            muted          : true,
+        };
+
+        settings.save(&path).unwrap();
+        let loaded = AudioSettings::load(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn bad_lines_keep_the_defaults() {
+        let path = temp_settings_path("bad_lines");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "master_volume = 150\nmusic_volume = -10\neffects_volume = 40\nmuted = maybe\nloud\n").unwrap();
+
+        let loaded = AudioSettings::load(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        let mut expected = AudioSettings::new();
+        expected.effects_volume = 40;
+        assert_eq!(loaded, expected);
+
+        assert_eq!(AudioSettings::load(&temp_settings_path("missing")), AudioSettings::new());
+    }
+
+    #[test]
+    fn volumes_stay_between_0_and_100() {
+        assert_eq!(step_volume(95, 1), 100);
+        assert_eq!(step_volume(100, 1), 100);
+        assert_eq!(step_volume(5, -1), 0);
+        assert_eq!(step_volume(50, -2), 30);
+
+        assert_eq!(cycle_volume(90), 100);
+        assert_eq!(cycle_volume(100), 0);
+    }
+
+    #[test]
+    fn gains_apply_the_master_volume_and_mute() {
+        let mut settings = AudioSettings::new();
+        settings.master_volume = 50;
+        settings.music_volume = 80;
+        settings.effects_volume = 100;
+
+        assert_eq!(settings.music_gain(), 0.4);
+        assert_eq!(settings.effects_gain(), 0.5);
+
+        settings.muted = true;
+        assert_eq!(settings.music_gain(), 0.0);
+        assert_eq!(settings.effects_gain(), 0.0);
+    }
+}
@@ -0,0 +1,58 @@
+// NOTE(erick): Generates a level and writes it to assets/maps/ both as a
+// .lvl/.map pair and as XSB.
+// Usage: sokoban-generate <name> <width> <height> <boxes> [seed]
+
+extern crate sokoban;
+
+use sokoban::level::*;
+use sokoban::xsb::*;
+use sokoban::generator::*;
+
+use std::path::Path;
+
+fn main() {
+    let args : Vec<String> = std::env::args().collect();
+    if args.len() < 5 {
+        println!("Usage: {} <name> <width> <height> <boxes> [seed]", args[0]);
+        std::process::exit(2);
+    }
+
+    let name = args[1].as_str();
+    let parse_arg = |index: usize| -> u64 {
+        match args[index].parse::<u64>() {
+            Ok(value)   => value,
+            Err(_)      => {
+                println!("Could not parse {} as a number", args[index]);
+                std::process::exit(2);
+            }
+        }
+    };
+
+    let mut options = GeneratorOptions::new(parse_arg(2) as u32, parse_arg(3) as u32, parse_arg(4) as usize);
+    if args.len() > 5 {
+        options.seed = parse_arg(5);
+    }
+
+    let generated = match generate_level(name, &options) {
+        Some(generated) => generated,
+        None            => {
+            println!("Could not generate a level with these parameters");
+            std::process::exit(1);
+        }
+    };
+
+    let level = &generated.level;
+    if let Err(error) = write_level_file(name, &level.map, &level.textures, level.player_position) {
+        println!("Could not write {}.lvl: {}", name, error);
+        std::process::exit(2);
+    }
+
+    let xsb_path = asset_path_string(AssetType::Level, format!("{}.xsb", name).as_str());
+    if let Err(error) = write_xsb_file(Path::new(xsb_path.as_str()), &level.map, level.player_position) {
+        println!("Could not write {}: {}", xsb_path, error);
+        std::process::exit(2);
+    }
+
+    print!("{}", xsb_string(&level.map, level.player_position));
+    println!("Score {}: {} pushes, {} box changes", generated.score, generated.n_pushes, generated.n_box_changes);
+}
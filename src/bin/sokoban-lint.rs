@@ -0,0 +1,297 @@
+// NOTE(erick): Checks every level in assets/maps/ and every pack in
+// assets/packs/ and exits with a non-zero code if any of them has errors. Meant
+// to be run from the project root.
+
+extern crate sokoban;
+
+use sokoban::game::*;
+use sokoban::level::*;
+use sokoban::atlas::*;
+use sokoban::animation::*;
+use sokoban::sound::*;
+use sokoban::pack::*;
+
+use std::collections::hash_map::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+const LEVELS_DIRECTORY : &'static str = "assets/maps/";
+const PACKS_DIRECTORY : &'static str = "assets/packs/";
+
+struct Report {
+    n_errors   : u32,
+    n_warnings : u32,
+}
+
+impl Report {
+    fn new() -> Report {
+        Report {
+            n_errors   : 0,
+            n_warnings : 0,
+        }
+    }
+
+    fn error(&mut self, level_name: &str, message: &str) {
+        println!("Error({}.lvl): {}", level_name, message);
+        self.n_errors += 1;
+    }
+
+    fn warning(&mut self, level_name: &str, message: &str) {
+        println!("Warning({}.lvl): {}", level_name, message);
+        self.n_warnings += 1;
+    }
+
+    fn pack_error(&mut self, pack_path: &Path, message: &str) {
+        println!("Error({}): {}", pack_path.display(), message);
+        self.n_errors += 1;
+    }
+}
+
+fn level_names() -> Vec<String> {
+    let mut result = Vec::new();
+
+    let entries = match fs::read_dir(LEVELS_DIRECTORY) {
+        Ok(entries) => entries,
+        Err(error)  => {
+            println!("Could not read {}: {}", LEVELS_DIRECTORY, error);
+            std::process::exit(2);
+        }
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry)   => entry.path(),
+            Err(_)      => continue,
+        };
+
+        let is_level = path.extension().map_or(false, |extension| extension == "lvl");
+        if is_level {
+            if let Some(stem) = path.file_stem() {
+                result.push(stem.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    result.sort();
+    result
+}
+
+// NOTE(erick): Having no packs directory is fine.
+fn pack_paths() -> Vec<PathBuf> {
+    let mut result : Vec<PathBuf> = match fs::read_dir(PACKS_DIRECTORY) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_)      => Vec::new(),
+    };
+
+    result.sort();
+    result
+}
+
+fn check_assets(level_name: &str, level: &Level, report: &mut Report) {
+    let textures = &level.textures;
+    let mut sprites = vec![&textures.wall_tile, &textures.floor_tile, &textures.target_tile, &textures.box_sprite_sheet,
+                           &textures.player_sprite_sheet];
+    if let Some(ref box_on_target_sprite) = textures.box_on_target_sprite {
+        sprites.push(box_on_target_sprite);
+    }
+
+    for sprite in sprites.iter() {
+        // NOTE(erick): Atlas regions need the atlas, the region in it and the
+        // image the atlas points to.
+        let image = match split_atlas_reference(sprite.as_str()) {
+            Some((atlas_name, region_name)) => {
+                match parse_atlas(atlas_name) {
+                    Ok(atlas)   => {
+                        if let Err(error) = atlas.region(region_name) {
+                            report.error(level_name, format!("{}: {}", atlas_name, error).as_str());
+                        }
+                        atlas.image
+                    },
+                    Err(errors) => {
+                        for error in errors {
+                            report.error(level_name, format!("{}: {}", atlas_name, error).as_str());
+                        }
+                        continue;
+                    }
+                }
+            },
+            None                            => sprite.to_string(),
+        };
+
+        let sprite_path = asset_path_string(AssetType::Sprite, image.as_str());
+        if !Path::new(sprite_path.as_str()).exists() {
+            report.error(level_name, format!("Missing sprite {}", sprite_path).as_str());
+        }
+    }
+
+    let mut animations = Vec::new();
+    if let Some(ref player_animation) = textures.player_animation {
+        animations.push((player_animation, "idle_down"));
+    }
+    if let Some(ref effects_animation) = textures.effects_animation {
+        animations.push((effects_animation, "box_on_target"));
+    }
+
+    for &(animation_name, expected_clip) in animations.iter() {
+        match parse_animation_file(animation_name.as_str()) {
+            Ok(animation_set)   => {
+                let image_path = asset_path_string(AssetType::Sprite, animation_set.image.as_str());
+                if !Path::new(image_path.as_str()).exists() {
+                    report.error(level_name, format!("Missing sprite {}", image_path).as_str());
+                }
+                if animation_set.clip_index(expected_clip).is_none() {
+                    report.warning(level_name, format!("{} has no {} clip", animation_name, expected_clip).as_str());
+                }
+            },
+            Err(errors)         => {
+                for error in errors {
+                    report.error(level_name, format!("{}: {}", animation_name, error).as_str());
+                }
+            },
+        }
+    }
+
+    // NOTE(erick): Without a size the whole image is one frame and the lanes
+    // have nowhere to go.
+    if !textures.player_lanes.is_empty() && textures.player_sprite_size.is_none() {
+        report.warning(level_name, "player_lane is ignored without player_sprite_size");
+    }
+
+    if let Some(ref music_path) = level.map.level_music {
+        if !Path::new(music_path.as_str()).exists() {
+            report.error(level_name, format!("Missing music {}", music_path).as_str());
+        }
+    }
+
+    if let Some(ref sound_effects_path) = level.map.sound_effects {
+        match parse_sound_effects(sound_effects_path.as_str()) {
+            Ok(registry)    => {
+                for sound_path in registry.sounds.values() {
+                    if !Path::new(sound_path.as_str()).exists() {
+                        report.error(level_name, format!("Missing sound {}", sound_path).as_str());
+                    }
+                }
+            },
+            Err(errors)     => {
+                for error in errors {
+                    report.error(level_name, format!("{}: {}", sound_effects_path, error).as_str());
+                }
+            },
+        }
+    }
+}
+
+// NOTE(erick): Floor the player can't walk to and openings that let the player
+// walk out of the map. Boxes are ignored here.
+fn check_floor(level_name: &str, map: &Map, player_position: TilePosition, report: &mut Report) {
+    let reachable = map.reachable_tiles(player_position);
+
+    for &(x, y) in &reachable {
+        let mut is_open = x == 0 || y == 0 || x + 1 == map.n_cols() || y + 1 == map.n_lines();
+
+        for direction in Direction::all().iter() {
+            let (next_x, next_y) = direction.step((x, y));
+            if map.is_inside(next_x, next_y) {
+                if let TileType::Blank = map.tile_at(next_x as u32, next_y as u32) {
+                    is_open = true;
+                }
+            }
+        }
+
+        if is_open {
+            report.error(level_name, format!("The floor at {:?} is not enclosed by walls", (x, y)).as_str());
+        }
+    }
+
+    for y in 0..map.n_lines() {
+        for x in 0..map.n_cols() {
+            if !map.tile_at(x, y).is_walkable() || reachable.contains(&(x, y)) {
+                continue;
+            }
+
+            if !map.box_index_at((x, y)).is_none() {
+                report.error(level_name, format!("The box at {:?} is unreachable", (x, y)).as_str());
+            } else if map.tile_at(x, y) == TileType::Target {
+                report.error(level_name, format!("The target at {:?} is unreachable", (x, y)).as_str());
+            } else {
+                report.warning(level_name, format!("The floor at {:?} is unreachable", (x, y)).as_str());
+            }
+        }
+    }
+}
+
+fn check_next_levels(next_levels: &HashMap<String, String>, all_levels: &Vec<String>, report: &mut Report) {
+    let mut names : Vec<&String> = next_levels.keys().collect();
+    names.sort();
+
+    for name in &names {
+        let next_level = &next_levels[*name];
+        if !all_levels.contains(next_level) {
+            report.error(name, format!("next_level {}.lvl does not exist", next_level).as_str());
+        }
+    }
+
+    // NOTE(erick): Every cycle is reported once, by the level with the
+    // smallest name in it.
+    for name in &names {
+        let mut chain : Vec<&String> = vec![*name];
+
+        while let Some(next_level) = next_levels.get(*chain.last().unwrap()) {
+            if let Some(index) = chain.iter().position(|level| *level == next_level) {
+                let cycle = &chain[index..];
+                if cycle.iter().min() == Some(name) {
+                    let cycle_string : Vec<&str> = cycle.iter().map(|level| level.as_str()).collect();
+                    report.error(name, format!("next_level cycle: {} -> {}", cycle_string.join(" -> "), name).as_str());
+                }
+                break;
+            }
+
+            chain.push(next_level);
+        }
+    }
+}
+
+fn main() {
+    let all_levels = level_names();
+    let mut report = Report::new();
+    let mut next_levels = HashMap::new();
+
+    for level_name in &all_levels {
+        let level = match parse_level(level_name.as_str()) {
+            Ok(level)   => level,
+            Err(errors) => {
+                for error in errors {
+                    report.error(level_name, format!("{}", error).as_str());
+                }
+                continue;
+            }
+        };
+
+        check_assets(level_name, &level, &mut report);
+        check_floor(level_name, &level.map, level.player_position, &mut report);
+
+        if let Some(ref next_level_path) = level.map.next_level {
+            let next_level = remove_asset_path(AssetType::Level, next_level_path.as_str()).trim_end_matches(".lvl");
+            next_levels.insert(level_name.clone(), next_level.to_string());
+        }
+    }
+
+    check_next_levels(&next_levels, &all_levels, &mut report);
+
+    let all_packs = pack_paths();
+    for pack_path in &all_packs {
+        if let Err(errors) = read_pack_file(pack_path) {
+            for error in errors {
+                report.pack_error(pack_path, format!("{}", error).as_str());
+            }
+        }
+    }
+
+    println!("{} levels and {} packs checked: {} errors, {} warnings", all_levels.len(), all_packs.len(),
+             report.n_errors, report.n_warnings);
+
+    if report.n_errors > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,121 @@
+// NOTE(erick): Checks replay files without opening a window. The level of each
+// replay is found by its hash among the levels in assets/maps/ (and in the
+// pack given with --pack).
+// Usage: sokoban-replay [--pack <pack file>] <replay file>...
+// Exits with 1 if any replay doesn't solve its level.
+
+extern crate sokoban;
+
+use sokoban::level::*;
+use sokoban::pack::*;
+use sokoban::replay::*;
+
+use std::fs;
+use std::path::Path;
+
+fn shipped_levels() -> Vec<Level> {
+    let mut levels = Vec::new();
+
+    let maps_dir = asset_path_string(AssetType::Level, "");
+    let entries = match fs::read_dir(maps_dir.as_str()) {
+        Ok(entries) => entries,
+        Err(error)  => {
+            println!("Could not read {}: {}", maps_dir, error);
+            return levels;
+        }
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry)   => entry.path(),
+            Err(_)      => continue,
+        };
+        if path.extension().map_or(true, |extension| extension != "lvl") {
+            continue;
+        }
+
+        let level_name = path.file_stem().unwrap().to_string_lossy().to_string();
+        if let Ok(level) = parse_level(level_name.as_str()) {
+            levels.push(level);
+        }
+    }
+
+    levels
+}
+
+fn main() {
+    let args : Vec<String> = std::env::args().collect();
+
+    let mut levels = shipped_levels();
+    let mut replay_paths = Vec::new();
+
+    let mut arg_index = 1;
+    while arg_index < args.len() {
+        if args[arg_index] == "--pack" && arg_index + 1 < args.len() {
+            match read_pack_file(Path::new(args[arg_index + 1].as_str())) {
+                Ok(pack)        => { levels.extend(pack.levels.into_iter().map(|pack_level| pack_level.level)); },
+                Err(errors)     => {
+                    for error in errors {
+                        println!("Error({}): {}", args[arg_index + 1], error);
+                    }
+                    std::process::exit(2);
+                },
+            }
+            arg_index += 2;
+        } else {
+            replay_paths.push(args[arg_index].clone());
+            arg_index += 1;
+        }
+    }
+
+    if replay_paths.is_empty() {
+        println!("Usage: {} [--pack <pack file>] <replay file>...", args[0]);
+        std::process::exit(2);
+    }
+
+    let mut n_failed = 0;
+    for replay_path in &replay_paths {
+        let replay = match Replay::load(Path::new(replay_path.as_str())) {
+            Ok(replay)  => replay,
+            Err(errors) => {
+                for error in errors {
+                    println!("Error({}): {}", replay_path, error);
+                }
+                n_failed += 1;
+                continue;
+            }
+        };
+
+        let level = match levels.iter().find(|level| level.identity_hash() == replay.level_hash) {
+            Some(level) => level,
+            None        => {
+                println!("{}: No level with hash {:016x}", replay_path, replay.level_hash);
+                n_failed += 1;
+                continue;
+            }
+        };
+
+        match verify_replay(&replay, level) {
+            ReplayResult::Solved { n_moves, n_pushes } => {
+                println!("{}: {} solved in {} moves and {} pushes", replay_path, level.map.name, n_moves, n_pushes);
+            },
+            ReplayResult::NotSolved { n_moves, n_pushes } => {
+                println!("{}: {} not solved after {} moves and {} pushes", replay_path, level.map.name, n_moves, n_pushes);
+                n_failed += 1;
+            },
+            ReplayResult::IllegalMove { step, character } => {
+                println!("{}: Move {} ({}) is illegal in {}", replay_path, step, character, level.map.name);
+                n_failed += 1;
+            },
+            ReplayResult::WrongLevel => {
+                println!("{}: The replay is not for {}", replay_path, level.map.name);
+                n_failed += 1;
+            },
+        }
+    }
+
+    println!("{} replays checked: {} failed", replay_paths.len(), n_failed);
+    if n_failed > 0 {
+        std::process::exit(1);
+    }
+}
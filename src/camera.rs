@@ -0,0 +1,305 @@
+// NOTE(erick): What part of the level is on the screen. Everything here is in
+// tile units, right-handed like the rest of the game (y goes up), and position
+// is the lower-left corner of the view.
+//
+// The camera only moves when its focus (the player) leaves a dead zone around
+// the middle of the view, and then it eases towards where it should be instead
+// of jumping. It never shows what is outside the map, maps smaller than the
+// view are centered on it instead.
+//
+// The view keeps its size in tiles whatever the size of the window, the tiles
+// are scaled to fit and the rest of the window is left for the letterbox.
+
+use math::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Camera {
+    pub position      : Vector2,
+
+    // NOTE(erick): The size of the view in tiles.
+    pub width         : f32,
+    pub height        : f32,
+
+    // NOTE(erick): The focus can move this far (in tiles) from the middle of
+    // the view before the camera follows it.
+    pub dead_zone_width  : f32,
+    pub dead_zone_height : f32,
+
+    // NOTE(erick): How much of the way to its target the camera goes each
+    // second. Bigger is snappier.
+    pub follow_speed  : f32,
+
+    // NOTE(erick): Where the view is on the screen, in pixels. (screen_x0,
+    // screen_y0) is its top-left corner.
+    pub screen_x0     : i32,
+    pub screen_y0     : i32,
+    pub screen_width  : u32,
+    pub screen_height : u32,
+}
+
+impl Camera {
+    pub fn new(width: f32, height: f32, screen_width: u32, screen_height: u32) -> Camera {
+        let mut camera = Camera {
+            position      : Vector2::zero(),
+
+            width         : width,
+            height        : height,
+
+            dead_zone_width  : width * 0.3,
+            dead_zone_height : height * 0.3,
+
+            follow_speed  : 6.0,
+
+            screen_x0     : 0,
+            screen_y0     : 0,
+            screen_width  : 0,
+            screen_height : 0,
+        };
+        camera.fit_to_screen(screen_width, screen_height, None);
+
+        camera
+    }
+
+    // NOTE(erick): Makes the view as big as it can be inside the drawable area
+    // with square tiles, centered. With texture_size the tiles are scaled by
+    // whole numbers only, so pixel art doesn't get blurry; the view ends up
+    // smaller and the letterbox bigger. Screens too small for even 1x fall
+    // back to the plain fit.
+    pub fn fit_to_screen(&mut self, drawable_width: u32, drawable_height: u32, texture_size: Option<u32>) {
+        let mut tile_size = (drawable_width as f32 / self.width).min(drawable_height as f32 / self.height);
+
+        if let Some(texture_size) = texture_size {
+            let scale = (tile_size / texture_size as f32).floor();
+            if texture_size > 0 && scale >= 1.0 {
+                tile_size = scale * texture_size as f32;
+            }
+        }
+
+        self.screen_width = (tile_size * self.width) as u32;
+        self.screen_height = (tile_size * self.height) as u32;
+        self.screen_x0 = (drawable_width as i32 - self.screen_width as i32) / 2;
+        self.screen_y0 = (drawable_height as i32 - self.screen_height as i32) / 2;
+    }
+
+    pub fn tile_width_on_screen(&self) -> f32 {
+        self.screen_width as f32 / self.width
+    }
+
+    pub fn tile_height_on_screen(&self) -> f32 {
+        self.screen_height as f32 / self.height
+    }
+
+    // NOTE(erick): Where the camera wants to be so the focus is inside the dead
+    // zone. It moves as little as possible.
+    fn target_for(&self, focus: Vector2) -> Vector2 {
+        let mut target = self.position;
+
+        let dead_zone_x0 = self.position.x + (self.width - self.dead_zone_width) * 0.5;
+        let dead_zone_y0 = self.position.y + (self.height - self.dead_zone_height) * 0.5;
+
+        if focus.x < dead_zone_x0 {
+            target.x -= dead_zone_x0 - focus.x;
+        } else if focus.x > dead_zone_x0 + self.dead_zone_width {
+            target.x += focus.x - (dead_zone_x0 + self.dead_zone_width);
+        }
+
+        if focus.y < dead_zone_y0 {
+            target.y -= dead_zone_y0 - focus.y;
+        } else if focus.y > dead_zone_y0 + self.dead_zone_height {
+            target.y += focus.y - (dead_zone_y0 + self.dead_zone_height);
+        }
+
+        target
+    }
+
+    fn clamp_axis(position: f32, view_size: f32, map_size: f32) -> f32 {
+        if map_size <= view_size {
+            (map_size - view_size) * 0.5
+        } else {
+            position.max(0.0).min(map_size - view_size)
+        }
+    }
+
+    fn clamped(&self, position: Vector2, map_width: u32, map_height: u32) -> Vector2 {
+        Vector2::new(Camera::clamp_axis(position.x, self.width, map_width as f32),
+                     Camera::clamp_axis(position.y, self.height, map_height as f32))
+    }
+
+    pub fn follow(&mut self, focus: Vector2, map_width: u32, map_height: u32, dt: f32) {
+        let target = self.clamped(self.target_for(focus), map_width, map_height);
+        let t = (self.follow_speed * dt).min(1.0);
+
+        self.position = self.position + (target - self.position) * t;
+    }
+
+    // NOTE(erick): Centers the view on the focus right away. Used when a level
+    // is loaded, easing from the previous level would look weird.
+    pub fn snap_to(&mut self, focus: Vector2, map_width: u32, map_height: u32) {
+        let centered = Vector2::new(focus.x - self.width * 0.5, focus.y - self.height * 0.5);
+        self.position = self.clamped(centered, map_width, map_height);
+    }
+
+    // NOTE(erick): Moves the view by the given amount of tiles, staying inside
+    // the map. The editor uses it since there is no player to follow.
+    pub fn pan(&mut self, delta: Vector2, map_width: u32, map_height: u32) {
+        let position = self.position + delta;
+        self.position = self.clamped(position, map_width, map_height);
+    }
+
+    // NOTE(erick): The screen rect (x, y, width, height) of something at
+    // position with the given size, all in tiles. The screen is left-handed so
+    // y is the top edge.
+    pub fn to_screen_rect(&self, position: Vector2, width: f32, height: f32) -> (i32, i32, u32, u32) {
+        let tile_width = self.tile_width_on_screen();
+        let tile_height = self.tile_height_on_screen();
+
+        let x0 = ((position.x - self.position.x) * tile_width).floor() as i32;
+        let y0 = ((position.y - self.position.y) * tile_height).floor() as i32;
+        let x1 = ((position.x + width - self.position.x) * tile_width).floor() as i32;
+        let y1 = ((position.y + height - self.position.y) * tile_height).floor() as i32;
+
+        // NOTE(erick): Computing both edges (instead of the size) keeps
+        // neighbouring tiles from leaving gaps between them.
+        (self.screen_x0 + x0, self.screen_y0 + self.screen_height as i32 - y1,
+         (x1 - x0).max(0) as u32, (y1 - y0).max(0) as u32)
+    }
+
+    pub fn to_world(&self, screen_x: i32, screen_y: i32) -> Vector2 {
+        let view_x = screen_x - self.screen_x0;
+        let view_y = self.screen_y0 + self.screen_height as i32 - screen_y;
+
+        Vector2::new(self.position.x + view_x as f32 / self.tile_width_on_screen(),
+                     self.position.y + view_y as f32 / self.tile_height_on_screen())
+    }
+
+    pub fn is_on_view(&self, screen_x: i32, screen_y: i32) -> bool {
+        screen_x >= self.screen_x0 && screen_x < self.screen_x0 + self.screen_width as i32 &&
+            screen_y >= self.screen_y0 && screen_y < self.screen_y0 + self.screen_height as i32
+    }
+
+    // NOTE(erick): The tiles that are at least partially on the screen, as
+    // (x0, y0, x1, y1) with the ends excluded. It may go past the map.
+    pub fn visible_tiles(&self) -> (i32, i32, i32, i32) {
+        (self.position.x.floor() as i32,
+         self.position.y.floor() as i32,
+         (self.position.x + self.width).ceil() as i32,
+         (self.position.y + self.height).ceil() as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_at(position: Vector2, x: f32, y: f32) {
+        assert!((position.x - x).abs() < 0.001 && (position.y - y).abs() < 0.001,
+                "Expected ({}, {}), got ({}, {})", x, y, position.x, position.y);
+    }
+
+    // NOTE(erick): 10x8 tiles of 32 pixels. The dead zone goes from 3.5 to 6.5
+    // horizontally and from 2.8 to 5.2 vertically.
+    fn camera() -> Camera {
+        Camera::new(10.0, 8.0, 320, 256)
+    }
+
+    #[test]
+    fn stays_still_while_the_focus_is_in_the_dead_zone() {
+        let mut camera = camera();
+        camera.follow(Vector2::new(5.0, 4.0), 40, 40, 1.0);
+        assert_at(camera.position, 0.0, 0.0);
+
+        camera.follow(Vector2::new(6.4, 5.1), 40, 40, 1.0);
+        assert_at(camera.position, 0.0, 0.0);
+    }
+
+    #[test]
+    fn moves_just_enough_to_keep_the_focus_in_the_dead_zone() {
+        let mut camera = camera();
+        camera.follow(Vector2::new(8.0, 6.2), 40, 40, 1.0);
+        assert_at(camera.position, 1.5, 1.0);
+    }
+
+    #[test]
+    fn eases_towards_the_target() {
+        let mut camera = camera();
+        // NOTE(erick): follow_speed * dt is 0.3 of the way.
+        camera.follow(Vector2::new(8.0, 4.0), 40, 40, 0.05);
+        assert_at(camera.position, 0.45, 0.0);
+    }
+
+    #[test]
+    fn never_shows_what_is_outside_the_map() {
+        let mut camera = camera();
+        camera.follow(Vector2::new(100.0, 100.0), 20, 16, 1.0);
+        assert_at(camera.position, 10.0, 8.0);
+
+        camera.follow(Vector2::new(-5.0, -5.0), 20, 16, 1.0);
+        assert_at(camera.position, 0.0, 0.0);
+    }
+
+    #[test]
+    fn centers_maps_smaller_than_the_view() {
+        let mut camera = camera();
+        camera.follow(Vector2::new(1.0, 1.0), 6, 4, 1.0);
+        assert_at(camera.position, -2.0, -2.0);
+
+        camera.follow(Vector2::new(5.0, 3.0), 6, 4, 1.0);
+        assert_at(camera.position, -2.0, -2.0);
+
+        // NOTE(erick): Each axis on its own, a wide map still scrolls sideways.
+        camera.follow(Vector2::new(30.0, 1.0), 40, 4, 1.0);
+        assert_at(camera.position, 23.5, -2.0);
+    }
+
+    #[test]
+    fn fills_the_screen_and_letterboxes_the_rest() {
+        let mut camera = camera();
+        camera.fit_to_screen(1000, 700, None);
+
+        // NOTE(erick): Tiles are 87.5 pixels, the height is what limits them.
+        assert_eq!((camera.screen_width, camera.screen_height), (875, 700));
+        assert_eq!((camera.screen_x0, camera.screen_y0), (62, 0));
+
+        assert_at(camera.to_world(62, 700), 0.0, 0.0);
+        assert_at(camera.to_world(62 + 175, 700 - 175), 2.0, 2.0);
+    }
+
+    #[test]
+    fn scales_pixel_art_by_whole_numbers() {
+        let mut camera = camera();
+        camera.fit_to_screen(1000, 700, Some(32));
+
+        // NOTE(erick): 87.5 / 32 rounds down to 2x.
+        assert_eq!(camera.tile_width_on_screen(), 64.0);
+        assert_eq!((camera.screen_width, camera.screen_height), (640, 512));
+        assert_eq!((camera.screen_x0, camera.screen_y0), (180, 94));
+
+        assert_at(camera.to_world(180, 94 + 512), 0.0, 0.0);
+        assert_at(camera.to_world(180 + 3 * 64 + 32, 94 + 512 - 2 * 64 - 32), 3.5, 2.5);
+        assert!(camera.is_on_view(180, 94));
+        assert!(!camera.is_on_view(179, 94));
+        assert!(!camera.is_on_view(180 + 640, 94));
+    }
+
+    #[test]
+    fn small_screens_fall_back_to_the_plain_fit() {
+        let mut camera = camera();
+        camera.fit_to_screen(200, 160, Some(32));
+
+        assert_eq!((camera.screen_width, camera.screen_height), (200, 160));
+        assert_eq!((camera.screen_x0, camera.screen_y0), (0, 0));
+    }
+
+    #[test]
+    fn visible_tiles_cover_partially_visible_ones() {
+        let mut camera = camera();
+        camera.fit_to_screen(1000, 700, Some(32));
+        assert_eq!(camera.visible_tiles(), (0, 0, 10, 8));
+
+        camera.position = Vector2::new(1.5, 0.25);
+        assert_eq!(camera.visible_tiles(), (1, 0, 12, 9));
+        assert_at(camera.to_world(180, 94 + 512), 1.5, 0.25);
+    }
+}
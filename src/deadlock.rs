@@ -0,0 +1,429 @@
+// NOTE(erick): Detects positions from where a level can't be solved anymore.
+//  - Dead squares: tiles from where a box can never be pushed to a target.
+//  They only depend on the tiles, so we compute them once per level.
+//  - Freeze deadlocks: boxes that can't move along either axis, because of
+//  walls, dead squares or other frozen boxes, while not on a target.
+//  - Block deadlocks: a 2x2 square filled with walls and boxes where some box
+//  is not on a target.
+//  - Corral deadlocks: an area the player can't reach, fenced by boxes, that
+//  can't be opened nor solved. See find_corral_deadlock.
+
+use std::collections::HashSet;
+
+use game::*;
+
+// NOTE(erick): How many positions we look at before giving up on a corral.
+// Giving up means we don't report a deadlock.
+const MAX_CORRAL_STATES : usize = 2000;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Deadlock {
+    DeadSquare(TilePosition),
+    Freeze(Vec<TilePosition>),
+    Block(Vec<TilePosition>),
+    Corral(Vec<TilePosition>),
+}
+
+pub struct DeadlockDetector {
+    n_cols       : u32,
+    n_lines      : u32,
+    dead_squares : Vec<bool>,
+}
+
+impl DeadlockDetector {
+    pub fn new(map: &Map) -> DeadlockDetector {
+        let mut detector = DeadlockDetector {
+            n_cols       : map.n_cols(),
+            n_lines      : map.n_lines(),
+            dead_squares : vec![true; (map.n_cols() * map.n_lines()) as usize],
+        };
+
+        // NOTE(erick): Pull a box from every target. The tiles it can reach are
+        // the ones it can be pushed back from.
+        let mut stack = Vec::new();
+        for y in 0..map.n_lines() {
+            for x in 0..map.n_cols() {
+                if let TileType::Target = map.tile_at(x, y) {
+                    stack.push((x, y));
+                }
+            }
+        }
+
+        while let Some(position) = stack.pop() {
+            let index = detector.index(position);
+            if !detector.dead_squares[index] {
+                continue;
+            }
+            detector.dead_squares[index] = false;
+
+            for direction in Direction::all().iter() {
+                let (box_x, box_y) = direction.step(position);
+                if !DeadlockDetector::is_walkable(map, box_x, box_y) {
+                    continue;
+                }
+
+                let (player_x, player_y) = direction.step((box_x as u32, box_y as u32));
+                if !DeadlockDetector::is_walkable(map, player_x, player_y) {
+                    continue;
+                }
+
+                stack.push((box_x as u32, box_y as u32));
+            }
+        }
+
+        detector
+    }
+
+    fn index(&self, position: TilePosition) -> usize {
+        (position.1 * self.n_cols + position.0) as usize
+    }
+
+    fn is_walkable(map: &Map, x: i32, y: i32) -> bool {
+        map.is_inside(x, y) && map.tile_at(x as u32, y as u32).is_walkable()
+    }
+
+    pub fn is_dead_square(&self, position: TilePosition) -> bool {
+        if position.0 >= self.n_cols || position.1 >= self.n_lines {
+            return true;
+        }
+
+        self.dead_squares[self.index(position)]
+    }
+
+    // NOTE(erick): Checks every box in the map, then the corrals.
+    pub fn find_deadlock(&self, map: &Map, player: TilePosition) -> Option<Deadlock> {
+        for _box in &map.boxes {
+            let deadlock = self.check_box(map, *_box);
+            if !deadlock.is_none() {
+                return deadlock;
+            }
+        }
+
+        self.find_corral_deadlock(map, player)
+    }
+
+    // NOTE(erick): For every corral we keep only the boxes around it (the ones
+    // next to its tiles) and look for pushes that either let the player in or
+    // put all of those boxes on targets. Other boxes can only get in the way, so
+    // if no such pushes exist the level can't be solved anymore.
+    pub fn find_corral_deadlock(&self, map: &Map, player: TilePosition) -> Option<Deadlock> {
+        let mut has_box = vec![false; map.tiles.len()];
+        for _box in &map.boxes {
+            has_box[self.index(*_box)] = true;
+        }
+
+        let reachable = self.player_reach(map, player, &has_box);
+        let mut in_some_corral = vec![false; map.tiles.len()];
+
+        for y in 0..self.n_lines {
+            for x in 0..self.n_cols {
+                let index = self.index((x, y));
+                if in_some_corral[index] || reachable[index] || has_box[index] ||
+                    !map.tile_at(x, y).is_walkable() {
+                    continue;
+                }
+
+                // NOTE(erick): Flood fill the corral, collecting the boxes around it.
+                let mut corral = Vec::new();
+                let mut fence = Vec::new();
+                let mut stack = vec![(x, y)];
+                while let Some(position) = stack.pop() {
+                    let index = self.index(position);
+                    if in_some_corral[index] {
+                        continue;
+                    }
+                    in_some_corral[index] = true;
+                    corral.push(position);
+
+                    for neighbour in self.neighbours(map, position) {
+                        if has_box[self.index(neighbour)] {
+                            if !fence.contains(&neighbour) {
+                                fence.push(neighbour);
+                            }
+                        } else {
+                            stack.push(neighbour);
+                        }
+                    }
+                }
+
+                if !self.can_open_corral(map, player, &corral, &fence) {
+                    fence.sort();
+                    return Some(Deadlock::Corral(fence));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn can_open_corral(&self, map: &Map, player: TilePosition, corral: &[TilePosition], fence: &[TilePosition]) -> bool {
+        let on_targets = |boxes: &Vec<TilePosition>| {
+            boxes.iter().all(|_box| map.tile_at(_box.0, _box.1) == TileType::Target)
+        };
+
+        let mut start = fence.to_vec();
+        start.sort();
+        if on_targets(&start) {
+            return true;
+        }
+
+        // NOTE(erick): Positions are the boxes plus the top-left most tile the
+        // player can reach, so walking around doesn't make new positions.
+        let mut seen = HashSet::new();
+        let mut stack = vec![(start, player)];
+
+        while let Some((boxes, player)) = stack.pop() {
+            let mut has_box = vec![false; map.tiles.len()];
+            for _box in &boxes {
+                has_box[self.index(*_box)] = true;
+            }
+
+            let reachable = self.player_reach(map, player, &has_box);
+            if corral.iter().any(|position| reachable[self.index(*position)]) {
+                return true;
+            }
+
+            let top_left = (0..reachable.len()).find(|index| reachable[*index]).unwrap();
+            if !seen.insert((boxes.clone(), top_left)) {
+                continue;
+            }
+            if seen.len() > MAX_CORRAL_STATES {
+                return true;
+            }
+
+            for (box_slot, _box) in boxes.iter().enumerate() {
+                for direction in Direction::all().iter() {
+                    let (player_x, player_y) = direction.opposite().step(*_box);
+                    let (next_x, next_y) = direction.step(*_box);
+                    if !DeadlockDetector::is_walkable(map, player_x, player_y) ||
+                        !reachable[self.index((player_x as u32, player_y as u32))] ||
+                        !DeadlockDetector::is_walkable(map, next_x, next_y) {
+                        continue;
+                    }
+
+                    let next = (next_x as u32, next_y as u32);
+                    if has_box[self.index(next)] || self.is_dead_square(next) {
+                        continue;
+                    }
+
+                    let mut next_boxes = boxes.clone();
+                    next_boxes[box_slot] = next;
+                    next_boxes.sort();
+                    if on_targets(&next_boxes) {
+                        return true;
+                    }
+
+                    stack.push((next_boxes, *_box));
+                }
+            }
+        }
+
+        false
+    }
+
+    fn neighbours(&self, map: &Map, position: TilePosition) -> Vec<TilePosition> {
+        Direction::all().iter()
+            .map(|direction| direction.step(position))
+            .filter(|&(x, y)| DeadlockDetector::is_walkable(map, x, y))
+            .map(|(x, y)| (x as u32, y as u32))
+            .collect()
+    }
+
+    // NOTE(erick): The tiles the player can walk to without pushing anything.
+    fn player_reach(&self, map: &Map, player: TilePosition, has_box: &Vec<bool>) -> Vec<bool> {
+        let mut reachable = vec![false; map.tiles.len()];
+        let mut stack = vec![player];
+
+        while let Some(position) = stack.pop() {
+            let index = self.index(position);
+            if reachable[index] {
+                continue;
+            }
+            reachable[index] = true;
+
+            for neighbour in self.neighbours(map, position) {
+                if !has_box[self.index(neighbour)] {
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    // NOTE(erick): Only looks for deadlocks involving the given box. That's all
+    // that needs checking after a push.
+    pub fn check_box(&self, map: &Map, box_position: TilePosition) -> Option<Deadlock> {
+        let on_target = |position: &TilePosition| map.tile_at(position.0, position.1) == TileType::Target;
+
+        if !on_target(&box_position) && self.is_dead_square(box_position) {
+            return Some(Deadlock::DeadSquare(box_position));
+        }
+
+        if let Some(block) = self.find_block(map, box_position) {
+            return Some(Deadlock::Block(block));
+        }
+
+        let mut frozen_boxes = Vec::new();
+        let mut treated_as_wall = Vec::new();
+        if self.is_frozen(map, box_position, &mut treated_as_wall, &mut frozen_boxes) {
+            if !frozen_boxes.iter().all(|_box| on_target(_box)) {
+                frozen_boxes.sort();
+                frozen_boxes.dedup();
+                return Some(Deadlock::Freeze(frozen_boxes));
+            }
+        }
+
+        None
+    }
+
+    fn is_wall(map: &Map, x: i32, y: i32) -> bool {
+        !DeadlockDetector::is_walkable(map, x, y)
+    }
+
+    fn has_box(map: &Map, x: i32, y: i32) -> bool {
+        map.is_inside(x, y) && !map.box_index_at((x as u32, y as u32)).is_none()
+    }
+
+    // NOTE(erick): Looks at the four 2x2 squares containing the box. Returns the
+    // boxes of the first square made only of walls and boxes where some box is
+    // not on a target.
+    fn find_block(&self, map: &Map, box_position: TilePosition) -> Option<Vec<TilePosition>> {
+        let (box_x, box_y) = (box_position.0 as i32, box_position.1 as i32);
+
+        for &(x0, y0) in &[(box_x - 1, box_y - 1), (box_x, box_y - 1), (box_x - 1, box_y), (box_x, box_y)] {
+            let square = [(x0, y0), (x0 + 1, y0), (x0, y0 + 1), (x0 + 1, y0 + 1)];
+
+            let is_filled = square.iter().all(|&(x, y)| {
+                DeadlockDetector::is_wall(map, x, y) || DeadlockDetector::has_box(map, x, y)
+            });
+
+            if !is_filled {
+                continue;
+            }
+
+            let boxes : Vec<TilePosition> = square.iter()
+                .filter(|&&(x, y)| DeadlockDetector::has_box(map, x, y))
+                .map(|&(x, y)| (x as u32, y as u32))
+                .collect();
+            if !boxes.iter().all(|_box| map.tile_at(_box.0, _box.1) == TileType::Target) {
+                return Some(boxes);
+            }
+        }
+
+        None
+    }
+
+    fn is_frozen(&self, map: &Map, position: TilePosition, treated_as_wall: &mut Vec<TilePosition>,
+                 frozen_boxes: &mut Vec<TilePosition>) -> bool {
+        treated_as_wall.push(position);
+        let n_frozen_boxes = frozen_boxes.len();
+
+        let horizontal = self.is_blocked_on_axis(map, position, Direction::Left, treated_as_wall, frozen_boxes);
+        let vertical = horizontal &&
+            self.is_blocked_on_axis(map, position, Direction::Down, treated_as_wall, frozen_boxes);
+
+        treated_as_wall.pop();
+
+        if horizontal && vertical {
+            frozen_boxes.push(position);
+            true
+        } else {
+            // NOTE(erick): The neighbours were only frozen if this box was.
+            frozen_boxes.truncate(n_frozen_boxes);
+            false
+        }
+    }
+
+    // NOTE(erick): A box is blocked along an axis if there is a wall on either
+    // side, dead squares on both sides or a frozen box on either side. The box
+    // being checked counts as a wall while we look at its neighbours, otherwise
+    // two boxes side by side would recurse forever.
+    fn is_blocked_on_axis(&self, map: &Map, position: TilePosition, direction: Direction,
+                          treated_as_wall: &mut Vec<TilePosition>, frozen_boxes: &mut Vec<TilePosition>) -> bool {
+        let sides = [direction.step(position), direction.opposite().step(position)];
+
+        let is_solid = |x: i32, y: i32, treated_as_wall: &Vec<TilePosition>| {
+            DeadlockDetector::is_wall(map, x, y) || treated_as_wall.contains(&(x as u32, y as u32))
+        };
+
+        if sides.iter().any(|&(x, y)| is_solid(x, y, treated_as_wall)) {
+            return true;
+        }
+
+        if sides.iter().all(|&(x, y)| self.is_dead_square((x as u32, y as u32))) {
+            return true;
+        }
+
+        for &(x, y) in sides.iter() {
+            if DeadlockDetector::has_box(map, x, y) {
+                if self.is_frozen(map, (x as u32, y as u32), treated_as_wall, frozen_boxes) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use level::*;
+    use xsb::*;
+
+    fn level(lines: &[&str]) -> Level {
+        let lines : Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        level_from_xsb("test", &lines).unwrap()
+    }
+
+    fn position(level: &Level, col: u32, row: u32) -> TilePosition {
+        Map::from_left_to_right_handed((col, row), level.map.n_lines())
+    }
+
+    #[test]
+    fn finds_dead_squares() {
+        let level = level(&["#####", "#@ .#", "#$  #", "#####"]);
+        let detector = DeadlockDetector::new(&level.map);
+
+        let box_position = position(&level, 1, 2);
+        assert_eq!(detector.check_box(&level.map, box_position), Some(Deadlock::DeadSquare(box_position)));
+    }
+
+    #[test]
+    fn block_check_skips_squares_on_targets() {
+        // NOTE(erick): The first square around the box is all boxes on targets,
+        // the one to its right is not.
+        let level = level(&["#######", "#@  ..#", "#**$  #", "#**$  #", "#######"]);
+        let detector = DeadlockDetector::new(&level.map);
+
+        match detector.check_box(&level.map, position(&level, 2, 2)) {
+            Some(Deadlock::Block(boxes))    => { assert!(boxes.contains(&position(&level, 3, 2))); },
+            deadlock                        => panic!("{:?}", deadlock),
+        }
+    }
+
+    #[test]
+    fn finds_corral_deadlocks() {
+        // NOTE(erick): The box can only go left, into a dead end, and the target
+        // is behind it.
+        let level = level(&["######", "##.###", "# $ @#", "## ###", "######"]);
+        let detector = DeadlockDetector::new(&level.map);
+
+        assert_eq!(detector.check_box(&level.map, position(&level, 2, 2)), None);
+        assert_eq!(detector.find_deadlock(&level.map, level.player_position),
+                   Some(Deadlock::Corral(vec![position(&level, 2, 2)])));
+    }
+
+    #[test]
+    fn solvable_levels_have_no_deadlocks() {
+        let level = level(&["####", "# .#", "#  ###", "#*@  #", "#  $ #", "#  ###", "####"]);
+        let detector = DeadlockDetector::new(&level.map);
+
+        assert_eq!(detector.find_deadlock(&level.map, level.player_position), None);
+    }
+}
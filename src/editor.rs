@@ -0,0 +1,239 @@
+// NOTE(erick): Level editing. The frontend translates mouse clicks into tile
+// positions and key presses into tools and text; everything else lives here so
+// it doesn't depend on SDL.
+
+use game::*;
+use level::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum EditorTool {
+    Paint(TileType),
+    Box,
+    Player,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum EditorField {
+    FileName,
+    LevelName,
+    LevelMusic,
+    NextLevel,
+}
+
+impl EditorField {
+    pub fn label(&self) -> &'static str {
+        match *self {
+            EditorField::FileName   => "file",
+            EditorField::LevelName  => "level_name",
+            EditorField::LevelMusic => "level_music",
+            EditorField::NextLevel  => "next_level",
+        }
+    }
+
+    // NOTE(erick): The order Tab cycles through the fields.
+    pub fn next(&self) -> EditorField {
+        match *self {
+            EditorField::FileName   => EditorField::LevelName,
+            EditorField::LevelName  => EditorField::LevelMusic,
+            EditorField::LevelMusic => EditorField::NextLevel,
+            EditorField::NextLevel  => EditorField::FileName,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Editor {
+    // NOTE(erick): The .lvl name without the extension. write_level_file adds
+    // it and the asset path.
+    pub file_name       : String,
+
+    pub map             : Map,
+    pub player_position : TilePosition,
+    pub textures        : LevelTextures,
+
+    pub tool            : EditorTool,
+    pub has_unsaved_changes : bool,
+
+    // NOTE(erick): The field being typed into and its text so far. The map
+    // only changes when the edit is committed.
+    pub editing_field   : Option<EditorField>,
+    pub field_text      : String,
+}
+
+impl Editor {
+    pub fn new(file_name: &str, level: &Level) -> Editor {
+        Editor {
+            file_name       : file_name.to_string(),
+
+            map             : level.map.clone(),
+            player_position : level.player_position,
+            textures        : level.textures.clone(),
+
+            tool            : EditorTool::Paint(TileType::Wall),
+            has_unsaved_changes : false,
+
+            editing_field   : None,
+            field_text      : String::new(),
+        }
+    }
+
+    fn is_walkable(&self, position: TilePosition) -> bool {
+        self.map.tile_at(position.0, position.1).is_walkable()
+    }
+
+    // NOTE(erick): Applies the current tool to a tile. Returns whether anything
+    // changed. The player start is never covered by a wall or a box.
+    pub fn apply_tool(&mut self, position: TilePosition) -> bool {
+        if !self.map.is_inside(position.0 as i32, position.1 as i32) {
+            return false;
+        }
+
+        match self.tool {
+            EditorTool::Paint(tile) => {
+                if self.map.tile_at(position.0, position.1) == tile {
+                    return false;
+                }
+                if !tile.is_walkable() {
+                    if position == self.player_position {
+                        return false;
+                    }
+                    if let Some(box_index) = self.map.box_index_at(position) {
+                        self.map.boxes.remove(box_index);
+                    }
+                }
+
+                self.map.set_tile_at(position.0, position.1, tile);
+            },
+            EditorTool::Box => {
+                if !self.is_walkable(position) || position == self.player_position ||
+                    !self.map.box_index_at(position).is_none() {
+                    return false;
+                }
+
+                self.map.boxes.push(position);
+            },
+            EditorTool::Player => {
+                if !self.is_walkable(position) || position == self.player_position ||
+                    !self.map.box_index_at(position).is_none() {
+                    return false;
+                }
+
+                self.player_position = position;
+            },
+        }
+
+        self.has_unsaved_changes = true;
+        true
+    }
+
+    pub fn remove_box(&mut self, position: TilePosition) -> bool {
+        match self.map.box_index_at(position) {
+            Some(box_index) => {
+                self.map.boxes.remove(box_index);
+                self.has_unsaved_changes = true;
+                true
+            },
+            None            => false,
+        }
+    }
+
+    // NOTE(erick): Music and next level are shown without the asset path, the
+    // same way they are written to the .lvl file.
+    pub fn field_value(&self, field: EditorField) -> String {
+        match field {
+            EditorField::FileName   => self.file_name.clone(),
+            EditorField::LevelName  => self.map.name.clone(),
+            EditorField::LevelMusic => self.map.level_music.as_ref()
+                .map_or(String::new(), |path| remove_asset_path(AssetType::Sound, path.as_str()).to_string()),
+            EditorField::NextLevel  => self.map.next_level.as_ref()
+                .map_or(String::new(), |path| remove_asset_path(AssetType::Level, path.as_str()).to_string()),
+        }
+    }
+
+    pub fn start_editing(&mut self, field: EditorField) {
+        self.field_text = self.field_value(field);
+        self.editing_field = Some(field);
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        if !self.editing_field.is_none() {
+            self.field_text.push_str(text);
+        }
+    }
+
+    pub fn erase_character(&mut self) {
+        self.field_text.pop();
+    }
+
+    pub fn cancel_editing(&mut self) {
+        self.editing_field = None;
+        self.field_text.clear();
+    }
+
+    // NOTE(erick): An empty text removes the optional keys.
+    pub fn commit_editing(&mut self) {
+        let field = match self.editing_field.take() {
+            Some(field) => field,
+            None        => { return; }
+        };
+
+        let text = self.field_text.trim().to_string();
+        self.field_text.clear();
+
+        let optional_path = |asset_type: AssetType| {
+            if text.is_empty() {
+                None
+            } else {
+                Some(asset_path_string(asset_type, text.as_str()))
+            }
+        };
+
+        match field {
+            EditorField::FileName   => {
+                if !text.is_empty() {
+                    self.file_name = text.clone();
+                }
+            },
+            EditorField::LevelName  => { self.map.name = text.clone(); },
+            EditorField::LevelMusic => { self.map.level_music = optional_path(AssetType::Sound); },
+            EditorField::NextLevel  => { self.map.next_level = optional_path(AssetType::Level); },
+        }
+
+        self.has_unsaved_changes = true;
+    }
+
+    pub fn to_level(&self) -> Level {
+        Level {
+            map             : self.map.clone(),
+            player_position : self.player_position,
+            textures        : self.textures.clone(),
+        }
+    }
+
+    // NOTE(erick): Only writes levels that would load back without errors.
+    pub fn save(&mut self) -> Result<(), Vec<LevelError>> {
+        let mut errors = validate_level(&self.map, self.player_position);
+        // NOTE(erick): Zero boxes and zero targets is valid for validate_level,
+        // but write_level_file refuses it.
+        if errors.is_empty() && self.map.boxes.is_empty() {
+            errors.push(LevelError::BoxTargetMismatch { boxes: 0, targets: 0 });
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        if let Err(error) = write_level_file(self.file_name.as_str(), &self.map, &self.textures, self.player_position) {
+            return Err(vec![LevelError::Write(format!("{}.lvl", self.file_name), error)]);
+        }
+        self.has_unsaved_changes = false;
+
+        Ok(())
+    }
+}
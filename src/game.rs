@@ -0,0 +1,532 @@
+// NOTE(erick): This module holds the game rules. It must not know anything
+// about SDL so we can load and play levels without a display.
+
+pub type TilePosition = (u32, u32);
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum TileType {
+    Floor,
+    Wall,
+    Target,
+    Blank,
+}
+
+impl TileType {
+    pub fn from_code(code: u32) -> Option<TileType> {
+        match code {
+            0 | 2 | 3   => Some(TileType::Floor),
+            1           => Some(TileType::Wall),
+            4           => Some(TileType::Target),
+            5           => Some(TileType::Blank),
+            _           => None,
+        }
+    }
+
+    pub fn to_code(&self) -> u32 {
+        match self {
+            &TileType::Floor     => 0,
+            &TileType::Wall      => 1,
+            &TileType::Target    => 4,
+            &TileType::Blank     => 5,
+        }
+    }
+
+    pub fn is_walkable(&self) -> bool {
+        match self {
+            &TileType::Floor | &TileType::Target => true,
+            _                                    => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Map {
+    pub name        : String,
+    pub level_music : Option<String>,
+    pub next_level  : Option<String>,
+    // NOTE(erick): A .sfx file, see the sound module.
+    pub sound_effects : Option<String>,
+
+    pub tiles: Vec<TileType>,
+    pub tiles_stride: i32,
+
+    pub boxes: Vec<TilePosition>,
+}
+
+impl Map {
+    pub fn new(name: &str) -> Map {
+        Map {
+            name        : name.to_string(),
+            level_music : None,
+            next_level  : None,
+            sound_effects : None,
+
+            tiles: Vec::new(),
+            tiles_stride: -1,
+
+            boxes: Vec::new(),
+        }
+    }
+
+    pub fn from_left_to_right_handed(position : (u32, u32), n_lines: u32) -> (u32, u32) {
+        (position.0, n_lines - position.1 - 1)
+    }
+
+    fn tile_index(&self, x: u32, y: u32) -> usize {
+        // NOTE(erick): Tiles are storage in a left-handed coordinate system.
+        // We invert it here
+        let y = self.n_lines() - y - 1;
+        (y * self.n_cols() + x) as usize
+    }
+
+    pub fn tile_at(&self, x: u32, y: u32) -> TileType {
+        self.tiles[self.tile_index(x, y)]
+    }
+
+    pub fn set_tile_at(&mut self, x: u32, y: u32, tile: TileType) {
+        let index = self.tile_index(x, y);
+        self.tiles[index] = tile;
+    }
+
+    pub fn n_cols(&self) -> u32 {
+        if self.tiles_stride < 0 {
+            0
+        }
+        else {
+            self.tiles_stride as u32
+        }
+    }
+
+    pub fn n_lines(&self) -> u32 {
+        if self.tiles_stride <= 0 {
+            0
+        } else {
+            self.tiles.len() as u32 / self.tiles_stride as u32
+        }
+    }
+
+    pub fn is_inside(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.n_cols() && (y as u32) < self.n_lines()
+    }
+
+    pub fn box_index_at(&self, position: TilePosition) -> Option<usize> {
+        self.boxes.iter().position(|_box| *_box == position)
+    }
+
+    // NOTE(erick): Every walkable tile the player could walk to from start if
+    // there were no boxes (start included).
+    pub fn reachable_tiles(&self, start: TilePosition) -> Vec<TilePosition> {
+        let mut result = Vec::new();
+        if !self.is_inside(start.0 as i32, start.1 as i32) {
+            return result;
+        }
+
+        let n_cols = self.n_cols();
+        let mut visited = vec![false; self.tiles.len()];
+        let mut stack = vec![start];
+
+        while let Some(position) = stack.pop() {
+            let visited_index = (position.1 * n_cols + position.0) as usize;
+            if visited[visited_index] {
+                continue;
+            }
+            visited[visited_index] = true;
+
+            if !self.tile_at(position.0, position.1).is_walkable() {
+                continue;
+            }
+            result.push(position);
+
+            for direction in Direction::all().iter() {
+                let (next_x, next_y) = direction.step(position);
+                if self.is_inside(next_x, next_y) {
+                    stack.push((next_x as u32, next_y as u32));
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn is_solved(&self) -> bool {
+        for _box in &self.boxes {
+            if let TileType::Target = self.tile_at(_box.0, _box.1) {
+
+            } else {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn all() -> [Direction; 4] {
+        [Direction::Up, Direction::Down, Direction::Left, Direction::Right]
+    }
+
+    // NOTE(erick): Right-handed, like the tile positions.
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            &Direction::Up      => ( 0,  1),
+            &Direction::Down    => ( 0, -1),
+            &Direction::Left    => (-1,  0),
+            &Direction::Right   => ( 1,  0),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            &Direction::Up      => Direction::Down,
+            &Direction::Down    => Direction::Up,
+            &Direction::Left    => Direction::Right,
+            &Direction::Right   => Direction::Left,
+        }
+    }
+
+    // NOTE(erick): Picks the dominant axis. Small values (like a joystick
+    // resting close to the center) give no direction at all.
+    pub fn from_axis(x: f32, y: f32) -> Option<Direction> {
+        const THRESHOLD : f32 = 0.5;
+
+        if x.abs() < THRESHOLD && y.abs() < THRESHOLD {
+            return None;
+        }
+
+        if x.abs() >= y.abs() {
+            if x > 0.0 { Some(Direction::Right) } else { Some(Direction::Left) }
+        } else {
+            if y > 0.0 { Some(Direction::Up) } else { Some(Direction::Down) }
+        }
+    }
+
+    // NOTE(erick): LURD notation: lowercase letters are moves, uppercase ones
+    // are pushes.
+    pub fn to_lurd(&self, is_push: bool) -> char {
+        let c = match self {
+            &Direction::Up      => 'u',
+            &Direction::Down    => 'd',
+            &Direction::Left    => 'l',
+            &Direction::Right   => 'r',
+        };
+
+        if is_push { c.to_ascii_uppercase() } else { c }
+    }
+
+    pub fn from_lurd(c: char) -> Option<(Direction, bool)> {
+        let direction = match c.to_ascii_lowercase() {
+            'u' => Direction::Up,
+            'd' => Direction::Down,
+            'l' => Direction::Left,
+            'r' => Direction::Right,
+            _   => { return None; }
+        };
+
+        Some((direction, c.is_uppercase()))
+    }
+
+    pub fn step(&self, position: TilePosition) -> (i32, i32) {
+        let (dx, dy) = self.offset();
+        (position.0 as i32 + dx, position.1 as i32 + dy)
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum MoveResult {
+    Blocked,
+    Moved,
+    // NOTE(erick): The index of the pushed box in Map::boxes.
+    Pushed(usize),
+}
+
+// NOTE(erick): One entry of the move history. pushed_box is the index (in
+// Map::boxes) of the box moved along with the player, if any.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct HistoryEntry {
+    pub direction  : Direction,
+    pub pushed_box : Option<usize>,
+}
+
+impl HistoryEntry {
+    pub fn is_push(&self) -> bool {
+        !self.pushed_box.is_none()
+    }
+}
+
+// NOTE(erick): The state of a level being played: the map (with the boxes),
+// the player's tile and every move made so far.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Board {
+    pub map    : Map,
+    pub player : TilePosition,
+
+    pub history    : Vec<HistoryEntry>,
+    // NOTE(erick): Moves taken back by undo. Cleared as soon as a new move is made.
+    pub redo_stack : Vec<HistoryEntry>,
+}
+
+impl Board {
+    pub fn new(map: Map, player: TilePosition) -> Board {
+        Board {
+            map    : map,
+            player : player,
+
+            history    : Vec::new(),
+            redo_stack : Vec::new(),
+        }
+    }
+
+    pub fn n_moves(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn n_pushes(&self) -> usize {
+        self.history.iter().filter(|entry| entry.is_push()).count()
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.map.is_solved()
+    }
+
+    // NOTE(erick): A tile the player or a box can be moved into.
+    pub fn is_free(&self, x: i32, y: i32) -> bool {
+        if !self.map.is_inside(x, y) {
+            return false;
+        }
+
+        let position = (x as u32, y as u32);
+        self.map.tile_at(position.0, position.1).is_walkable() &&
+            self.map.box_index_at(position).is_none()
+    }
+
+    pub fn try_move(&mut self, direction: Direction) -> MoveResult {
+        let result = self.apply_move(direction);
+
+        if let Some(entry) = Board::history_entry(direction, result) {
+            self.history.push(entry);
+            self.redo_stack.clear();
+        }
+
+        result
+    }
+
+    pub fn undo(&mut self) -> Option<HistoryEntry> {
+        let entry = match self.history.pop() {
+            Some(entry) => entry,
+            None        => { return None; }
+        };
+
+        let (back_x, back_y) = entry.direction.opposite().step(self.player);
+        if let Some(box_index) = entry.pushed_box {
+            // NOTE(erick): The box is always right in front of the player.
+            self.map.boxes[box_index] = self.player;
+        }
+        self.player = (back_x as u32, back_y as u32);
+
+        self.redo_stack.push(entry);
+        Some(entry)
+    }
+
+    pub fn redo(&mut self) -> Option<HistoryEntry> {
+        let entry = match self.redo_stack.pop() {
+            Some(entry) => entry,
+            None        => { return None; }
+        };
+
+        let result = self.apply_move(entry.direction);
+        match Board::history_entry(entry.direction, result) {
+            Some(applied) => {
+                self.history.push(applied);
+                Some(applied)
+            },
+            None => {
+                // NOTE(erick): This should never happen since the redo stack
+                // is cleared whenever the board changes in a different way.
+                self.redo_stack.clear();
+                None
+            },
+        }
+    }
+
+    // NOTE(erick): Undoes every move. They can still be redone.
+    pub fn restart(&mut self) {
+        while !self.undo().is_none() {}
+    }
+
+    fn history_entry(direction: Direction, result: MoveResult) -> Option<HistoryEntry> {
+        match result {
+            MoveResult::Blocked           => None,
+            MoveResult::Moved             => Some(HistoryEntry { direction: direction, pushed_box: None }),
+            MoveResult::Pushed(box_index) => Some(HistoryEntry { direction: direction, pushed_box: Some(box_index) }),
+        }
+    }
+
+    // NOTE(erick): Moves the player one tile. A box in the way is pushed if the
+    // tile behind it is free, a wall or a second box blocks the move.
+    fn apply_move(&mut self, direction: Direction) -> MoveResult {
+        let (next_x, next_y) = direction.step(self.player);
+        if !self.map.is_inside(next_x, next_y) {
+            return MoveResult::Blocked;
+        }
+
+        let next = (next_x as u32, next_y as u32);
+        if !self.map.tile_at(next.0, next.1).is_walkable() {
+            return MoveResult::Blocked;
+        }
+
+        match self.map.box_index_at(next) {
+            Some(box_index) => {
+                let (behind_x, behind_y) = direction.step(next);
+                if !self.is_free(behind_x, behind_y) {
+                    return MoveResult::Blocked;
+                }
+
+                self.map.boxes[box_index] = (behind_x as u32, behind_y as u32);
+                self.player = next;
+                MoveResult::Pushed(box_index)
+            },
+            None => {
+                self.player = next;
+                MoveResult::Moved
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xsb::*;
+
+    // NOTE(erick): Every test board is a single row between two walls, so the
+    // player and the boxes are all on y = 1.
+    fn board(row: &str) -> Board {
+        let wall : String = row.chars().map(|_| '#').collect();
+        let lines = vec![wall.clone(), row.to_string(), wall];
+        let level = level_from_xsb("test", &lines).unwrap();
+        Board::new(level.map, level.player_position)
+    }
+
+    #[test]
+    fn steps_into_free_floor() {
+        let mut board = board("#@ $.#");
+
+        assert_eq!(board.try_move(Direction::Right), MoveResult::Moved);
+        assert_eq!(board.player, (2, 1));
+        assert_eq!(board.map.boxes, vec![(3, 1)]);
+    }
+
+    #[test]
+    fn pushes_boxes_into_floor_and_targets() {
+        let mut board = board("#@$ .#");
+
+        assert_eq!(board.try_move(Direction::Right), MoveResult::Pushed(0));
+        assert_eq!(board.player, (2, 1));
+        assert_eq!(board.map.boxes, vec![(3, 1)]);
+        assert!(!board.is_solved());
+
+        assert_eq!(board.try_move(Direction::Right), MoveResult::Pushed(0));
+        assert_eq!(board.map.boxes, vec![(4, 1)]);
+        assert!(board.is_solved());
+        assert_eq!(board.n_pushes(), 2);
+    }
+
+    #[test]
+    fn walls_block_boxes() {
+        let mut board = board("#.@$#");
+
+        assert_eq!(board.try_move(Direction::Right), MoveResult::Blocked);
+        assert_eq!(board.player, (2, 1));
+        assert_eq!(board.map.boxes, vec![(3, 1)]);
+        assert_eq!(board.n_moves(), 0);
+    }
+
+    #[test]
+    fn boxes_block_boxes() {
+        let mut board = board("#@$$..#");
+
+        assert_eq!(board.try_move(Direction::Right), MoveResult::Blocked);
+        assert_eq!(board.player, (1, 1));
+        assert_eq!(board.map.boxes, vec![(2, 1), (3, 1)]);
+        assert_eq!(board.n_moves(), 0);
+    }
+
+    #[test]
+    fn walls_block_the_player() {
+        let mut board = board("#@ $.#");
+
+        assert_eq!(board.try_move(Direction::Left), MoveResult::Blocked);
+        assert_eq!(board.try_move(Direction::Up), MoveResult::Blocked);
+        assert_eq!(board.player, (1, 1));
+        assert_eq!(board.n_moves(), 0);
+    }
+
+    #[test]
+    fn undo_and_redo_a_push() {
+        let mut board = board("#@$ .#");
+        board.try_move(Direction::Right);
+
+        assert_eq!(board.undo(), Some(HistoryEntry { direction: Direction::Right, pushed_box: Some(0) }));
+        assert_eq!(board.player, (1, 1));
+        assert_eq!(board.map.boxes, vec![(2, 1)]);
+        assert_eq!(board.n_pushes(), 0);
+
+        assert_eq!(board.redo(), Some(HistoryEntry { direction: Direction::Right, pushed_box: Some(0) }));
+        assert_eq!(board.player, (2, 1));
+        assert_eq!(board.map.boxes, vec![(3, 1)]);
+        assert_eq!(board.n_pushes(), 1);
+        assert_eq!(board.redo(), None);
+    }
+
+    #[test]
+    fn new_moves_clear_the_redo_stack() {
+        let mut board = board("#  @$ .#");
+        board.try_move(Direction::Right);
+        board.undo();
+
+        assert_eq!(board.try_move(Direction::Left), MoveResult::Moved);
+        assert!(board.redo_stack.is_empty());
+        assert_eq!(board.redo(), None);
+        assert_eq!(board.player, (2, 1));
+    }
+
+    #[test]
+    fn restart_goes_back_to_the_start() {
+        let mut board = board("#@$  .#");
+        for _ in 0..3 {
+            board.try_move(Direction::Right);
+        }
+        board.try_move(Direction::Left);
+
+        board.restart();
+        assert_eq!(board.player, (1, 1));
+        assert_eq!(board.map.boxes, vec![(2, 1)]);
+        assert_eq!(board.n_moves(), 0);
+        assert_eq!(board.n_pushes(), 0);
+        assert_eq!(board.redo_stack.len(), 4);
+    }
+}
@@ -0,0 +1,349 @@
+// NOTE(erick): Procedural level generator.
+//
+// 1. Start from a map full of walls and carve a room with a random walk.
+// 2. Place the targets on the floor, with a box on top of each one.
+// 3. Play backwards: the player walks around pulling boxes away from the
+// targets. Every pull is a push played in reverse, so the level is always
+// solvable.
+// 4. Generate a few candidates and keep the one with the best score (longer
+// solutions that switch between boxes more often are better).
+
+use game::*;
+use level::*;
+use solver::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct GeneratorOptions {
+    pub width        : u32,
+    pub height       : u32,
+    pub n_boxes      : usize,
+
+    pub n_candidates : usize,
+    // NOTE(erick): Number of random steps played backwards for each candidate.
+    pub n_steps      : usize,
+    pub seed         : u64,
+}
+
+impl GeneratorOptions {
+    pub fn new(width: u32, height: u32, n_boxes: usize) -> GeneratorOptions {
+        GeneratorOptions {
+            width        : width,
+            height       : height,
+            n_boxes      : n_boxes,
+
+            n_candidates : 16,
+            n_steps      : 400,
+            seed         : 0x2545F4914F6CDD1D,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct GeneratedLevel {
+    pub level    : Level,
+    pub score    : u32,
+    pub n_pushes : usize,
+    // NOTE(erick): How many times the solution switches from one box to another.
+    pub n_box_changes : usize,
+}
+
+// NOTE(erick): xorshift64*. We don't need anything fancy and this way the
+// same seed always gives the same level.
+struct Random {
+    state : u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random {
+            state : if seed == 0 { 0x9E3779B97F4A7C15 } else { seed },
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn direction(&mut self) -> Direction {
+        Direction::all()[self.below(4)]
+    }
+}
+
+pub fn generate_level(name: &str, options: &GeneratorOptions) -> Option<GeneratedLevel> {
+    if options.width < 5 || options.height < 5 || options.n_boxes == 0 {
+        return None;
+    }
+
+    let mut random = Random::new(options.seed);
+    let mut best : Option<GeneratedLevel> = None;
+
+    for _ in 0..options.n_candidates {
+        let candidate = match generate_candidate(name, options, &mut random) {
+            Some(candidate) => candidate,
+            None            => continue,
+        };
+
+        let is_better = match best {
+            Some(ref best)  => candidate.score > best.score,
+            None            => true,
+        };
+        if is_better {
+            best = Some(candidate);
+        }
+    }
+
+    best
+}
+
+fn carve_room(name: &str, options: &GeneratorOptions, random: &mut Random) -> Map {
+    let mut map = Map::new(name);
+    map.tiles = vec![TileType::Wall; (options.width * options.height) as usize];
+    map.tiles_stride = options.width as i32;
+
+    // NOTE(erick): The border is never carved so the room is always enclosed.
+    let interior = ((options.width - 2) * (options.height - 2)) as usize;
+    let floor_goal = interior / 2 + options.n_boxes * 2;
+
+    let mut position = (options.width / 2, options.height / 2);
+    let mut n_floor = 0;
+    let mut n_steps = 0;
+
+    while n_floor < floor_goal && n_steps < interior * 64 {
+        n_steps += 1;
+
+        if let TileType::Wall = map.tile_at(position.0, position.1) {
+            map.set_tile_at(position.0, position.1, TileType::Floor);
+            n_floor += 1;
+        }
+
+        let (next_x, next_y) = random.direction().step(position);
+        if next_x >= 1 && next_y >= 1 && next_x < options.width as i32 - 1 && next_y < options.height as i32 - 1 {
+            position = (next_x as u32, next_y as u32);
+        }
+    }
+
+    map
+}
+
+// NOTE(erick): Walls that don't touch any floor (diagonals included) are not
+// part of the room.
+fn blank_outer_walls(map: &mut Map) {
+    let mut blank_tiles = Vec::new();
+
+    for y in 0..map.n_lines() {
+        for x in 0..map.n_cols() {
+            let mut touches_floor = false;
+            for dy in -1..2 {
+                for dx in -1..2 {
+                    let (neighbour_x, neighbour_y) = (x as i32 + dx, y as i32 + dy);
+                    if map.is_inside(neighbour_x, neighbour_y) &&
+                        map.tile_at(neighbour_x as u32, neighbour_y as u32).is_walkable() {
+                        touches_floor = true;
+                    }
+                }
+            }
+
+            if !touches_floor {
+                blank_tiles.push((x, y));
+            }
+        }
+    }
+
+    for (x, y) in blank_tiles {
+        map.set_tile_at(x, y, TileType::Blank);
+    }
+}
+
+fn floor_tiles(map: &Map) -> Vec<TilePosition> {
+    let mut result = Vec::new();
+    for y in 0..map.n_lines() {
+        for x in 0..map.n_cols() {
+            if map.tile_at(x, y).is_walkable() {
+                result.push((x, y));
+            }
+        }
+    }
+
+    result
+}
+
+fn generate_candidate(name: &str, options: &GeneratorOptions, random: &mut Random) -> Option<GeneratedLevel> {
+    let mut map = carve_room(name, options, random);
+
+    //
+    // Targets. We prefer tiles with room around them, otherwise most boxes
+    // can't be pulled at all.
+    //
+    let mut floor = floor_tiles(&map);
+    floor.retain(|&position| {
+        Direction::all().iter().filter(|direction| {
+            let (x, y) = direction.step(position);
+            map.is_inside(x, y) && map.tile_at(x as u32, y as u32).is_walkable()
+        }).count() >= 2
+    });
+    if floor.len() < options.n_boxes + 1 {
+        return None;
+    }
+
+    for _ in 0..options.n_boxes {
+        let target = floor.swap_remove(random.below(floor.len()));
+        map.set_tile_at(target.0, target.1, TileType::Target);
+        map.boxes.push(target);
+    }
+
+    let player = floor[random.below(floor.len())];
+    let mut board = Board::new(map, player);
+
+    //
+    // Reverse play
+    //
+    for _ in 0..options.n_steps {
+        let direction = random.direction();
+        let (next_x, next_y) = direction.step(board.player);
+        if !board.is_free(next_x, next_y) {
+            continue;
+        }
+
+        let (behind_x, behind_y) = direction.opposite().step(board.player);
+        let box_behind = if board.map.is_inside(behind_x, behind_y) {
+            board.map.box_index_at((behind_x as u32, behind_y as u32))
+        } else {
+            None
+        };
+
+        if let Some(box_index) = box_behind {
+            if random.chance(70) {
+                board.map.boxes[box_index] = board.player;
+            }
+        }
+        board.player = (next_x as u32, next_y as u32);
+    }
+
+    if board.is_solved() {
+        return None;
+    }
+
+    blank_outer_walls(&mut board.map);
+
+    //
+    // Score
+    //
+    let mut solver_options = SolverOptions::new(Objective::Pushes);
+    solver_options.max_nodes = 20000;
+    solver_options.time_limit = None;
+
+    let solution = match solve(&board, &solver_options) {
+        SolverResult::Solved(solution)  => solution,
+        // NOTE(erick): It is solvable, but too hard to score. Skip it.
+        _                               => { return None; }
+    };
+
+    let n_box_changes = count_box_changes(&board, solution.lurd.as_str());
+    let score = solution.n_pushes as u32 + 2 * n_box_changes as u32;
+
+    Some(GeneratedLevel {
+        level    : Level {
+            map             : board.map,
+            player_position : board.player,
+            textures        : LevelTextures::default_textures(),
+        },
+        score    : score,
+        n_pushes : solution.n_pushes,
+        n_box_changes : n_box_changes,
+    })
+}
+
+fn count_box_changes(board: &Board, lurd: &str) -> usize {
+    let mut board = board.clone();
+    let mut last_box = None;
+    let mut n_changes = 0;
+
+    for c in lurd.chars() {
+        let direction = match Direction::from_lurd(c) {
+            Some((direction, _))    => direction,
+            None                    => break,
+        };
+
+        if let MoveResult::Pushed(box_index) = board.try_move(direction) {
+            if !last_box.is_none() && last_box != Some(box_index) {
+                n_changes += 1;
+            }
+            last_box = Some(box_index);
+        }
+    }
+
+    n_changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_options() -> GeneratorOptions {
+        let mut options = GeneratorOptions::new(9, 8, 3);
+        options.n_candidates = 4;
+        options.n_steps = 200;
+        options.seed = 1234;
+
+        options
+    }
+
+    #[test]
+    fn generates_an_enclosed_solvable_level() {
+        let generated = generate_level("generated", &small_options()).expect("No level was generated");
+        let map = &generated.level.map;
+
+        let n_targets = map.tiles.iter().filter(|tile| **tile == TileType::Target).count();
+        assert_eq!(map.boxes.len(), 3);
+        assert_eq!(n_targets, 3);
+
+        // NOTE(erick): Every walkable tile must be surrounded by walkable
+        // tiles or walls, never by blanks or the edge of the map.
+        for y in 0..map.n_lines() {
+            for x in 0..map.n_cols() {
+                if !map.tile_at(x, y).is_walkable() {
+                    continue;
+                }
+
+                for direction in Direction::all().iter() {
+                    let (next_x, next_y) = direction.step((x, y));
+                    assert!(map.is_inside(next_x, next_y), "({}, {}) is on the edge of the map", x, y);
+                    assert!(map.tile_at(next_x as u32, next_y as u32) != TileType::Blank,
+                            "({}, {}) touches a blank tile", x, y);
+                }
+            }
+        }
+
+        let board = Board::new(generated.level.map.clone(), generated.level.player_position);
+        let mut solver_options = SolverOptions::new(Objective::Pushes);
+        solver_options.time_limit = None;
+
+        match solve(&board, &solver_options) {
+            SolverResult::Solved(solution)  => assert_eq!(solution.n_pushes, generated.n_pushes),
+            _                               => panic!("The generated level could not be solved"),
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_level() {
+        let first = generate_level("generated", &small_options()).unwrap();
+        let second = generate_level("generated", &small_options()).unwrap();
+
+        assert_eq!(first.level.map.tiles, second.level.map.tiles);
+        assert_eq!(first.level.map.boxes, second.level.map.boxes);
+        assert_eq!(first.level.player_position, second.level.player_position);
+    }
+}
@@ -0,0 +1,146 @@
+// NOTE(erick): Hints run the solver in a separate thread so the game loop never
+// waits for it. The frontend polls the request every frame and cancels it
+// if the board changes in the meantime.
+
+use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+
+use game::*;
+use solver::*;
+
+// NOTE(erick): The next push of a solution: the box to push and where to.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct Hint {
+    pub box_position : TilePosition,
+    pub direction    : Direction,
+}
+
+#[derive(Debug)]
+pub enum HintStatus {
+    Pending,
+    Ready(Hint),
+    // NOTE(erick): The solver could not find a solution from the current state
+    // (or gave up).
+    NoHint,
+}
+
+pub struct HintRequest {
+    receiver : Receiver<Option<Hint>>,
+    cancel   : Arc<AtomicBool>,
+}
+
+impl HintRequest {
+    pub fn start(board: &Board, mut options: SolverOptions) -> HintRequest {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        options.cancel = Some(cancel.clone());
+
+        let board = board.clone();
+        thread::spawn(move || {
+            let hint = match solve(&board, &options) {
+                SolverResult::Solved(solution)  => first_push(&board, solution.lurd.as_str()),
+                _                               => None,
+            };
+
+            // NOTE(erick): The request may have been dropped already.
+            let _ = sender.send(hint);
+        });
+
+        HintRequest {
+            receiver : receiver,
+            cancel   : cancel,
+        }
+    }
+
+    pub fn poll(&self) -> HintStatus {
+        match self.receiver.try_recv() {
+            Ok(Some(hint))                  => HintStatus::Ready(hint),
+            Ok(None)                        => HintStatus::NoHint,
+            Err(TryRecvError::Empty)        => HintStatus::Pending,
+            Err(TryRecvError::Disconnected) => HintStatus::NoHint,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for HintRequest {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// NOTE(erick): Walks the LURD string until the first push.
+pub fn first_push(board: &Board, lurd: &str) -> Option<Hint> {
+    let mut player = board.player;
+
+    for c in lurd.chars() {
+        let (direction, is_push) = match Direction::from_lurd(c) {
+            Some(step)  => step,
+            None        => { return None; }
+        };
+
+        let (next_x, next_y) = direction.step(player);
+        if is_push {
+            return Some(Hint {
+                box_position : (next_x as u32, next_y as u32),
+                direction    : direction,
+            });
+        }
+        player = (next_x as u32, next_y as u32);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use xsb::*;
+
+    fn board_from_xsb(lines: &[&str]) -> Board {
+        let lines : Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        let level = level_from_xsb("test", &lines).unwrap();
+        Board::new(level.map, level.player_position)
+    }
+
+    #[test]
+    fn first_push_skips_the_walk() {
+        let board = board_from_xsb(&["######", "#@ $.#", "######"]);
+
+        assert_eq!(first_push(&board, "rR"), Some(Hint { box_position: (3, 1), direction: Direction::Right }));
+        assert_eq!(first_push(&board, "r"), None);
+    }
+
+    #[test]
+    fn hint_request_finds_the_next_push() {
+        let board = board_from_xsb(&["######", "#@ $.#", "######"]);
+        let request = HintRequest::start(&board, SolverOptions::new(Objective::Pushes));
+
+        let mut status = request.poll();
+        for _ in 0..500 {
+            if let HintStatus::Pending = status {
+                thread::sleep(Duration::from_millis(10));
+                status = request.poll();
+            }
+        }
+
+        match status {
+            HintStatus::Ready(hint) => { assert_eq!(hint, Hint { box_position: (3, 1), direction: Direction::Right }); },
+            status                  => panic!("{:?}", status),
+        }
+    }
+}
@@ -0,0 +1,717 @@
+extern crate regex;
+
+use self::regex::Regex;
+
+use std::path::Path;
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::fs::File;
+use std::io::Write;
+use std::io;
+
+use std::fmt;
+
+use game::*;
+use animation::*;
+use xsb::*;
+
+#[derive(Debug)]
+pub enum AssetType {
+    Sound,
+    Sprite,
+    Map,
+    Level,
+}
+
+const DEFAULT_PLAYER_SPRITE_SHEET : &'static str = "player.bmp";
+const DEFAULT_PLAYER_ANIMATION_FPS : u32 = 8;
+
+// NOTE(erick): The names of the images used to draw a level. We only keep
+// their names here, loading them is up to the frontend. Any of them can be an
+// atlas region instead of a whole image (see the atlas module).
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct LevelTextures {
+    pub wall_tile           : String,
+    pub floor_tile          : String,
+    pub target_tile         : String,
+
+    pub box_sprite_sheet    : String,
+    pub box_sprite_width    : u32,
+    pub box_sprite_height   : u32,
+    // NOTE(erick): Boxes on targets look like the other boxes without it.
+    pub box_on_target_sprite : Option<String>,
+
+    // NOTE(erick): Without a size the whole image is a single frame. The lanes
+    // are the rows of the sheet, see the animation module. An .anim file in
+    // player_animation replaces all of them.
+    pub player_sprite_sheet  : String,
+    pub player_sprite_size   : Option<(u32, u32)>,
+    pub player_animation_fps : u32,
+    pub player_lanes         : Vec<LaneDefinition>,
+    pub player_animation     : Option<String>,
+
+    // NOTE(erick): An .anim file with the clips played on top of the map, like
+    // box_on_target when a box lands on a target.
+    pub effects_animation    : Option<String>,
+}
+
+impl LevelTextures {
+    // NOTE(erick): What every level shipped with the game uses. Handy for levels
+    // coming from formats that don't know about textures.
+    pub fn default_textures() -> LevelTextures {
+        LevelTextures {
+            wall_tile           : String::from("wall.bmp"),
+            floor_tile          : String::from("floor.bmp"),
+            target_tile         : String::from("target.bmp"),
+
+            box_sprite_sheet    : String::from("box.bmp"),
+            box_sprite_width    : 28,
+            box_sprite_height   : 28,
+            box_on_target_sprite : None,
+
+            player_sprite_sheet  : String::from(DEFAULT_PLAYER_SPRITE_SHEET),
+            player_sprite_size   : None,
+            player_animation_fps : DEFAULT_PLAYER_ANIMATION_FPS,
+            player_lanes         : Vec::new(),
+            player_animation     : None,
+
+            effects_animation    : None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Level {
+    pub map             : Map,
+    pub player_position : TilePosition,
+    pub textures        : LevelTextures,
+}
+
+impl Level {
+    // NOTE(erick): Identifies a level by what is played (tiles, boxes and
+    // player start), so renaming it or moving it to another pack doesn't matter.
+    // We hash its XSB text, which leaves out the blank space around the level,
+    // so a round trip through XSB keeps the same identity too.
+    // FNV-1a, because it has to be stable between runs and builds.
+    pub fn identity_hash(&self) -> u64 {
+        let mut hash : u64 = 0xcbf29ce484222325;
+        for byte in xsb_string(&self.map, self.player_position).bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        hash
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(String, io::Error),
+    Write(String, io::Error),
+    MissingEqualsSign { line: u32 },
+    UnknownKey { line: u32, key: String },
+    MissingKey(&'static str),
+    BadNumber { line: u32, value: String },
+    BadTuple { line: u32, value: String },
+    BadLane { line: u32, value: String },
+    // NOTE(erick): The line numbers of these two refer to the .map file.
+    RaggedRow { line: u32, expected: u32, found: u32 },
+    UnknownTileCode { line: u32, code: String },
+    UnknownCharacter { line: u32, character: char },
+    EmptyMap,
+    NoPlayer,
+    MoreThanOnePlayer { line: u32 },
+    BoxOutOfBounds(TilePosition),
+    BoxOnWall(TilePosition, TileType),
+    PlayerOutOfBounds(TilePosition),
+    PlayerOnWall(TilePosition, TileType),
+    BoxTargetMismatch { boxes: usize, targets: usize },
+    // NOTE(erick): A validation error blamed on the .lvl line that caused it.
+    AtLine { line: u32, error: Box<LevelError> },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &LevelError::Io(ref path, ref error)                => write!(f, "Could not read {}: {}", path, error),
+            &LevelError::Write(ref path, ref error)             => write!(f, "Could not write {}: {}", path, error),
+            &LevelError::MissingEqualsSign { line }             => write!(f, "Line {}: Could not find '=' sign", line),
+            &LevelError::UnknownKey { line, ref key }           => write!(f, "Line {}: Unknown variable: {}", line, key),
+            &LevelError::MissingKey(key)                        => write!(f, "{} must be specified", key),
+            &LevelError::BadNumber { line, ref value }          => write!(f, "Line {}: Could not parse {} as a number", line, value),
+            &LevelError::BadTuple { line, ref value }           => write!(f, "Line {}: Could not parse {} as position tuples", line, value),
+            &LevelError::BadLane { line, ref value }            => write!(f, "Line {}: Could not parse {} as <state>_<direction>, <number of frames>", line, value),
+            &LevelError::RaggedRow { line, expected, found }    => write!(f, "Map line {}: Expected {} tiles, found {}", line, expected, found),
+            &LevelError::UnknownTileCode { line, ref code }     => write!(f, "Map line {}: Unknown tile code {}", line, code),
+            &LevelError::UnknownCharacter { line, character }   => write!(f, "Line {}: Unknown character {:?}", line, character),
+            &LevelError::EmptyMap                               => write!(f, "The map is empty"),
+            &LevelError::NoPlayer                               => write!(f, "No player initial position"),
+            &LevelError::MoreThanOnePlayer { line }             => write!(f, "Line {}: More than one player", line),
+            &LevelError::BoxOutOfBounds(position)               => write!(f, "Box at {:?} is outside the map", position),
+            &LevelError::BoxOnWall(position, tile)              => write!(f, "Box at {:?} is on a {:?} tile", position, tile),
+            &LevelError::PlayerOutOfBounds(position)            => write!(f, "Player at {:?} is outside the map", position),
+            &LevelError::PlayerOnWall(position, tile)           => write!(f, "Player at {:?} is on a {:?} tile", position, tile),
+            &LevelError::BoxTargetMismatch { boxes, targets }   => write!(f, "There are {} boxes and {} targets", boxes, targets),
+            &LevelError::AtLine { line, ref error }             => write!(f, "Line {}: {}", line, error),
+        }
+    }
+}
+
+pub fn asset_path_string(asset_type: AssetType, asset_name: &str) -> String {
+    let mut result = String::new();
+
+    match asset_type {
+        AssetType::Sound    => { result.push_str("assets/") },
+        AssetType::Sprite   => { result.push_str("assets/") },
+        AssetType::Map      => { result.push_str("assets/maps/") },
+        AssetType::Level    => { result.push_str("assets/maps/") },
+    }
+    result.push_str(asset_name);
+
+    result
+}
+
+pub fn remove_asset_path(asset_type: AssetType, asset_path: &str) -> &str {
+    let offset : usize;
+
+    match asset_type {
+        AssetType::Sound    => { offset = "assets/".len() },
+        AssetType::Sprite   => { offset = "assets/".len() },
+        AssetType::Map      => { offset = "assets/maps/".len() },
+        AssetType::Level    => { offset = "assets/maps/".len() },
+    };
+
+    &asset_path[offset..]
+}
+
+pub fn fill_tiles_and_stride(map: &mut Map, map_file: &Path) -> Result<(), Vec<LevelError>> {
+    let input_file = match File::open(map_file) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![LevelError::Io(format!("{:?}", map_file), error)]); }
+    };
+
+    let file_data = BufReader::new(&input_file);
+    let mut errors = Vec::new();
+
+    let mut n_lines: u32 = 0;
+    for line in file_data.lines() {
+        n_lines += 1;
+
+        let line = match line {
+            Ok(line)    => line,
+            Err(error)  => {
+                errors.push(LevelError::Io(format!("{:?}", map_file), error));
+                break;
+            }
+        };
+        let tiles_code = line.split_whitespace();
+
+        let mut n_tiles: u32 = 0;
+        for code in tiles_code {
+            n_tiles += 1;
+            let tile_type = code.parse::<u32>().ok().and_then(TileType::from_code);
+            match tile_type {
+                Some(tile_type) => map.tiles.push(tile_type),
+                None            => {
+                    errors.push(LevelError::UnknownTileCode { line: n_lines, code: code.to_string() });
+                    // NOTE(erick): Keep the row aligned so we can keep checking the others.
+                    map.tiles.push(TileType::Blank);
+                },
+            }
+        }
+
+        if map.tiles_stride < 0 {
+            map.tiles_stride = n_tiles as i32;
+        } else {
+            if map.tiles_stride != n_tiles as i32 {
+                errors.push(LevelError::RaggedRow { line: n_lines, expected: map.tiles_stride as u32, found: n_tiles });
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// NOTE(erick): Checks the rules every playable level must follow, no matter
+// which file format it came from.
+pub fn validate_level(map: &Map, player_position: TilePosition) -> Vec<LevelError> {
+    let mut errors = Vec::new();
+
+    if map.tiles.is_empty() {
+        errors.push(LevelError::EmptyMap);
+        return errors;
+    }
+
+    let (player_x, player_y) = player_position;
+    if !map.is_inside(player_x as i32, player_y as i32) {
+        errors.push(LevelError::PlayerOutOfBounds(player_position));
+    } else {
+        let tile = map.tile_at(player_x, player_y);
+        if !tile.is_walkable() {
+            errors.push(LevelError::PlayerOnWall(player_position, tile));
+        }
+    }
+
+    for &(box_x, box_y) in &map.boxes {
+        if !map.is_inside(box_x as i32, box_y as i32) {
+            errors.push(LevelError::BoxOutOfBounds((box_x, box_y)));
+        } else {
+            let tile = map.tile_at(box_x, box_y);
+            if !tile.is_walkable() {
+                errors.push(LevelError::BoxOnWall((box_x, box_y), tile));
+            }
+        }
+    }
+
+    let n_targets = map.tiles.iter().filter(|tile| **tile == TileType::Target).count();
+    if n_targets != map.boxes.len() {
+        errors.push(LevelError::BoxTargetMismatch { boxes: map.boxes.len(), targets: n_targets });
+    }
+
+    errors
+}
+
+pub fn write_level_file(level_file_name: &str, map: &Map, textures: &LevelTextures, player_position: (u32, u32)) -> io::Result<()> {
+    //
+    // Level file
+    //
+    let mut level_file_with_extension = String::from(level_file_name);
+    level_file_with_extension.push_str(".lvl");
+
+    let level_file_path = asset_path_string(AssetType::Level, level_file_with_extension.as_str());
+    let level_output_path = Path::new(level_file_path.as_str());
+
+    //
+    // Map file
+    //
+    let mut map_file_with_extension = String::from(level_file_name);
+    map_file_with_extension.push_str(".map");
+
+    let map_file_path = asset_path_string(AssetType::Map, map_file_with_extension.as_str());
+    let map_output_path = Path::new(map_file_path.as_str());
+
+
+    if map.boxes.len() == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Map must have at least one box"));
+    }
+
+    let mut output_file = File::create(level_output_path)?;
+
+    output_file.write_all(format!("level_name = {}\n", map.name).as_bytes())?;
+
+    if !map.level_music.is_none() {
+        output_file.write_all(format!("level_music = {}\n",
+            remove_asset_path(AssetType::Sound, map.level_music.as_ref().unwrap())).as_bytes())?;
+    }
+
+    if let Some(ref sound_effects) = map.sound_effects {
+        output_file.write_all(format!("sound_effects = {}\n",
+            remove_asset_path(AssetType::Sound, sound_effects)).as_bytes())?;
+    }
+
+    if !map.next_level.is_none() {
+        output_file.write_all(format!("next_level = {}\n\n",
+            remove_asset_path(AssetType::Level, map.next_level.as_ref().unwrap().as_str())).as_bytes())?;
+    }
+
+    output_file.write_all(format!("wall_tile = {}\n", textures.wall_tile).as_bytes())?;
+    output_file.write_all(format!("floor_tile = {}\n", textures.floor_tile).as_bytes())?;
+    output_file.write_all(format!("target_tile = {}\n\n", textures.target_tile).as_bytes())?;
+
+    output_file.write_all(format!("player_position = ({}, {})\n\n", player_position.0, player_position.1).as_bytes())?;
+
+    output_file.write_all(format!("box_sprite_sheet = {}\n", textures.box_sprite_sheet).as_bytes())?;
+    output_file.write_all(format!("box_sprite_width = {}\n", textures.box_sprite_width).as_bytes())?;
+    output_file.write_all(format!("box_sprite_height = {}\n", textures.box_sprite_height).as_bytes())?;
+    if let Some(ref box_on_target_sprite) = textures.box_on_target_sprite {
+        output_file.write_all(format!("box_on_target_sprite = {}\n", box_on_target_sprite).as_bytes())?;
+    }
+
+    output_file.write_all(format!("player_sprite_sheet = {}\n", textures.player_sprite_sheet).as_bytes())?;
+    if let Some((width, height)) = textures.player_sprite_size {
+        output_file.write_all(format!("player_sprite_size = ({}, {})\n", width, height).as_bytes())?;
+    }
+    if !textures.player_lanes.is_empty() {
+        output_file.write_all(format!("player_animation_fps = {}\n", textures.player_animation_fps).as_bytes())?;
+    }
+    for lane in &textures.player_lanes {
+        output_file.write_all(format!("player_lane = {}, {}\n", lane.name, lane.number_of_frames).as_bytes())?;
+    }
+    if let Some(ref player_animation) = textures.player_animation {
+        output_file.write_all(format!("player_animation = {}\n", player_animation).as_bytes())?;
+    }
+    if let Some(ref effects_animation) = textures.effects_animation {
+        output_file.write_all(format!("effects_animation = {}\n", effects_animation).as_bytes())?;
+    }
+
+    output_file.write_all("box_positions = {".as_bytes())?;
+
+    let mut first = true;
+    for _box in &map.boxes {
+        if !first {
+            output_file.write_all(", ".as_bytes())?;
+        }
+        output_file.write_all(format!("({}, {})", _box.0, _box.1).as_bytes())?;
+        first = false;
+    }
+
+    output_file.write_all("}\n\n".as_bytes())?;
+
+    output_file.write_all(format!("tile_map = {}", map_file_with_extension).as_bytes())?;
+    write_map_file(map_output_path, map)
+}
+
+pub fn write_map_file(map_path: &Path, map: &Map) -> io::Result<()> {
+    let mut map_file = File::create(map_path)?;
+
+    let mut current_col = 0;
+    for tile_type in &map.tiles {
+        if current_col == map.tiles_stride {
+            current_col = 0;
+            map_file.write_all("\n".as_bytes())?;
+        }
+
+        map_file.write_all(format!("{} ", tile_type.to_code()).as_bytes())?;
+
+        current_col += 1;
+    }
+
+    Ok(())
+}
+
+// NOTE(erick): Reports every problem found in the level instead of stopping
+// at the first one.
+pub fn parse_level(level_name: &str) -> Result<Level, Vec<LevelError>> {
+    let mut filename = String::from(level_name);
+    filename.push_str(".lvl");
+
+    let level_full_path_string = asset_path_string(AssetType::Level, filename.as_str());
+    let level_file_path = Path::new(level_full_path_string.as_str());
+
+
+    let mut _level_name         = None;
+    let mut _level_music        = None;
+    let mut _sound_effects      = None;
+    let mut _next_level         = None;
+    let mut _wall_tile          = None;
+    let mut _floor_tile         = None;
+    let mut _target_tile        = None;
+    let mut _tile_map           = None;
+    let mut _player_position    = None;
+    let mut _box_sprite_sheet   = None;
+    let mut _box_sprite_width   = None;
+    let mut _box_sprite_height  = None;
+    let mut _box_on_target_sprite = None;
+    let mut _player_sprite_sheet  = None;
+    let mut _player_sprite_size   = None;
+    let mut _player_animation_fps = None;
+    let mut _player_lanes         = Vec::new();
+    let mut _player_animation     = None;
+    let mut _effects_animation    = None;
+    let mut _box_positions      = None;
+
+    let mut player_position_line = 0;
+    let mut box_positions_line   = 0;
+
+    let level_file = match File::open(level_file_path) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![LevelError::Io(level_full_path_string.clone(), error)]); }
+    };
+
+    let level_data = BufReader::new(&level_file);
+    let mut errors = Vec::new();
+
+    let mut line_number = 0;
+    for line in level_data.lines() {
+        line_number += 1;
+
+        let line = match line {
+            Ok(line)    => line,
+            Err(error)  => {
+                errors.push(LevelError::Io(level_full_path_string.clone(), error));
+                break;
+            }
+        };
+        if line == "" {
+            continue;
+        }
+        if line.starts_with("//") {
+            continue;
+        }
+
+        let attrib_index = line.find('=');
+        if attrib_index.is_none() {
+            errors.push(LevelError::MissingEqualsSign { line: line_number });
+            continue;
+        }
+
+        let _split = line.split_at(attrib_index.unwrap());
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        let bad_tuple  = LevelError::BadTuple  { line: line_number, value: rhs.to_string() };
+        let bad_number = LevelError::BadNumber { line: line_number, value: rhs.to_string() };
+        let bad_lane   = LevelError::BadLane   { line: line_number, value: rhs.to_string() };
+
+        match lhs {
+            "level_name"          => {_level_name           = Some(rhs.to_string())},
+            "level_music"         => {_level_music          = Some(rhs.to_string())},
+            "sound_effects"       => {_sound_effects        = Some(rhs.to_string())},
+            "next_level"          => {_next_level           = Some(rhs.to_string())},
+            "tile_map"            => {_tile_map             = Some(rhs.to_string())},
+            "wall_tile"           => {_wall_tile            = Some(rhs.to_string())},
+            "floor_tile"          => {_floor_tile           = Some(rhs.to_string())},
+            "target_tile"         => {_target_tile          = Some(rhs.to_string())},
+            "box_sprite_sheet"    => {_box_sprite_sheet     = Some(rhs.to_string())},
+            "box_on_target_sprite" => {_box_on_target_sprite = Some(rhs.to_string())},
+            "player_position"     => {
+                _player_position = parse_or_push_error(parse_position_tuple(rhs), bad_tuple, &mut errors);
+                player_position_line = line_number;
+            },
+            "box_positions"       => {
+                _box_positions = parse_or_push_error(parse_position_tuple_vec(rhs), bad_tuple, &mut errors);
+                box_positions_line = line_number;
+            },
+            "box_sprite_width"    => {_box_sprite_width     = parse_or_push_error(rhs.parse::<u32>().ok(), bad_number, &mut errors)},
+            "box_sprite_height"   => {_box_sprite_height    = parse_or_push_error(rhs.parse::<u32>().ok(), bad_number, &mut errors)},
+            "player_sprite_sheet" => {_player_sprite_sheet  = Some(rhs.to_string())},
+            "player_sprite_size"  => {_player_sprite_size   = parse_or_push_error(parse_position_tuple(rhs), bad_tuple, &mut errors)},
+            "player_animation_fps" => {_player_animation_fps = parse_or_push_error(rhs.parse::<u32>().ok(), bad_number, &mut errors)},
+            "player_animation"    => {_player_animation     = Some(rhs.to_string())},
+            "effects_animation"   => {_effects_animation    = Some(rhs.to_string())},
+            "player_lane"         => {
+                if let Some(lane) = parse_or_push_error(parse_lane_definition(rhs), bad_lane, &mut errors) {
+                    _player_lanes.push(lane);
+                }
+            },
+            _                     => {errors.push(LevelError::UnknownKey { line: line_number, key: lhs.to_string() })}
+        }
+    }
+
+    //
+    // We got all data from the file. Now we need to check if we got all the information that we need.
+    //
+    if _level_name.is_none()        { errors.push(LevelError::MissingKey("level_name")); }
+
+    if _wall_tile.is_none()         { errors.push(LevelError::MissingKey("wall_tile")); }
+    if _floor_tile.is_none()        { errors.push(LevelError::MissingKey("floor_tile")); }
+    if _target_tile.is_none()       { errors.push(LevelError::MissingKey("target_tile")); }
+    if _box_sprite_sheet.is_none()  { errors.push(LevelError::MissingKey("box_sprite_sheet")); }
+    if _box_sprite_width.is_none()  { errors.push(LevelError::MissingKey("box_sprite_width")); }
+    if _box_sprite_height.is_none() { errors.push(LevelError::MissingKey("box_sprite_height")); }
+
+    if _tile_map.is_none()          { errors.push(LevelError::MissingKey("tile_map")); }
+    if _player_position.is_none()   { errors.push(LevelError::MissingKey("player_position")); }
+    if _box_positions.is_none()     { errors.push(LevelError::MissingKey("box_positions")); }
+
+    //
+    // Now we create the map
+    //
+    let mut result_map = Map::new(_level_name.as_ref().map_or("", |name| name.as_str()));
+
+    result_map.level_music = match _level_music {
+        Some(path) => Some(asset_path_string(AssetType::Sound, path.as_str())),
+        None => None,
+    };
+    result_map.sound_effects = _sound_effects.map(|path| asset_path_string(AssetType::Sound, path.as_str()));
+    result_map.next_level = match _next_level {
+        Some(path) => Some(asset_path_string(AssetType::Level, path.as_str())),
+        None => None,
+    };
+
+    let mut map_was_read = false;
+    if let Some(tile_map) = _tile_map {
+        let map_path = asset_path_string(AssetType::Map, tile_map.as_str());
+        match fill_tiles_and_stride(&mut result_map, Path::new(map_path.as_str())) {
+            Ok(())          => { map_was_read = true; },
+            Err(map_errors) => {
+                map_was_read = !map_errors.iter().any(|error| match error {
+                    &LevelError::Io(_, _) => true,
+                    _                     => false,
+                });
+                errors.extend(map_errors);
+            },
+        }
+    }
+
+    if let Some(box_positions) = _box_positions {
+        result_map.boxes = box_positions;
+    }
+
+    // NOTE(erick): Validating a map that could not be read would only report
+    // spurious errors. A map with bad rows is still validated so the user
+    // gets every problem in one go.
+    if let (true, Some(player_position)) = (map_was_read, _player_position) {
+        for error in validate_level(&result_map, player_position) {
+            let line = match error {
+                LevelError::PlayerOutOfBounds(_) | LevelError::PlayerOnWall(_, _) => player_position_line,
+                LevelError::BoxOutOfBounds(_)    | LevelError::BoxOnWall(_, _)    => box_positions_line,
+                _                                                                 => 0,
+            };
+
+            if line == 0 {
+                errors.push(error);
+            } else {
+                errors.push(LevelError::AtLine { line: line, error: Box::new(error) });
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let textures = LevelTextures {
+        wall_tile           : _wall_tile.unwrap(),
+        floor_tile          : _floor_tile.unwrap(),
+        target_tile         : _target_tile.unwrap(),
+
+        box_sprite_sheet    : _box_sprite_sheet.unwrap(),
+        box_sprite_width    : _box_sprite_width.unwrap(),
+        box_sprite_height   : _box_sprite_height.unwrap(),
+        box_on_target_sprite : _box_on_target_sprite,
+
+        player_sprite_sheet  : _player_sprite_sheet.unwrap_or(String::from(DEFAULT_PLAYER_SPRITE_SHEET)),
+        player_sprite_size   : _player_sprite_size,
+        player_animation_fps : _player_animation_fps.unwrap_or(DEFAULT_PLAYER_ANIMATION_FPS),
+        player_lanes         : _player_lanes,
+        player_animation     : _player_animation,
+
+        effects_animation    : _effects_animation,
+    };
+
+    Ok(Level {
+        map             : result_map,
+        player_position : _player_position.unwrap(),
+        textures        : textures,
+    })
+}
+
+fn parse_or_push_error<T>(value: Option<T>, error: LevelError, errors: &mut Vec<LevelError>) -> Option<T> {
+    if value.is_none() {
+        errors.push(error);
+    }
+
+    value
+}
+
+fn tuple_from_strings<T> (v_0_str: &str, v_1_str: &str) -> Option< (T, T) > where T: ::std::str::FromStr {
+    let v_0 = v_0_str.parse::<T>();
+    let v_1 = v_1_str.parse::<T>();
+
+    match (v_0, v_1) {
+        (Ok(v_0), Ok(v_1))  => Some((v_0, v_1)),
+        _                   => None,
+    }
+}
+
+fn parse_position_tuple(s: &str) -> Option<(u32, u32)> {
+    // NOTE(erick): This regex is almost identical to the one the the parse_position_tuple_vec function. Any modification here should
+    // be reflected there.
+    // NOTE(erick): Matches:
+    // '(' <any number of white spaces> <an integer number> ',' <any number of white spaces> <an integer number> <any number of white spaces> ')'
+    let tuple_re = Regex::new(r"\(\s*(-?[0-9]+),\s*(-?[0-9]+)\s*\)").unwrap();
+
+    let captures = match tuple_re.captures(s) {
+        Some(cap)   => cap,
+        None        => { return None; }
+    };
+
+    let v_0_str = captures.get(1).unwrap().as_str();
+    let v_1_str = captures.get(2).unwrap().as_str();
+
+    let result = tuple_from_strings::<u32>(v_0_str, v_1_str);
+
+    result
+}
+
+fn parse_position_tuple_vec(s: &str) -> Option<Vec< (u32, u32) > > {
+    let mut result = Vec::new();
+    // TODO(erick): Some unit tests for this regexes would be nice!
+    let tuple_vec_re = Regex::new(
+        r"\{\s*((?:\(\s*(?:-?[0-9]+),\s*(?:-?[0-9]+)\s*\)\s*,\s*)*(?:\(\s*(?:-?[0-9]+),\s*(?:-?[0-9]+)\s*\)))\s*\}")
+        .unwrap();
+
+    // NOTE(erick): This regex is almost identical to the one the the parse_position_tuple function. Any modification here should
+    // be reflected there.
+    let tuple_and_rest_re = Regex::new(r"\s*\(\s*(-?[0-9]+),\s*(-?[0-9]+)\s*\)\s*(?:,\s*(.*))?").unwrap();
+
+    let vec_captures = match tuple_vec_re.captures(s) {
+        Some(cap)   => cap,
+        None        => { return None; }
+    };
+
+    let mut vec_str =  vec_captures.get(1).unwrap().as_str();
+
+    // NOTE(erick): It would be interesting to write an iterator for this loop.
+    loop {
+        let tuple_capture = tuple_and_rest_re.captures(vec_str).unwrap();
+        let tuple_v0_str = tuple_capture.get(1).unwrap().as_str();
+        let tuple_v1_str = tuple_capture.get(2).unwrap().as_str();
+
+        let rest = tuple_capture.get(3);
+
+        match tuple_from_strings::<u32>(tuple_v0_str, tuple_v1_str) {
+            Some(tuple) => result.push(tuple),
+            None        => { return None; }
+        }
+
+        if rest.is_none() {
+            break;
+        } else {
+            vec_str = rest.unwrap().as_str();
+        }
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn every_problem_is_reported_with_its_line() {
+        let name = format!("test-broken-{}", process::id());
+        let level_path = asset_path_string(AssetType::Level, format!("{}.lvl", name).as_str());
+        let map_path = asset_path_string(AssetType::Map, format!("{}.map", name).as_str());
+
+        fs::write(&map_path, "1 1 1 1\n1 0 9 1\n1 0 4\n1 1 1 1\n").unwrap();
+        fs::write(&level_path, format!("level_name = Broken\n\
+                                        wall_tile = wall.bmp\n\
+                                        floor_tile = floor.bmp\n\
+                                        target_tile = target.bmp\n\
+                                        tile_map = {}.map\n\
+                                        player_position = (10, 10)\n\
+                                        box_sprite_sheet = box.bmp\n\
+                                        box_sprite_width = 28\n\
+                                        box_sprite_height = 28\n\
+                                        box_positions = {{(0, 0)}}\n", name)).unwrap();
+
+        let result = parse_level(name.as_str());
+        fs::remove_file(&map_path).unwrap();
+        fs::remove_file(&level_path).unwrap();
+
+        let errors = match result {
+            Ok(_)       => panic!("A broken level was accepted"),
+            Err(errors) => errors,
+        };
+        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+
+        assert_eq!(messages, vec![
+            "Map line 2: Unknown tile code 9",
+            "Map line 3: Expected 4 tiles, found 3",
+            "Line 6: Player at (10, 10) is outside the map",
+            "Line 10: Box at (0, 0) is on a Wall tile",
+        ]);
+    }
+}
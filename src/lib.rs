@@ -1,2 +1,20 @@
 pub mod math;
 pub mod sdl_misc;
+pub mod game;
+pub mod level;
+pub mod xsb;
+pub mod pack;
+pub mod solver;
+pub mod deadlock;
+pub mod hint;
+pub mod generator;
+pub mod editor;
+pub mod progress;
+pub mod replay;
+pub mod scene;
+pub mod camera;
+pub mod atlas;
+pub mod theme;
+pub mod animation;
+pub mod sound;
+pub mod audio_settings;
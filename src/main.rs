@@ -1,35 +1,50 @@
 extern crate sdl2;
 extern crate gl;
-extern crate regex;
 
 use sdl2::render::Texture;
 use sdl2::render::Renderer;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{LCTRLMOD, RCTRLMOD};
+use sdl2::mouse::MouseButton;
 use sdl2::rect::Rect;
 
 use sdl2::ttf::Font;
 use sdl2::pixels::Color;
 use sdl2::render::TextureQuery;
+use sdl2::render::BlendMode;
 
 use sdl2::controller::Axis::*;
+use sdl2::controller::Button;
+use sdl2::video::FullscreenType;
+use sdl2::image::{INIT_PNG, INIT_JPG};
+
 
 use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Rc;
-
-use std::io::BufRead;
-use std::io::BufReader;
-use std::fs::File;
-use std::io::Write;
-
-use regex::Regex;
-
 use std::collections::hash_map::HashMap;
 
 extern crate sokoban;
 use sokoban::math::*;
 use sokoban::sdl_misc::*;
+use sokoban::game::*;
+use sokoban::level::*;
+use sokoban::pack::*;
+use sokoban::deadlock::*;
+use sokoban::solver::*;
+use sokoban::hint::*;
+use sokoban::editor::*;
+use sokoban::progress::*;
+use sokoban::replay::*;
+use sokoban::scene::*;
+use sokoban::camera::*;
+use sokoban::atlas::*;
+use sokoban::theme::*;
+use sokoban::animation::*;
+use sokoban::sound::*;
+use sokoban::audio_settings::*;
 
 #[allow(dead_code)]
 fn allowed_motion_before_collision(moving: &Rect2, direction: Vector2, obstacle: &Rect2) -> f32 {
@@ -42,13 +57,35 @@ fn allowed_motion_before_collision(moving: &Rect2, direction: Vector2, obstacle:
 }
 
 const GAME_NAME : &'static str = "Sokoban";
+const BUILT_IN_PACK : &'static str = "assets/packs/sokoban.pack";
 const WINDOW_WIDTH  : u32 = 800;
 const WINDOW_HEIGHT : u32 = 592;
 
+// NOTE(erick): How many tiles fit on the screen.
+const CAMERA_WIDTH  : f32 = 20.0;
+const CAMERA_HEIGHT : f32 = 16.0;
+// NOTE(erick): In tiles per second.
+const ENTITY_MOVE_SPEED : f32 = 8.0;
+
+#[derive(Debug)]
+struct GameOptions {
+    show_frame_time: bool,
+    show_deadlock_warnings: bool,
+    fullscreen: bool,
+    // NOTE(erick): Scale the tiles by whole numbers only.
+    integer_scale: bool,
+    // NOTE(erick): An index into the loaded themes. None draws the tiles of
+    // the level.
+    theme_index: Option<usize>,
+}
+
 #[derive(Debug)]
 struct GameState {
     is_running: bool,
     old_ticks: u32,
+    // NOTE(erick): Never empty. The last scene is the one on top.
+    scenes: Vec<Scene>,
+    options: GameOptions,
 }
 
 impl GameState {
@@ -56,8 +93,48 @@ impl GameState {
         GameState {
             is_running: true,
             old_ticks: 0,
+            scenes: vec![Scene::Title(Menu::new())],
+            options: GameOptions {
+                show_frame_time: true,
+                show_deadlock_warnings: true,
+                fullscreen: false,
+                integer_scale: false,
+                theme_index: None,
+            },
         }
     }
+
+    fn scene(&self) -> Scene {
+        *self.scenes.last().unwrap()
+    }
+
+    // NOTE(erick): Replaces the scene on top.
+    fn set_scene(&mut self, scene: Scene) {
+        *self.scenes.last_mut().unwrap() = scene;
+    }
+
+    fn scene_mut(&mut self) -> &mut Scene {
+        self.scenes.last_mut().unwrap()
+    }
+
+    fn push_scene(&mut self, scene: Scene) {
+        self.scenes.push(scene);
+    }
+
+    fn pop_scene(&mut self) {
+        if self.scenes.len() > 1 {
+            self.scenes.pop();
+        }
+    }
+
+    fn reset_scenes(&mut self, scene: Scene) {
+        self.scenes.clear();
+        self.scenes.push(scene);
+    }
+
+    fn shows_board(&self) -> bool {
+        self.scenes.iter().any(|scene| scene.shows_board())
+    }
 }
 
 #[derive(Debug)]
@@ -68,8 +145,26 @@ struct GameInputState {
     right_x_axis: f32,
     right_y_axis: f32,
 
+    // NOTE(erick): action_a is undo, action_b is redo.
     action_a: bool,
     action_b: bool,
+    restart: bool,
+    hint: bool,
+
+    toggle_editor: bool,
+    toggle_test_play: bool,
+    toggle_fullscreen: bool,
+    toggle_mute: bool,
+    next_theme: bool,
+    save_replay: bool,
+    // NOTE(erick): Menu input. cancel is Escape or Start, it pauses the game
+    // and closes menus.
+    confirm: bool,
+    cancel: bool,
+    menu_up: bool,
+    menu_down: bool,
+    menu_left: bool,
+    menu_right: bool,
 }
 
 impl GameInputState {
@@ -83,50 +178,69 @@ impl GameInputState {
 
             action_a: false,
             action_b: false,
+            restart: false,
+            hint: false,
+
+            toggle_editor: false,
+            toggle_test_play: false,
+            toggle_fullscreen: false,
+            toggle_mute: false,
+            next_theme: false,
+            save_replay: false,
+            confirm: false,
+            cancel: false,
+            menu_up: false,
+            menu_down: false,
+            menu_left: false,
+            menu_right: false,
         }
     }
 
+    fn clear_actions(&mut self) {
+        self.action_a = false;
+        self.action_b = false;
+        self.restart = false;
+        self.hint = false;
+
+        self.toggle_editor = false;
+        self.toggle_test_play = false;
+        self.toggle_fullscreen = false;
+        self.toggle_mute = false;
+        self.next_theme = false;
+        self.save_replay = false;
+        self.confirm = false;
+        self.cancel = false;
+        self.menu_up = false;
+        self.menu_down = false;
+        self.menu_left = false;
+        self.menu_right = false;
+    }
+
+    // NOTE(erick): Menus go down as rows go, so dy is positive going down.
+    fn menu_delta(&self) -> (i32, i32) {
+        let dx = self.menu_right as i32 - self.menu_left as i32;
+        let dy = self.menu_down as i32 - self.menu_up as i32;
+        (dx, dy)
+    }
+
     fn no_left_axis_input(&self) -> bool {
         self.left_x_axis == 0.0f32 && self.left_y_axis == 0.0f32
     }
 }
 
-#[derive(Debug)]
-#[derive(Clone)]
-struct AnimationLane {
-    number_of_frames        : u32,
-}
-
-#[derive(Debug)]
+// NOTE(erick): The clips of a sprite sheet and which one is playing (see the
+// animation module). Sheets without clips always show the same sprite.
 #[derive(Clone)]
 struct AnimationInfo {
-    is_animating    : bool,
-
-    acc_dt          : f32,
-    fps             : u32,
-    frame_time      : f32,
-
-    current_frame   : u32,
-    animation_lanes : Vec<AnimationLane>,
-    // TODO(erick): We should either find out a way to disable bound checks or
-    // see if we can use a reference here.
-    current_animation_lane_index : isize,
+    animation_set : Option<Rc<AnimationSet>>,
+    player        : AnimationPlayer,
 }
 
 impl AnimationInfo {
-    fn new(animating: bool, _fps: u32) -> AnimationInfo {
-        let _frame_time = if _fps > 0 {1.0 / _fps as f32} else {0.0};
-
+    fn new(animation_set: Option<Rc<AnimationSet>>) -> AnimationInfo {
         AnimationInfo {
-            is_animating    : animating,
-
-            acc_dt          : 0.0,
-            fps             : _fps,
-            frame_time      : _frame_time,
-
-            current_frame   : 0,
-            animation_lanes : Vec::new(),
-            current_animation_lane_index : -1,
+            animation_set : animation_set,
+            player        : AnimationPlayer::new(0),
         }
     }
 }
@@ -139,6 +253,10 @@ struct SpriteSheet {
 
     sprite_x_offset : i32,
     sprite_y_offset : i32,
+    // NOTE(erick): Where the sheet starts in the texture. Not zero for sheets
+    // that are an atlas region.
+    sheet_x0        : i32,
+    sheet_y0        : i32,
     sprite_sheet_width  : u32,
     sprite_sheet_height : u32,
 
@@ -152,57 +270,55 @@ impl SpriteSheet {
             w: u32, h: u32, _animation_info: AnimationInfo) -> SpriteSheet {
         // TODO(erick): We are not checking whether the sprite sheet dimensions are
         // multiples of the sprite dimensions.
-        let _fps = 16;
-
-        SpriteSheet {
+        let mut result = SpriteSheet {
             sprite_height : h,
             sprite_width  : w,
 
             sprite_x_offset : 0,
             sprite_y_offset : 0,
+            sheet_x0        : 0,
+            sheet_y0        : 0,
             sprite_sheet_width   : sprite_sheet_w,
             sprite_sheet_height  : sprite_sheet_h,
 
             texture : _texture,
 
             animation_info : _animation_info,
-        }
-    }
-
-    fn animation_accumulate_dt(&mut self, dt: f32) {
-        //
-        // Early-outs
-        //
-        if !self.animation_info.is_animating {
-            return;
-        }
+        };
+        result.show_current_frame();
 
-        if self.animation_info.current_animation_lane_index == -1 {
-            return;
-        }
+        result
+    }
 
-        self.animation_info.acc_dt += dt;
+    // NOTE(erick): Frames can be anywhere in the sheet and have any size.
+    fn show_current_frame(&mut self) {
+        let frame = match self.animation_info.animation_set {
+            Some(ref animation_set) if !animation_set.clips.is_empty() => *self.animation_info.player.frame(animation_set),
+            _                                                           => { return; }
+        };
 
-        if self.animation_info.acc_dt < self.animation_info.frame_time {
-            return
-        }
+        self.sprite_x_offset = frame.rect.x as i32;
+        self.sprite_y_offset = frame.rect.y as i32;
+        self.sprite_width = frame.rect.width;
+        self.sprite_height = frame.rect.height;
+    }
 
-        let ref current_lane = self.animation_info.animation_lanes[self.animation_info.current_animation_lane_index as usize];
-        while self.animation_info.acc_dt >= self.animation_info.frame_time {
-            self.animation_info.acc_dt -= self.animation_info.frame_time;
+    fn animation_accumulate_dt(&mut self, dt: f32) -> Option<AnimationEvent> {
+        let event = match self.animation_info.animation_set {
+            Some(ref animation_set) if !animation_set.clips.is_empty() => self.animation_info.player.update(animation_set, dt),
+            _                                                           => { return None; }
+        };
+        self.show_current_frame();
 
-            self.animation_info.current_frame += 1;
-            if self.animation_info.current_frame >= current_lane.number_of_frames {
-                self.animation_info.current_frame = 0;
-            }
-        }
-        self.sprite_x_offset = (self.sprite_width * self.animation_info.current_frame) as i32;
+        event
     }
 
+    // NOTE(erick): Lanes are the clips of the animation set.
     fn set_animation_lane_index(&mut self, index: usize) {
-        if index < self.animation_info.animation_lanes.len() {
-            self.animation_info.current_animation_lane_index = index as isize;
-            self.sprite_y_offset = (self.sprite_height * index as u32) as i32;
+        let n_clips = self.animation_info.animation_set.as_ref().map_or(0, |animation_set| animation_set.clips.len());
+        if index < n_clips {
+            self.animation_info.player.play(index);
+            self.show_current_frame();
         }
     }
 }
@@ -248,183 +364,284 @@ impl Entity {
         self.position.y = self.position.y.floor() + y_diff * 0.5;
     }
 
+    #[allow(dead_code)]
     fn containing_rect(&self) -> Rect2 {
         Rect2::from_point_and_dimensions(self.position, self.collision_width, self.collision_height)
     }
 
-    fn collision_against_entities(&self, entities: &Vec<Entity>, movement: Vector2) -> isize {
-        let self_rect = self.containing_rect();
-        let target_rect = &self_rect + movement;
-
-        for index in 0..entities.len() {
-            let ref entity = entities[index];
-            let entity_rect = entity.containing_rect();
+    // NOTE(erick): What the camera follows.
+    fn center(&self) -> Vector2 {
+        Vector2::new(self.position.x + self.draw_width * 0.5, self.position.y + self.draw_height * 0.5)
+    }
 
-            if !target_rect.collides_with(&entity_rect).is_none() {
-                return index as isize;
-            }
-        }
+    // NOTE(erick): Where the entity should be drawn when standing on a given tile.
+    fn position_on_tile(&self, tile: TilePosition) -> Vector2 {
+        let x_diff = self.draw_width.ceil() - self.draw_width;
+        let y_diff = self.draw_height.ceil() - self.draw_height;
 
-        -1
+        Vector2::new(tile.0 as f32 + x_diff * 0.5, tile.1 as f32 + y_diff * 0.5)
     }
 
-    fn collision_against_tiles(&self, map: &Map, mut movement: Vector2) -> Vector2 {
-        let entity_rect = self.containing_rect();
+    // NOTE(erick): Movement is grid-discrete, this only interpolates the sprite
+    // between two tiles. Returns true once the target was reached.
+    fn move_towards(&mut self, target: Vector2, speed: f32, dt: f32) -> bool {
+        let mut delta = target - self.position;
+        let distance = (delta.x * delta.x + delta.y * delta.y).sqrt();
+        let max_step = speed * dt;
+
+        if distance <= max_step {
+            self.position = target;
+            self.velocity = Vector2::zero();
+            return true;
+        }
 
-        let target_rect = &entity_rect + movement;
+        delta.normalize_or_zero();
+        self.velocity = delta * speed;
+        self.position += self.velocity * dt;
 
-        // NOTE(erick): We only need to search inside the bounding rectangle
-        let bounding_rect = Rect2::bounding_rect(&entity_rect, &target_rect);
-        let min_point = bounding_rect.lower_left();
-        let max_point = bounding_rect.upper_right();
+        false
+    }
 
-        let min_tile_x = min_point.x.floor() as u32; // Inclusive
-        let min_tile_y = min_point.y.floor() as u32; // Inclusive
+    fn draw(&self, camera: &Camera, renderer: &mut Renderer) {
+        self.draw_with_sheet(&self.sprite_sheet, camera, renderer);
+    }
 
-        let max_tile_x = max_point.x.ceil() as u32; // Exclusive
-        let max_tile_y = max_point.y.ceil() as u32; // Exclusive
+    // NOTE(erick): Draws the entity with another look, e.g. boxes on targets.
+    fn draw_with_sheet(&self, sprite_sheet: &SpriteSheet, camera: &Camera, renderer: &mut Renderer) {
+        let (x_screen_coord, y_screen_coord, w_screen_coord, h_screen_coord) =
+            camera.to_screen_rect(self.position, self.draw_width, self.draw_height);
 
-        'outter: for tile_y in min_tile_y..max_tile_y {
-            for tile_x in min_tile_x..max_tile_x {
-                let tile_type = map.tile_at(tile_x, tile_y);
-                if let TileType::Wall = tile_type {
-                    let tile_rect = Rect2 {
-                        x0: tile_x as f32,
-                        y0: tile_y as f32,
+        let source_rect = Rect::new(sprite_sheet.sheet_x0 + sprite_sheet.sprite_x_offset,
+                                    sprite_sheet.sheet_y0 + sprite_sheet.sprite_y_offset,
+                                    sprite_sheet.sprite_width, sprite_sheet.sprite_height);
+        let dest_rect = Rect::new(x_screen_coord, y_screen_coord, w_screen_coord, h_screen_coord);
+        renderer.copy_ex(&sprite_sheet.texture, Some(source_rect), Some(dest_rect), 0.0, None, false, false).unwrap();
+    }
+}
 
-                        x1: 1.0 + tile_x as f32,
-                        y1: 1.0 + tile_y as f32,
-                    };
+// NOTE(erick): A rectangle of a texture. Sprites that come from the same atlas
+// share the texture.
+#[derive(Clone)]
+struct Sprite {
+    texture : Rc<Texture>,
+    rect    : Rect,
+}
 
-                    if !target_rect.collides_with(&tile_rect).is_none() {
-                        movement = Vector2::zero();
-                        break 'outter;
+impl Sprite {
+    // NOTE(erick): texture_name is an image file or an atlas region (see the
+    // atlas module). Images already in images are not loaded again. A broken
+    // atlas or a missing region gives the errors, ready to be printed.
+    fn load(texture_name: &str, renderer: &Renderer,
+            images: &mut HashMap<String, (Rc<Texture>, u32, u32)>) -> Result<Sprite, Vec<String>> {
+        match split_atlas_reference(texture_name) {
+            Some((atlas_name, region_name)) => {
+                let atlas = match parse_atlas(atlas_name) {
+                    Ok(atlas)   => atlas,
+                    Err(errors) => {
+                        return Err(errors.iter().map(|error| format!("Error({}): {}", atlas_name, error)).collect());
                     }
-                }
-            }
-        }
+                };
 
-        movement
+                Sprite::from_atlas(&atlas, region_name, renderer, images)
+            },
+            None                            => Sprite::from_image(texture_name, None, renderer, images),
+        }
     }
 
-    fn draw(&self, renderer: &mut Renderer) {
-         // TODO(erick): Should the camera move?
-        const CAMERA_Y0     : u32 = 0;
-        const CAMERA_X0     : u32 = 0;
+    // NOTE(erick): Like load, but a sprite that can't be loaded is reported and
+    // replaced by default_name (an image, like the default textures). The
+    // defaults ship with the game, without them there is nothing to draw.
+    fn load_or_default(texture_name: &str, default_name: &str, renderer: &Renderer,
+                       images: &mut HashMap<String, (Rc<Texture>, u32, u32)>) -> Sprite {
+        match Sprite::load(texture_name, renderer, images) {
+            Ok(sprite)      => sprite,
+            Err(errors)     => {
+                for error in errors {
+                    println!("{}", error);
+                }
+                println!("Using {} instead of {}", default_name, texture_name);
 
-        const CAMERA_HEIGHT : u32 = 16;
-        const CAMERA_WIDTH  : u32 = 20;
+                match Sprite::from_image(default_name, None, renderer, images) {
+                    Ok(sprite)  => sprite,
+                    Err(errors) => panic!("Could not load the default sprite {}: {}", default_name, errors.join(", ")),
+                }
+            },
+        }
+    }
 
-        let x_camera_coord = self.position.x - CAMERA_X0 as f32;
-        let y_camera_coord = self.position.y - CAMERA_Y0 as f32;
+    fn from_atlas(atlas: &TextureAtlas, region_name: &str, renderer: &Renderer,
+                  images: &mut HashMap<String, (Rc<Texture>, u32, u32)>) -> Result<Sprite, Vec<String>> {
+        match atlas.region(region_name) {
+            Ok(region)  => Sprite::from_image(atlas.image.as_str(), Some(region), renderer, images),
+            Err(error)  => Err(vec![format!("Error({}): {}", atlas.image, error)]),
+        }
+    }
 
-        let x_screen_coord = (x_camera_coord * (WINDOW_WIDTH / CAMERA_WIDTH) as f32) as i32;
-        let y_screen_coord = WINDOW_HEIGHT as i32 - ( (y_camera_coord + self.draw_height) * (WINDOW_HEIGHT / CAMERA_HEIGHT) as f32) as i32;
+    fn from_image(image_name: &str, region: Option<AtlasRect>, renderer: &Renderer,
+                  images: &mut HashMap<String, (Rc<Texture>, u32, u32)>) -> Result<Sprite, Vec<String>> {
+        if !images.contains_key(image_name) {
+            let image_path = asset_path_string(AssetType::Sprite, image_name);
+            let (texture, width, height) = match texture_from_path(Path::new(image_path.as_str()), renderer) {
+                Ok(loaded)  => loaded,
+                Err(error)  => { return Err(vec![error]); }
+            };
+            images.insert(image_name.to_string(), (Rc::new(texture), width, height));
+        }
 
-        let w_screen_coord = (self.draw_width * (WINDOW_WIDTH / CAMERA_WIDTH) as f32) as u32;
-        let h_screen_coord = (self.draw_height * (WINDOW_HEIGHT / CAMERA_HEIGHT) as f32) as u32;
+        let (ref texture, width, height) = images[image_name];
+        let rect = match region {
+            Some(region)    => Rect::new(region.x as i32, region.y as i32, region.width, region.height),
+            None            => Rect::new(0, 0, width, height),
+        };
 
-        let source_rect = Rect::new(self.sprite_sheet.sprite_x_offset, self.sprite_sheet.sprite_y_offset,
-                                    self.sprite_sheet.sprite_width, self.sprite_sheet.sprite_height);
-        let dest_rect = Rect::new(x_screen_coord, y_screen_coord, w_screen_coord, h_screen_coord);
-        renderer.copy_ex(&self.sprite_sheet.texture, Some(source_rect), Some(dest_rect), 0.0, None, false, false).unwrap();
+        Ok(Sprite {
+            texture : texture.clone(),
+            rect    : rect,
+        })
     }
-}
 
-#[derive(Debug)]
-#[derive(Clone)]
-#[derive(Copy)]
-enum TileType {
-    Floor,
-    Wall,
-    Target,
-    Blank,
-}
+    // NOTE(erick): A sheet of sprite_width x sprite_height frames inside the
+    // sprite.
+    fn to_sprite_sheet(&self, sprite_width: u32, sprite_height: u32, animation_info: AnimationInfo) -> SpriteSheet {
+        let mut sprite_sheet = SpriteSheet::new(self.texture.clone(), self.rect.width(), self.rect.height(),
+                                                sprite_width, sprite_height, animation_info);
+        sprite_sheet.sheet_x0 = self.rect.x();
+        sprite_sheet.sheet_y0 = self.rect.y();
 
-impl TileType {
-    fn from_code(code: u32) -> Option<TileType> {
-        match code {
-            0 | 2 | 3   => Some(TileType::Floor),
-            1           => Some(TileType::Wall),
-            4           => Some(TileType::Target),
-            5           => Some(TileType::Blank),
-            _           => None,
-        }
+        sprite_sheet
     }
 }
 
 struct MapData {
-    floor_texture   : Rc<Texture>,
-    wall_texture    : Rc<Texture>,
-    target_texture  : Rc<Texture>,
-    box_texture     : Rc<Texture>,
-
-    tile_texture_width  : u32,
-    tile_texture_height : u32,
-
-    box_texture_width   : u32,
-    box_texture_height  : u32,
+    floor           : Sprite,
+    wall            : Sprite,
+    target          : Sprite,
+    _box            : Sprite,
+    box_on_target   : Option<Sprite>,
 }
 
 impl MapData {
-    fn load(renderer: &Renderer, floor_path: &Path, wall_path: &Path,
-            target_path: &Path, box_path: &Path) -> MapData {
-        let (floor, w, h)   = texture_from_path(floor_path, renderer);
-        let (wall, ..)      = texture_from_path(wall_path, renderer);
-        let (target, ..)    = texture_from_path(target_path, renderer);
-
-        let(_box, b_w, b_h) = texture_from_path(box_path, renderer);
+    fn load(renderer: &Renderer, textures: &LevelTextures) -> MapData {
+        let mut images = HashMap::new();
+        let defaults = LevelTextures::default_textures();
+
+        // NOTE(erick): Without its sprite, boxes on targets look like any other box.
+        let box_on_target = match textures.box_on_target_sprite {
+            Some(ref texture_name)  => {
+                match Sprite::load(texture_name.as_str(), renderer, &mut images) {
+                    Ok(sprite)      => Some(sprite),
+                    Err(errors)     => {
+                        for error in errors {
+                            println!("{}", error);
+                        }
+                        None
+                    },
+                }
+            },
+            None                    => None,
+        };
 
         MapData {
-            floor_texture   : Rc::new(floor),
-            wall_texture    : Rc::new(wall),
-            target_texture  : Rc::new(target),
-            box_texture     : Rc::new(_box),
-
-            tile_texture_width  : w,
-            tile_texture_height : h,
-
-            box_texture_width   : b_w,
-            box_texture_height  : b_h,
+            floor           : Sprite::load_or_default(textures.floor_tile.as_str(), defaults.floor_tile.as_str(), renderer, &mut images),
+            wall            : Sprite::load_or_default(textures.wall_tile.as_str(), defaults.wall_tile.as_str(), renderer, &mut images),
+            target          : Sprite::load_or_default(textures.target_tile.as_str(), defaults.target_tile.as_str(), renderer, &mut images),
+            _box            : Sprite::load_or_default(textures.box_sprite_sheet.as_str(), defaults.box_sprite_sheet.as_str(),
+                                                      renderer, &mut images),
+            box_on_target   : box_on_target,
         }
     }
 
     #[allow(dead_code)]
     fn load_default(renderer: &Renderer) -> MapData {
-        let default_floor_path  = Path::new("assets/floor.bmp");
-        let default_wall_path   = Path::new("assets/wall.bmp");
-        let default_target_path = Path::new("assets/target.bmp");
-        let default_box_path    = Path::new("assets/box.bmp");
-
-        MapData::load(renderer, default_floor_path, default_wall_path,
-                        default_target_path, default_box_path)
+        MapData::load(renderer, &LevelTextures::default_textures())
     }
 }
 
-// TODO(erick): Tiles and box should be in separated structs, otherwise we can't borrow a mutable box
-// and an immutable map. CHECK FIRST!!!!
-struct Map {
-    name        : String,
-    level_music : Option<String>,
-    next_level  : Option<String>,
+// NOTE(erick): The sprites of a theme (see the theme module), ready to draw.
+// Themes only change the tiles, boxes and the player come from the level.
+struct ThemeView {
+    autotile    : Autotile,
+    walls       : Vec<Sprite>,
+    floors      : Vec<Sprite>,
+    target      : Sprite,
+    blank       : Option<Sprite>,
+    blank_color : Color,
+}
 
-    tiles: Vec<TileType>,
-    tiles_stride: i32,
+impl ThemeView {
+    // NOTE(erick): parse_theme already checked the regions, this can only fail
+    // if the theme changed since.
+    fn load(theme: &Theme, renderer: &Renderer) -> Result<ThemeView, Vec<String>> {
+        let mut images = HashMap::new();
+        let atlas = &theme.atlas;
+
+        let walls = (0..theme.autotile.n_pieces())
+            .map(|index| Sprite::from_atlas(atlas, format!("wall_{}", index).as_str(), renderer, &mut images))
+            .collect::<Result<Vec<Sprite>, Vec<String>>>()?;
+        let floors = (0..theme.n_floor_variants)
+            .map(|index| Sprite::from_atlas(atlas, format!("floor_{}", index).as_str(), renderer, &mut images))
+            .collect::<Result<Vec<Sprite>, Vec<String>>>()?;
+        let target = Sprite::from_atlas(atlas, "target", renderer, &mut images)?;
+        let blank = if theme.has_blank {
+            Some(Sprite::from_atlas(atlas, "blank", renderer, &mut images)?)
+        } else {
+            None
+        };
+
+        let (r, g, b) = theme.blank_color;
 
+        Ok(ThemeView {
+            autotile    : theme.autotile,
+            walls       : walls,
+            floors      : floors,
+            target      : target,
+            blank       : blank,
+            blank_color : Color::RGB(r, g, b),
+        })
+    }
+}
+
+// NOTE(erick): Everything we need to draw a game::Map. The map itself (tiles and
+// boxes positions) lives in the game module.
+struct MapView {
     map_data: MapData,
     boxes: Vec<Entity>,
+    box_on_target_sheet: Option<SpriteSheet>,
+    // NOTE(erick): effects_sheet has the clips of the level effects, every
+    // effect playing is a copy of it.
+    effects_sheet: Option<SpriteSheet>,
+    effects: Vec<Entity>,
 }
 
-impl Map {
-    fn add_box(map: &mut Map, sprite_width: u32, sprite_height: u32, _x: u32, _y: u32) {
-        let boxes_anim_info = AnimationInfo::new(false, 0);
+impl MapView {
+    fn new(map: &Map, textures: &LevelTextures, renderer: &Renderer) -> MapView {
+        let _map_data = MapData::load(renderer, textures);
+        let box_on_target_sheet = _map_data.box_on_target.as_ref().map(|sprite| {
+            sprite.to_sprite_sheet(textures.box_sprite_width, textures.box_sprite_height, AnimationInfo::new(None))
+        });
+
+        let effects_sheet = textures.effects_animation.as_ref()
+            .and_then(|animation_name| load_animation_sheet(animation_name.as_str(), renderer));
+
+        let mut result = MapView {
+            map_data: _map_data,
+            boxes: Vec::new(),
+            box_on_target_sheet: box_on_target_sheet,
+            effects_sheet: effects_sheet,
+            effects: Vec::new(),
+        };
+
+        for &(box_x, box_y) in &map.boxes {
+            MapView::add_box(&mut result, textures.box_sprite_width, textures.box_sprite_height, box_x, box_y);
+        }
+
+        result
+    }
+
+    fn add_box(view: &mut MapView, sprite_width: u32, sprite_height: u32, _x: u32, _y: u32) {
+        let boxes_anim_info = AnimationInfo::new(None);
 
-        let _sprite = SpriteSheet::new(map.map_data.box_texture.clone(),
-                        map.map_data.box_texture_width,
-                        map.map_data.box_texture_height,
-                        sprite_width,
-                        sprite_height,
-                        boxes_anim_info);
+        let _sprite = view.map_data._box.to_sprite_sheet(sprite_width, sprite_height, boxes_anim_info);
 
         let e_box = Entity {
             position : Vector2 {
@@ -441,124 +658,230 @@ impl Map {
 
             sprite_sheet    : _sprite,
         };
-        map.boxes.push(e_box);
+        view.boxes.push(e_box);
     }
 
-    #[allow(dead_code)]
-    fn from_left_to_right_handed(position : (u32, u32), n_lines: u32) -> (u32, u32) {
-        (position.0, n_lines - position.1 - 1)
+    // NOTE(erick): Used when the number of boxes changes (editing or switching
+    // boards).
+    fn set_boxes(&mut self, map: &Map, textures: &LevelTextures) {
+        self.boxes.clear();
+        self.effects.clear();
+        for &(box_x, box_y) in &map.boxes {
+            MapView::add_box(self, textures.box_sprite_width, textures.box_sprite_height, box_x, box_y);
+        }
     }
 
-    fn fill_tiles_and_stride(map: &mut Map, map_file: &Path) {
-        let input_file = File::open(map_file).expect(format!("Could not open file: {:?}", map_file).as_str());
-
-        let file_data = BufReader::new(&input_file);
-
-        let mut n_lines: u32 = 0;
-        for line in file_data.lines() {
-            n_lines += 1;
-
-            let line = line.unwrap();
-            let tiles_code = line.split_whitespace();
+    fn snap_boxes(&mut self, map: &Map) {
+        self.effects.clear();
+        for (index, _box) in self.boxes.iter_mut().enumerate() {
+            let (box_x, box_y) = map.boxes[index];
+            _box.position = Vector2::new(box_x as f32, box_y as f32);
+        }
+    }
 
-            let mut n_tiles: u32 = 0;
-            for code in tiles_code {
-                n_tiles += 1;
-                let code = code.parse::<u32>().unwrap();
-                let tile_type = TileType::from_code(code).unwrap();
-                map.tiles.push(tile_type);
+    fn update_boxes(&mut self, map: &Map, speed: f32, dt: f32) -> bool {
+        let mut all_arrived = true;
+        let mut landed_on_targets = Vec::new();
+        for (index, _box) in self.boxes.iter_mut().enumerate() {
+            let (box_x, box_y) = map.boxes[index];
+            let target = Vector2::new(box_x as f32, box_y as f32);
+            let was_moving = _box.position != target;
+
+            if !_box.move_towards(target, speed, dt) {
+                all_arrived = false;
+            } else if was_moving && map.tile_at(box_x, box_y) == TileType::Target {
+                landed_on_targets.push((box_x, box_y));
             }
+        }
 
-            if map.tiles_stride < 0 {
-                map.tiles_stride = n_tiles as i32;
-            } else {
-                if map.tiles_stride != n_tiles as i32 {
-                    // TODO(erick): Error
-                    println!("Invalid line ({}) at file {:?}", n_lines, map_file);
-                }
-            }
+        for tile in landed_on_targets {
+            self.spawn_effect("box_on_target", tile);
         }
+
+        all_arrived
     }
 
+    // NOTE(erick): Does nothing when the level has no such effect.
+    fn spawn_effect(&mut self, clip_name: &str, tile: TilePosition) {
+        let mut sprite_sheet = match self.effects_sheet {
+            Some(ref effects_sheet) => effects_sheet.clone(),
+            None                    => { return; }
+        };
 
-    fn tile_at(&self, x: u32, y: u32) -> TileType {
-        // NOTE(erick): Tiles are storage in a left-handed coordinate system.
-        // We invert it here
-        let y = self.n_lines() - y - 1;
-        let pos : usize = (y * self.n_cols() + x) as usize;
+        let clip_index = match sprite_sheet.animation_info.animation_set.as_ref().and_then(|animation_set| animation_set.clip_index(clip_name)) {
+            Some(index) => index,
+            None        => { return; }
+        };
+        sprite_sheet.set_animation_lane_index(clip_index);
 
-        self.tiles[pos]
+        let position = Vector2::new(tile.0 as f32, tile.1 as f32);
+        self.effects.push(Entity::new(sprite_sheet, position, 1.0, 1.0, 1.0, 1.0));
     }
 
-    fn n_cols(&self) -> u32 {
-        if self.tiles_stride < 0 {
-            0
-        }
-        else {
-            self.tiles_stride as u32
+    // NOTE(erick): Effects go away once their clip is done. Looping ones stay
+    // until the boxes are reset.
+    fn update_effects(&mut self, dt: f32) {
+        let mut index = 0;
+        while index < self.effects.len() {
+            match self.effects[index].sprite_sheet.animation_accumulate_dt(dt) {
+                Some(AnimationEvent::Finished(_))   => { self.effects.remove(index); },
+                None                                => { index += 1; },
+            }
         }
     }
 
-    fn n_lines(&self) -> u32 {
-        self.tiles.len() as u32 / self.tiles_stride as u32
-    }
-
-    fn draw_tile(tile: TileType, x: u32, y: u32, width: u32, height: u32, map_data: &MapData, renderer: &mut Renderer) {
-        let tile_x_screen_coord = (x * width) as i32;
-        let tile_y_screen_coord = (WINDOW_HEIGHT - y * height - height) as i32;
-
-        let tile_texture = match tile {
-            TileType::Floor   => Some(&map_data.floor_texture),
-            TileType::Wall    => Some(&map_data.wall_texture),
-            TileType::Target  => Some(&map_data.target_texture),
-            _                 => None
+    fn draw_tile(map: &Map, x: u32, y: u32, camera: &Camera, map_data: &MapData,
+                 theme: Option<&ThemeView>, renderer: &mut Renderer) {
+        let (tile_x_screen_coord, tile_y_screen_coord, width, height) =
+            camera.to_screen_rect(Vector2::new(x as f32, y as f32), 1.0, 1.0);
+
+        let tile = map.tile_at(x, y);
+        let tile_sprite = match theme {
+            Some(theme) => {
+                match tile {
+                    TileType::Floor   => Some(&theme.floors[floor_variant_index(x, y, theme.floors.len())]),
+                    TileType::Wall    => Some(&theme.walls[wall_piece_index(map, x, y, theme.autotile)]),
+                    TileType::Target  => Some(&theme.target),
+                    TileType::Blank   => theme.blank.as_ref(),
+                }
+            },
+            None        => {
+                match tile {
+                    TileType::Floor   => Some(&map_data.floor),
+                    TileType::Wall    => Some(&map_data.wall),
+                    TileType::Target  => Some(&map_data.target),
+                    _                 => None
+                }
+            },
         };
 
-        if !tile_texture.is_none() {
-            let tile_texture = tile_texture.unwrap();
+        if !tile_sprite.is_none() {
+            let tile_sprite = tile_sprite.unwrap();
 
-            let source_rect = Rect::new(0, 0, map_data.tile_texture_width, map_data.tile_texture_height);
             let dest_rect = Rect::new(tile_x_screen_coord, tile_y_screen_coord, width, height);
-            renderer.copy_ex(tile_texture, Some(source_rect), Some(dest_rect), 0.0, None, false, false).unwrap();
+            renderer.copy_ex(&tile_sprite.texture, Some(tile_sprite.rect), Some(dest_rect), 0.0, None, false, false).unwrap();
+        }
+    }
+
+    // NOTE(erick): The inverse of the mapping used by MapView::draw.
+    fn tile_at_screen(map: &Map, camera: &Camera, screen_x: i32, screen_y: i32) -> Option<TilePosition> {
+        if !camera.is_on_view(screen_x, screen_y) {
+            return None;
+        }
+
+        let world_position = camera.to_world(screen_x, screen_y);
+        let tile_x = world_position.x.floor() as i32;
+        let tile_y = world_position.y.floor() as i32;
+
+        if map.is_inside(tile_x, tile_y) {
+            Some((tile_x as u32, tile_y as u32))
+        } else {
+            None
         }
     }
 
-    fn draw(&self, renderer: &mut Renderer) {
-        // TODO(erick): Should the camera move?
-        const CAMERA_Y0     : u32 = 0;
-        const CAMERA_X0     : u32 = 0;
+    // NOTE(erick): Drawn after MapView::draw. Highlights the box to be pushed
+    // and the tile it should be pushed to.
+    fn draw_hint(hint: &Hint, camera: &Camera, renderer: &mut Renderer) {
+        let tile_rect = |x: i32, y: i32| {
+            let (screen_x, screen_y, width, height) = camera.to_screen_rect(Vector2::new(x as f32, y as f32), 1.0, 1.0);
+            Rect::new(screen_x, screen_y, width, height)
+        };
+
+        let (box_x, box_y) = hint.box_position;
+        let (destination_x, destination_y) = hint.direction.step(hint.box_position);
 
-        const CAMERA_HEIGHT : u32 = 16;
-        const CAMERA_WIDTH  : u32 = 20;
+        let old_color = renderer.draw_color();
+        renderer.set_blend_mode(BlendMode::Blend);
 
-        let tile_width_in_camera    = WINDOW_WIDTH / CAMERA_WIDTH;
-        let tile_height_in_camera   = WINDOW_HEIGHT / CAMERA_HEIGHT;
+        renderer.set_draw_color(Color::RGBA(0, 255, 0, 112));
+        renderer.fill_rect(tile_rect(box_x as i32, box_y as i32)).unwrap();
+        renderer.set_draw_color(Color::RGBA(0, 255, 0, 56));
+        renderer.fill_rect(tile_rect(destination_x, destination_y)).unwrap();
+
+        renderer.set_draw_color(old_color);
+    }
 
-        for tile_y in CAMERA_Y0..self.n_lines() {
-            // NOTE(erick): We are outside the camera.
-            if tile_y >= CAMERA_HEIGHT { break; }
+    fn draw(&self, map: &Map, camera: &Camera, theme: Option<&ThemeView>, renderer: &mut Renderer) {
+        let (x0, y0, x1, y1) = camera.visible_tiles();
 
-            for tile_x in CAMERA_X0..self.n_cols() {
-                // NOTE(erick): We are outside the camera.
-                if tile_x >= CAMERA_WIDTH { break; }
+        // NOTE(erick): The outer darkness. It covers the view, not only the
+        // Blank tiles, so small maps float on it.
+        if let Some(theme) = theme {
+            let old_color = renderer.draw_color();
+            renderer.set_draw_color(theme.blank_color);
+            renderer.fill_rect(Rect::new(camera.screen_x0, camera.screen_y0, camera.screen_width, camera.screen_height)).unwrap();
+            renderer.set_draw_color(old_color);
+        }
 
-                let tile = self.tile_at(tile_x, tile_y);
-                Map::draw_tile(tile, tile_x, tile_y, tile_width_in_camera, tile_height_in_camera, &self.map_data, renderer);
+        // NOTE(erick): Only the tiles that are on the screen.
+        for tile_y in y0.max(0)..y1.min(map.n_lines() as i32) {
+            for tile_x in x0.max(0)..x1.min(map.n_cols() as i32) {
+                MapView::draw_tile(map, tile_x as u32, tile_y as u32, camera, &self.map_data, theme, renderer);
             }
         }
 
         for _box in &self.boxes {
-            _box.draw(renderer);
+            // NOTE(erick): The sprite changes once the box is more than halfway
+            // onto the target.
+            let tile_x = _box.position.x.round() as i32;
+            let tile_y = _box.position.y.round() as i32;
+            let is_on_target = map.is_inside(tile_x, tile_y) &&
+                map.tile_at(tile_x as u32, tile_y as u32) == TileType::Target;
+
+            match self.box_on_target_sheet {
+                Some(ref sheet) if is_on_target => { _box.draw_with_sheet(sheet, camera, renderer); },
+                _                               => { _box.draw(camera, renderer); },
+            }
+        }
+
+        for effect in &self.effects {
+            effect.draw(camera, renderer);
         }
     }
 }
 
 fn main() {
+    // NOTE(erick): Arguments are parsed before anything else so a typo doesn't
+    // open a window just to close it.
+    // Usage: sokoban [pack file] [--replay <replay file>] [--no-audio]
+    // Without a pack file we play the levels shipped with the game.
+    let args : Vec<String> = std::env::args().collect();
+    let usage = format!("Usage: {} [pack file] [--replay <replay file>] [--no-audio]", args[0]);
+    let mut pack_path = None;
+    let mut replay_path = None;
+    let mut audio_enabled = true;
+
+    let mut arg_index = 1;
+    while arg_index < args.len() {
+        if args[arg_index] == "--replay" {
+            if arg_index + 1 >= args.len() {
+                println!("--replay needs a replay file");
+                println!("{}", usage);
+                std::process::exit(2);
+            }
+            replay_path = Some(args[arg_index + 1].clone());
+            arg_index += 2;
+        } else if args[arg_index] == "--no-audio" {
+            audio_enabled = false;
+            arg_index += 1;
+        } else if args[arg_index].starts_with("--") {
+            println!("Unknown option {}", args[arg_index]);
+            println!("{}", usage);
+            std::process::exit(2);
+        } else {
+            pack_path = Some(args[arg_index].clone());
+            arg_index += 1;
+        }
+    }
+
     let mut game_state : GameState = GameState::new();
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let ttf_context = sdl2::ttf::init().unwrap();
+    // NOTE(erick): Only needed for PNG and JPG, BMP files load without it.
+    let _image_context = sdl2::image::init(INIT_PNG | INIT_JPG).unwrap();
 
     let window = video_subsystem.window(GAME_NAME, WINDOW_WIDTH, WINDOW_HEIGHT)
         .resizable()
@@ -581,8 +904,6 @@ fn main() {
     let game_controller_subsystem = sdl_context.game_controller().unwrap();
     let mut timer = sdl_context.timer().unwrap();
     let mut events = sdl_context.event_pump().unwrap();
-    #[allow(unused_variables)]
-    let mixer = AudioMixer::new(&sdl_context);
 
 
     // NOTE(erick): controller has to be here because
@@ -605,36 +926,232 @@ fn main() {
     //
     // Player and Map
     //
-    let (mut map, mut player) = parse_level("1-starting", &renderer).unwrap();
+    let packs = load_packs(pack_path);
+    if packs.is_empty() {
+        println!("Could not load any level pack");
+        std::process::exit(1);
+    }
 
-    #[allow(unused_variables)]
-    let level_music;
-    if !map.level_music.is_none() {
-        level_music = play_music(Path::new(map.level_music.as_ref().unwrap().as_str()));
+    //
+    // Progress
+    //
+    let progress_path = progress_file_path();
+    let mut progress = match progress_path {
+        Some(ref path)  => Progress::load(path),
+        None            => Progress::new(),
+    };
+    // NOTE(erick): The level being played. It is loaded for real once a scene
+    // asks for it through level_to_load.
+    let mut pack_index = 0;
+    let mut level_index = 0;
+    let mut startup_level : Option<(usize, usize)> = None;
+
+    //
+    // Replays
+    //
+    let mut playback : Option<ReplayPlayback> = None;
+    if let Some(replay_path) = replay_path {
+        let loaded_replay = match Replay::load(Path::new(replay_path.as_str())) {
+            Ok(replay)  => replay,
+            Err(errors) => {
+                for error in errors {
+                    println!("Error({}): {}", replay_path, error);
+                }
+                std::process::exit(1);
+            }
+        };
+
+        startup_level = find_level(&packs, loaded_replay.level_hash);
+        if startup_level.is_none() {
+            println!("{}: The replay is not for any level we know of", replay_path);
+            std::process::exit(1);
+        }
+
+        playback = Some(ReplayPlayback::new(&loaded_replay));
+        game_state.reset_scenes(Scene::Replay);
     }
 
+    let mut level = packs[pack_index].levels[level_index].level.clone();
+    let mut map_view = MapView::new(&level.map, &level.textures, &renderer);
+    let mut player = create_player(level.player_position, &level.textures, &renderer);
+    let mut player_facing = Direction::Down;
+    let mut camera = Camera::new(CAMERA_WIDTH, CAMERA_HEIGHT, WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let themes = load_themes();
+    let mut theme_view : Option<ThemeView> = None;
+    let mut audio = Audio::new(&sdl_context, audio_enabled);
+    audio.set_sound_effects(load_sound_registry(&level.map, None));
+    // NOTE(erick): Holding a direction against a wall tries the move every
+    // frame, it should only bump once.
+    let mut bumped_direction : Option<Direction> = None;
+    let mut board = Board::new(level.map.clone(), level.player_position);
+    let mut deadlock_detector = DeadlockDetector::new(&board.map);
+    let mut deadlock = None;
+
+    let mut hint_request : Option<HintRequest> = None;
+    let mut hint : Option<Hint> = None;
+
+    // NOTE(erick): Every attempt is recorded, it is saved when the level is
+    // solved or when asked to (F6).
+    let mut replay = Replay::new(level.identity_hash());
+    let mut replay_message : Option<String> = None;
 
-    // NOTE(erick): Running cat animation stuff. This is only so we can have
-    // an example of the animation code
-    let mut cat_anim_info = AnimationInfo::new(true, 12);
-    cat_anim_info.animation_lanes.push(AnimationLane{number_of_frames: 6});
-    cat_anim_info.animation_lanes.push(AnimationLane{number_of_frames: 6});
-    let (running_cat_texture, texture_w, texture_h) = texture_from_path(Path::new("assets/animate.bmp"), &renderer);
-    let mut running_cat_sprite = SpriteSheet::new(Rc::new(running_cat_texture), texture_w, texture_h, 128, 82, cat_anim_info);
-    running_cat_sprite.set_animation_lane_index(1);
-    let running_cat_width = 4.0;
-    let running_cat_height = running_cat_width * 82.0 / 128.0;
-    let mut running_cat = Entity::new(running_cat_sprite, Vector2::new(16.0, 13.5), running_cat_width, running_cat_height, running_cat_width, running_cat_height);
+    //
+    // Editor
+    //
+    let mut editor : Option<Editor> = None;
+    let mut editor_message : Option<String> = None;
+
+    let audio_settings_path = audio_settings_file_path();
+    let mut audio_settings = match audio_settings_path {
+        Some(ref path)  => AudioSettings::load(path),
+        None            => AudioSettings::new(),
+    };
+    audio.set_volumes(&audio_settings);
 
+    let mut level_start_ticks = timer.ticks();
 
     game_state.is_running = true;
     while game_state.is_running {
 
+        keyboard_input.clear_actions();
+        joystick_input.clear_actions();
+
+        let mut editor_changed = false;
+
         for event in events.poll_iter() {
+            let is_typing = match editor {
+                Some(ref editor)    => game_state.scene() == Scene::Edit && !editor.editing_field.is_none(),
+                None                => false,
+            };
+
             match event {
-                Event::Quit {..} | Event::KeyDown {keycode: Some(Keycode::Escape), ..} => {
+                Event::Quit {..} => {
                     game_state.is_running = false;
                 },
+                Event::KeyDown {keycode: Some(Keycode::Escape), ..} => {
+                    if is_typing {
+                        editor.as_mut().unwrap().cancel_editing();
+                    } else if game_state.scene() == Scene::Edit {
+                        keyboard_input.toggle_editor = true;
+                    } else {
+                        keyboard_input.cancel = true;
+                    }
+                },
+                Event::KeyDown {keycode: Some(Keycode::F2), ..} => { keyboard_input.toggle_editor = true; },
+                Event::KeyDown {keycode: Some(Keycode::F5), ..} => { keyboard_input.toggle_test_play = true; },
+                Event::KeyDown {keycode: Some(Keycode::F8), ..} => { keyboard_input.toggle_mute = true; },
+                Event::KeyDown {keycode: Some(Keycode::F9), ..} => { keyboard_input.next_theme = true; },
+                Event::KeyDown {keycode: Some(Keycode::F11), ..} => { keyboard_input.toggle_fullscreen = true; },
+                Event::KeyDown {keycode: Some(keycode), ..} if game_state.scene() == Scene::Replay => {
+                    let playback = playback.as_mut().unwrap();
+                    match keycode {
+                        Keycode::Space                      => { playback.is_paused = !playback.is_paused; },
+                        Keycode::Equals | Keycode::KpPlus   => { playback.speed = (playback.speed * 2.0).min(16.0); },
+                        Keycode::Minus | Keycode::KpMinus   => { playback.speed = (playback.speed * 0.5).max(0.25); },
+                        Keycode::R                          => { keyboard_input.restart = true; },
+                        _                                   => {},
+                    }
+                },
+                Event::KeyDown {keycode: Some(keycode), keymod, ..} if game_state.scene() == Scene::Edit => {
+                    let editor = editor.as_mut().unwrap();
+
+                    if is_typing {
+                        match keycode {
+                            Keycode::Return     => { editor.commit_editing(); },
+                            Keycode::Backspace  => { editor.erase_character(); },
+                            Keycode::Tab        => {
+                                let next_field = editor.editing_field.unwrap().next();
+                                editor.commit_editing();
+                                editor.start_editing(next_field);
+                            },
+                            _                   => {},
+                        }
+                        continue;
+                    }
+
+                    match keycode {
+                        Keycode::Num1   => { editor.tool = EditorTool::Paint(TileType::Floor); },
+                        Keycode::Num2   => { editor.tool = EditorTool::Paint(TileType::Wall); },
+                        Keycode::Num3   => { editor.tool = EditorTool::Paint(TileType::Target); },
+                        Keycode::Num4   => { editor.tool = EditorTool::Paint(TileType::Blank); },
+                        Keycode::B      => { editor.tool = EditorTool::Box; },
+                        Keycode::P      => { editor.tool = EditorTool::Player; },
+                        Keycode::Tab    => { editor.start_editing(EditorField::FileName); },
+                        // NOTE(erick): There is no player to follow in the editor.
+                        Keycode::Left   => { camera.pan(Vector2::new(-1.0, 0.0), editor.map.n_cols(), editor.map.n_lines()); },
+                        Keycode::Right  => { camera.pan(Vector2::new(1.0, 0.0), editor.map.n_cols(), editor.map.n_lines()); },
+                        Keycode::Up     => { camera.pan(Vector2::new(0.0, 1.0), editor.map.n_cols(), editor.map.n_lines()); },
+                        Keycode::Down   => { camera.pan(Vector2::new(0.0, -1.0), editor.map.n_cols(), editor.map.n_lines()); },
+                        Keycode::S if keymod.intersects(LCTRLMOD | RCTRLMOD) => {
+                            editor_message = Some(match editor.save() {
+                                Ok(())      => format!("Saved {}.lvl", editor.file_name),
+                                Err(errors) => format!("Not saved: {}", errors[0]),
+                            });
+                        },
+                        _               => {},
+                    }
+                },
+                Event::KeyDown {keycode: Some(keycode), ..} => {
+                    match keycode {
+                        Keycode::U | Keycode::Z => { keyboard_input.action_a = true; },
+                        Keycode::Y              => { keyboard_input.action_b = true; },
+                        Keycode::R              => { keyboard_input.restart = true; },
+                        Keycode::H              => { keyboard_input.hint = true; },
+                        Keycode::F6             => { keyboard_input.save_replay = true; },
+                        Keycode::Return | Keycode::KpEnter | Keycode::Space => { keyboard_input.confirm = true; },
+                        _                       => {},
+                    }
+
+                    match keycode {
+                        Keycode::Up | Keycode::W    => { keyboard_input.menu_up = true; },
+                        Keycode::Down | Keycode::S  => { keyboard_input.menu_down = true; },
+                        Keycode::Left | Keycode::A  => { keyboard_input.menu_left = true; },
+                        Keycode::Right | Keycode::D => { keyboard_input.menu_right = true; },
+                        _                       => {},
+                    }
+                },
+                Event::TextInput { ref text, .. } if is_typing => {
+                    editor.as_mut().unwrap().type_text(text.as_str());
+                },
+                Event::MouseButtonDown { mouse_btn, x, y, .. } if game_state.scene() == Scene::Edit && !is_typing => {
+                    let editor = editor.as_mut().unwrap();
+                    if let Some(position) = MapView::tile_at_screen(&editor.map, &camera, x, y) {
+                        match mouse_btn {
+                            MouseButton::Left   => { editor_changed |= editor.apply_tool(position); },
+                            MouseButton::Right  => { editor_changed |= editor.remove_box(position); },
+                            _                   => {},
+                        }
+                    }
+                },
+                // NOTE(erick): Dragging paints too, so walls can be drawn in one go.
+                Event::MouseMotion { mousestate, x, y, .. } if game_state.scene() == Scene::Edit && !is_typing => {
+                    let editor = editor.as_mut().unwrap();
+                    if let Some(position) = MapView::tile_at_screen(&editor.map, &camera, x, y) {
+                        if mousestate.left() {
+                            editor_changed |= editor.apply_tool(position);
+                        } else if mousestate.right() {
+                            editor_changed |= editor.remove_box(position);
+                        }
+                    }
+                },
+                Event::ControllerButtonDown { button: Button::Start, .. } => { joystick_input.cancel = true; },
+                Event::ControllerButtonDown { .. } if game_state.scene() == Scene::Replay => {},
+                Event::ControllerButtonDown { button, .. } => {
+                    match button {
+                        // NOTE(erick): A also confirms in menus and B goes back, the
+                        // board only looks at undo and redo while playing.
+                        Button::A           => { joystick_input.action_a = true; joystick_input.confirm = true; },
+                        Button::B           => { joystick_input.action_b = true; },
+                        Button::Back        => { joystick_input.restart = true; },
+                        Button::Y           => { joystick_input.hint = true; },
+                        Button::DPadUp      => { joystick_input.menu_up = true; },
+                        Button::DPadDown    => { joystick_input.menu_down = true; },
+                        Button::DPadLeft    => { joystick_input.menu_left = true; },
+                        Button::DPadRight   => { joystick_input.menu_right = true; },
+                        _               => {},
+                    }
+                },
                 Event::ControllerAxisMotion{ axis, value: val, .. } => {
                     fn handle_axis_input(axis: &mut f32, val: i16) {
                         // Axis motion is an absolute value in the range
@@ -697,9 +1214,281 @@ fn main() {
         }
 
         let new_ticks = timer.ticks();
-        let dt = ((new_ticks - game_state.old_ticks) as f32) / 1000.0;
+        let frame_ticks = new_ticks - game_state.old_ticks;
+        let dt = (frame_ticks as f32) / 1000.0;
         game_state.old_ticks = new_ticks;
 
+        //
+        // Editor
+        //
+        let mut new_level : Option<Level> = None;
+
+        //
+        // Scenes
+        //
+        let mut level_to_load = startup_level.take();
+        // NOTE(erick): Pause menu items that act on the board. They are applied
+        // with the rest of the board input below.
+        let mut pause_action : Option<MenuItem> = None;
+        let was_playing = game_state.scene().is_playing();
+
+        let confirm = keyboard_input.confirm || joystick_input.confirm;
+        let cancel = keyboard_input.cancel || joystick_input.cancel;
+        // NOTE(erick): B goes back in menus, like Escape.
+        let back = cancel || joystick_input.action_b;
+        let (menu_dx, menu_dy) = {
+            let (keyboard_dx, keyboard_dy) = keyboard_input.menu_delta();
+            let (joystick_dx, joystick_dy) = joystick_input.menu_delta();
+            (keyboard_dx + joystick_dx, keyboard_dy + joystick_dy)
+        };
+        let mut audio_changed = false;
+
+        let n_menu_items = match game_state.scene() {
+            Scene::PackSelect(_)    => packs.len(),
+            scene                   => scene.menu_items().len(),
+        };
+        match *game_state.scene_mut() {
+            Scene::Title(ref mut menu) | Scene::PackSelect(ref mut menu) |
+            Scene::Pause(ref mut menu) | Scene::Options(ref mut menu) => {
+                menu.move_selection(menu_dy, n_menu_items);
+            },
+            Scene::LevelSelect(ref mut grid) => {
+                grid.move_selection(menu_dx, menu_dy, packs[grid.pack_index].levels.len());
+            },
+            _ => {},
+        }
+
+        match game_state.scene() {
+            Scene::Title(menu) if confirm => {
+                match TITLE_MENU[menu.selected] {
+                    MenuItem::Continue  => {
+                        level_to_load = Some(continue_level(&packs, &progress));
+                        game_state.reset_scenes(Scene::Play);
+                    },
+                    MenuItem::Levels    => {
+                        // NOTE(erick): There is nothing to choose from with a single pack.
+                        if packs.len() == 1 {
+                            let selected = progress.last_played_index(&packs[0]).unwrap_or(0);
+                            game_state.push_scene(Scene::LevelSelect(LevelGrid::new(0, selected)));
+                        } else {
+                            game_state.push_scene(Scene::PackSelect(Menu::new()));
+                        }
+                    },
+                    MenuItem::Options   => { game_state.push_scene(Scene::Options(Menu::new())); },
+                    MenuItem::Quit      => { game_state.is_running = false; },
+                    _                   => {},
+                }
+            },
+            Scene::PackSelect(menu) => {
+                if confirm {
+                    let selected = progress.last_played_index(&packs[menu.selected]).unwrap_or(0);
+                    game_state.push_scene(Scene::LevelSelect(LevelGrid::new(menu.selected, selected)));
+                } else if back {
+                    game_state.pop_scene();
+                }
+            },
+            Scene::LevelSelect(grid) => {
+                if confirm {
+                    level_to_load = Some((grid.pack_index, grid.selected));
+                    game_state.reset_scenes(Scene::Play);
+                } else if back {
+                    game_state.pop_scene();
+                }
+            },
+            Scene::Pause(menu) => {
+                if confirm {
+                    match PAUSE_MENU[menu.selected] {
+                        MenuItem::Resume        => { game_state.pop_scene(); },
+                        MenuItem::Restart | MenuItem::Undo => {
+                            pause_action = Some(PAUSE_MENU[menu.selected]);
+                            game_state.pop_scene();
+                        },
+                        MenuItem::Options       => { game_state.push_scene(Scene::Options(Menu::new())); },
+                        MenuItem::QuitToTitle   => {
+                            playback = None;
+                            game_state.reset_scenes(Scene::Title(Menu::new()));
+                        },
+                        _                       => {},
+                    }
+                } else if back {
+                    game_state.pop_scene();
+                }
+            },
+            Scene::Options(menu) => {
+                if confirm {
+                    match OPTIONS_MENU[menu.selected] {
+                        MenuItem::MasterVolume      => { audio_settings.master_volume = cycle_volume(audio_settings.master_volume); audio_changed = true; },
+                        MenuItem::MusicVolume       => { audio_settings.music_volume = cycle_volume(audio_settings.music_volume); audio_changed = true; },
+                        MenuItem::EffectsVolume     => { audio_settings.effects_volume = cycle_volume(audio_settings.effects_volume); audio_changed = true; },
+                        MenuItem::Mute              => { keyboard_input.toggle_mute = true; },
+                        MenuItem::FrameTime         => { game_state.options.show_frame_time = !game_state.options.show_frame_time; },
+                        MenuItem::DeadlockWarnings  => { game_state.options.show_deadlock_warnings = !game_state.options.show_deadlock_warnings; },
+                        MenuItem::Fullscreen        => { keyboard_input.toggle_fullscreen = true; },
+                        MenuItem::IntegerScale      => { game_state.options.integer_scale = !game_state.options.integer_scale; },
+                        MenuItem::Theme             => { keyboard_input.next_theme = true; },
+                        MenuItem::Back              => { game_state.pop_scene(); },
+                        _                           => {},
+                    }
+                } else if back {
+                    game_state.pop_scene();
+                } else if menu_dx != 0 {
+                    match OPTIONS_MENU[menu.selected] {
+                        MenuItem::MasterVolume      => { audio_settings.master_volume = step_volume(audio_settings.master_volume, menu_dx); audio_changed = true; },
+                        MenuItem::MusicVolume       => { audio_settings.music_volume = step_volume(audio_settings.music_volume, menu_dx); audio_changed = true; },
+                        MenuItem::EffectsVolume     => { audio_settings.effects_volume = step_volume(audio_settings.effects_volume, menu_dx); audio_changed = true; },
+                        _                           => {},
+                    }
+                }
+            },
+            Scene::LevelComplete(_) if confirm => {
+                match packs[pack_index].next_level_index(level_index) {
+                    Some(next_level_index)  => {
+                        level_to_load = Some((pack_index, next_level_index));
+                        game_state.set_scene(Scene::Play);
+                    },
+                    None                    => { game_state.set_scene(Scene::PackComplete); },
+                }
+            },
+            Scene::PackComplete if confirm => { game_state.reset_scenes(Scene::Title(Menu::new())); },
+            Scene::Play | Scene::TestPlay | Scene::Replay if cancel => {
+                game_state.push_scene(Scene::Pause(Menu::new()));
+            },
+            _ => {},
+        }
+
+        if let Some((new_pack_index, new_level_index)) = level_to_load {
+            pack_index = new_pack_index;
+            level_index = new_level_index;
+            level = packs[pack_index].levels[level_index].level.clone();
+            new_level = Some(level.clone());
+
+            // NOTE(erick): The next level may use other textures.
+            map_view = MapView::new(&level.map, &level.textures, &renderer);
+            audio.set_sound_effects(load_sound_registry(&level.map, game_state.options.theme_index.map(|index| &themes[index])));
+            audio.play_music(level.map.level_music.as_ref());
+            editor = None;
+
+            // NOTE(erick): Watching a replay is not playing.
+            if game_state.scene() != Scene::Replay {
+                progress.set_last_played(packs[pack_index].title.as_str(), level.identity_hash());
+            }
+        }
+
+        // NOTE(erick): The clock of the attempt stops while it is paused.
+        match game_state.scene() {
+            Scene::Pause(_) | Scene::Options(_) if game_state.shows_board() => { level_start_ticks += frame_ticks; },
+            _ => {},
+        }
+
+        //
+        // Audio
+        //
+        if keyboard_input.toggle_mute {
+            audio_settings.muted = !audio_settings.muted;
+            audio_changed = true;
+        }
+        if audio_changed {
+            audio.set_volumes(&audio_settings);
+            save_audio_settings(&audio_settings, &audio_settings_path);
+        }
+        audio.update(dt);
+
+        //
+        // Screen
+        //
+        if keyboard_input.toggle_fullscreen {
+            game_state.options.fullscreen = !game_state.options.fullscreen;
+            set_fullscreen(&mut renderer, game_state.options.fullscreen);
+        }
+
+        // NOTE(erick): The window may have been resized (or gone fullscreen), the
+        // drawable size is the one that counts on high-DPI screens.
+        let (drawable_width, drawable_height) = renderer.output_size().unwrap();
+        // NOTE(erick): Themes are switched in place, the level doesn't notice.
+        if keyboard_input.next_theme {
+            game_state.options.theme_index = match game_state.options.theme_index {
+                None                                        => if themes.is_empty() { None } else { Some(0) },
+                Some(index) if index + 1 < themes.len()     => Some(index + 1),
+                Some(_)                                     => None,
+            };
+            theme_view = match game_state.options.theme_index.map(|index| ThemeView::load(&themes[index], &renderer)) {
+                Some(Ok(view))      => Some(view),
+                Some(Err(errors))   => {
+                    for error in errors {
+                        println!("{}", error);
+                    }
+                    game_state.options.theme_index = None;
+                    None
+                },
+                None                => None,
+            };
+            audio.set_sound_effects(load_sound_registry(&level.map, game_state.options.theme_index.map(|index| &themes[index])));
+        }
+
+        let tile_texture_width = match theme_view {
+            Some(ref theme_view)    => theme_view.floors[0].rect.width(),
+            None                    => map_view.map_data.floor.rect.width(),
+        };
+        let texture_size = if game_state.options.integer_scale { Some(tile_texture_width) } else { None };
+        camera.fit_to_screen(drawable_width, drawable_height, texture_size);
+
+        if keyboard_input.toggle_editor {
+            match game_state.scene() {
+                Scene::Play      => {
+                    if editor.is_none() {
+                        editor = Some(Editor::new(level_file_name(&packs[pack_index].levels[level_index]).as_str(), &level));
+                    }
+                    game_state.set_scene(Scene::Edit);
+                    editor_changed = true;
+                },
+                // NOTE(erick): Leaving the editor plays the edited level from the start.
+                Scene::Edit      => {
+                    let editor = editor.as_mut().unwrap();
+                    editor.cancel_editing();
+                    level = editor.to_level();
+                    new_level = Some(level.clone());
+                    game_state.set_scene(Scene::Play);
+                },
+                Scene::TestPlay  => {
+                    game_state.set_scene(Scene::Edit);
+                    editor_changed = true;
+                },
+                _                => {},
+            }
+        } else if keyboard_input.toggle_test_play {
+            match game_state.scene() {
+                Scene::Edit      => {
+                    let editor = editor.as_mut().unwrap();
+                    editor.cancel_editing();
+                    new_level = Some(editor.to_level());
+                    game_state.set_scene(Scene::TestPlay);
+                },
+                Scene::TestPlay  => {
+                    game_state.set_scene(Scene::Edit);
+                    editor_changed = true;
+                },
+                _                => {},
+            }
+        }
+
+        if game_state.scene() == Scene::Edit {
+            let editor = editor.as_ref().unwrap();
+
+            if editor_changed {
+                map_view.set_boxes(&editor.map, &editor.textures);
+                player.position = player.position_on_tile(editor.player_position);
+            }
+
+            renderer.clear();
+            map_view.draw(&editor.map, &camera, theme_view.as_ref(), &mut renderer);
+            player.draw(&camera, &mut renderer);
+            draw_letterbox(&mut renderer, &camera);
+            draw_editor_text(&mut renderer, &fps_font, editor, &editor_message);
+            renderer.present();
+
+            continue;
+        }
+
 
         let mut move_direction = Vector2::zero();
         // NOTE(erick): We only read the keyboard when there is no input on the joystick
@@ -713,477 +1502,680 @@ fn main() {
             move_direction.y = joystick_input.left_y_axis;
         }
 
-        // HACK(erick): We are moving the player (and the boxes) here.
-        // This code need to be generalized, but I don't know to to generalize it yet.
-        // TODO(erick): Entity-vs-entity collision when the second entity is not movable
-        // is not handled yet.
+        let mut board_changed = false;
 
-        fn move_entity(entity: &mut Entity, mut force: Vector2, map: &mut Map, dt: f32) {
-            const entity_mass : f32 = 0.0058;
-            const drag : f32 = 20.0;
+        if let Some(new_level) = new_level {
+            board = Board::new(new_level.map.clone(), new_level.player_position);
+            board_changed = true;
+            level_start_ticks = timer.ticks();
+            replay = Replay::new(new_level.identity_hash());
 
-            force.normalize_or_zero();
+            map_view.set_boxes(&board.map, &level.textures);
+            // NOTE(erick): The next level may use another player sprite sheet.
+            player = create_player(board.player, &new_level.textures, &renderer);
+            player_facing = Direction::Down;
+            deadlock_detector = DeadlockDetector::new(&board.map);
 
-            entity.acceleration = force / entity_mass - entity.velocity * drag;
-            entity.velocity += entity.acceleration * dt;
-            let mut target_movement = entity.velocity * dt;
+            camera.snap_to(player.center(), board.map.n_cols(), board.map.n_lines());
+        }
 
-            target_movement = entity.collision_against_tiles(map, target_movement);
-            entity.position += target_movement;
+        // NOTE(erick): The input that closed a menu is not played on the board.
+        let takes_input = was_playing && game_state.scene().is_playing();
+        // NOTE(erick): Replays can only be restarted, undoing would leave the
+        // board behind the playback.
+        let can_undo = takes_input && game_state.scene() != Scene::Replay;
+
+        if pause_action == Some(MenuItem::Restart) || (takes_input && (keyboard_input.restart || joystick_input.restart)) {
+            board.restart();
+            board_changed = true;
+            // NOTE(erick): Sliding every sprite back to its initial tile looks weird.
+            player.position = player.position_on_tile(board.player);
+            map_view.snap_boxes(&board.map);
+
+            if let Some(ref mut playback) = playback {
+                playback.restart();
+            }
+        } else if pause_action == Some(MenuItem::Undo) || (can_undo && (keyboard_input.action_a || joystick_input.action_a)) {
+            if game_state.scene() != Scene::Replay {
+                board_changed = !board.undo().is_none();
+                if board_changed {
+                    audio.play_effect(SoundEffect::Undo);
+                }
+            }
+        } else if can_undo && (keyboard_input.action_b || joystick_input.action_b) {
+            board_changed = !board.redo().is_none();
+        }
 
+        if board_changed && game_state.scene() != Scene::Replay {
+            replay.sync_with_board(&board, timer.ticks() - level_start_ticks);
         }
 
-        move_entity(&mut player, move_direction, &mut map, dt);
+        // NOTE(erick): Fast replays also move the sprites faster, otherwise the
+        // animation would hold the replay back.
+        let move_speed = match playback {
+            Some(ref playback)  => ENTITY_MOVE_SPEED * playback.speed.max(1.0),
+            None                => ENTITY_MOVE_SPEED,
+        };
+        if let Some(ref mut playback) = playback {
+            if game_state.scene().is_playing() {
+                playback.advance(dt);
+            }
+        }
 
-        {
-            let mut end_game = true;
-            for _box in & map.boxes {
-                let box_tile = map.tile_at(_box.position.x as u32, _box.position.y as u32);
-                if let TileType::Target = box_tile {
+        // NOTE(erick): The game rules only know about tiles. We only ask for a
+        // new move once the sprites reached the tiles of the previous one.
+        let player_target = player.position_on_tile(board.player);
+        let player_arrived = player.move_towards(player_target, move_speed, dt);
+        let boxes_arrived = map_view.update_boxes(&board.map, move_speed, dt);
 
+        // NOTE(erick): The player faces where it is going, and a box sliding
+        // along with it means it is pushing one.
+        if let Some(direction) = Direction::from_axis(player.velocity.x, player.velocity.y) {
+            player_facing = direction;
+        }
+        let player_state = if player_arrived {
+            PlayerState::Idle
+        } else if !boxes_arrived {
+            PlayerState::Push
+        } else {
+            PlayerState::Walk
+        };
+        let player_clip = match player.sprite_sheet.animation_info.animation_set {
+            Some(ref animation_set) => find_player_clip(animation_set, player_state, player_facing),
+            None                    => None,
+        };
+        if let Some(clip_index) = player_clip {
+            player.sprite_sheet.set_animation_lane_index(clip_index);
+        }
+        camera.follow(player.center(), board.map.n_cols(), board.map.n_lines(), dt);
+
+        if !game_state.scene().is_playing() {
+            // NOTE(erick): Nothing moves behind the menus and the completion screens.
+        } else if player_arrived && boxes_arrived {
+            if game_state.scene() == Scene::Replay {
+                if let Some(direction) = playback.as_mut().unwrap().next_move() {
+                    let result = board.try_move(direction);
+                    board_changed = result != MoveResult::Blocked;
+                    for effect in move_sounds(&board, result) {
+                        audio.play_effect(effect);
+                    }
+                }
+            } else if board.is_solved() {
+                if game_state.scene() == Scene::TestPlay {
+                    game_state.set_scene(Scene::Edit);
+                    map_view.set_boxes(&editor.as_ref().unwrap().map, &level.textures);
+                    player.position = player.position_on_tile(editor.as_ref().unwrap().player_position);
                 } else {
-                    end_game = false;
-                    break;
+                    let time_ms = timer.ticks() - level_start_ticks;
+                    let is_new_best = progress.record_solve(packs[pack_index].title.as_str(), level.identity_hash(),
+                                                            board.n_moves(), board.n_pushes(), time_ms);
+                    save_progress(&progress, &progress_path);
+                    save_replay(&replay);
+
+                    audio.play_effect(SoundEffect::LevelComplete);
+                    game_state.set_scene(Scene::LevelComplete(LevelStats {
+                        n_moves     : board.n_moves(),
+                        n_pushes    : board.n_pushes(),
+                        time_ms     : time_ms,
+                        is_new_best : is_new_best,
+                    }));
                 }
+            } else if let Some(direction) = Direction::from_axis(move_direction.x, move_direction.y) {
+                let result = board.try_move(direction);
+                if result != MoveResult::Blocked {
+                    board_changed = true;
+                    replay.sync_with_board(&board, timer.ticks() - level_start_ticks);
+                }
+
+                if result != MoveResult::Blocked || bumped_direction != Some(direction) {
+                    for effect in move_sounds(&board, result) {
+                        audio.play_effect(effect);
+                    }
+                }
+                bumped_direction = if result == MoveResult::Blocked { Some(direction) } else { None };
+            } else {
+                bumped_direction = None;
             }
+        }
+
+        if keyboard_input.save_replay && takes_input && game_state.scene() != Scene::Replay {
+            replay_message = save_replay(&replay).map(|path| format!("Replay saved to {}", path.display()));
+        }
+
+        if board_changed {
+            deadlock = deadlock_detector.find_deadlock(&board.map, board.player);
 
-            if end_game {
-                game_state.is_running = false;
+            // NOTE(erick): The hint was computed for a board that no longer exists.
+            // Dropping the request cancels the solver.
+            hint_request = None;
+            hint = None;
+        }
+
+        if (keyboard_input.hint || joystick_input.hint) && takes_input {
+            if hint_request.is_none() && hint.is_none() {
+                let mut hint_options = SolverOptions::new(Objective::Pushes);
+                hint_options.time_limit = Some(std::time::Duration::from_secs(5));
+                hint_request = Some(HintRequest::start(&board, hint_options));
             }
         }
 
+        let mut hint_finished = false;
+        if let Some(ref request) = hint_request {
+            match request.poll() {
+                HintStatus::Pending         => {},
+                HintStatus::Ready(new_hint) => { hint = Some(new_hint); hint_finished = true; },
+                HintStatus::NoHint          => { hint_finished = true; },
+            }
+        }
+        if hint_finished {
+            hint_request = None;
+        }
+
 
         let fps_text = format!("Frame time: {:.3}", dt);
 
-        running_cat.sprite_sheet.animation_accumulate_dt(dt);
+        player.sprite_sheet.animation_accumulate_dt(dt);
+        map_view.update_effects(dt);
 
         renderer.clear();
-        map.draw(&mut renderer);
-        player.draw(&mut renderer);
-        running_cat.draw(&mut renderer);
 
-        draw_text(&mut renderer, &fps_font, Color::RGB(255, 0, 0), &fps_text, Vector2::new(0.02, 0.02), false);
+        if game_state.shows_board() {
+            map_view.draw(&board.map, &camera, theme_view.as_ref(), &mut renderer);
+            if let Some(ref hint) = hint {
+                MapView::draw_hint(hint, &camera, &mut renderer);
+            }
+            player.draw(&camera, &mut renderer);
+            draw_letterbox(&mut renderer, &camera);
 
-        draw_text(&mut renderer, &level_title_font, Color::RGBA(0, 167, 208, 127), &map.name, Vector2::new(0.5, 0.1), true);
+            draw_text(&mut renderer, &level_title_font, Color::RGBA(0, 167, 208, 127), &board.map.name, Vector2::new(0.5, 0.1), true);
 
-        renderer.present();
+            if let Some(ref playback) = playback {
+                let replay_text = if playback.is_finished() {
+                    if board.is_solved() { String::from("Replay finished: solved") } else { String::from("Replay finished: not solved") }
+                } else if playback.is_paused {
+                    format!("Replay x{} (paused)", playback.speed)
+                } else {
+                    format!("Replay x{}   Space: pause  +/-: speed  R: restart", playback.speed)
+                };
+                draw_text(&mut renderer, &fps_font, Color::RGB(255, 255, 255), &replay_text, Vector2::new(0.5, 0.86), true);
+            }
 
-        // use std::time::Duration;
-        // std::thread::sleep(Duration::from_millis(100));
-    }
-}
+            if let Some(ref message) = replay_message {
+                draw_text(&mut renderer, &fps_font, Color::RGB(255, 128, 0), message, Vector2::new(0.02, 0.96), false);
+            }
 
-#[derive(Debug)]
-enum AssetType {
-    Sound,
-    Sprite,
-    Map,
-    Level,
-}
+            if !deadlock.is_none() && game_state.options.show_deadlock_warnings {
+                let deadlock_text = String::from("This level is now unsolvable. Press U to undo");
+                draw_text(&mut renderer, &fps_font, Color::RGB(255, 64, 64), &deadlock_text, Vector2::new(0.5, 0.92), true);
+            }
+        }
 
-fn create_player(player_position: (u32, u32), renderer: &Renderer) -> Entity {
-    let player_anim_info = AnimationInfo::new(false, 0);
+        if game_state.options.show_frame_time {
+            draw_text(&mut renderer, &fps_font, Color::RGB(255, 0, 0), &fps_text, Vector2::new(0.02, 0.02), false);
+        }
 
-    let (player_texture, texture_w, texture_h) = texture_from_path(Path::new("assets/player.bmp"), &renderer);
-    let player_sprite = SpriteSheet::new(Rc::new(player_texture), texture_w, texture_h, texture_w, texture_h, player_anim_info);
+        match game_state.scene() {
+            Scene::LevelComplete(stats)  => {
+                draw_dim_overlay(&mut renderer);
+                draw_text(&mut renderer, &level_title_font, Color::RGB(0, 167, 208), &String::from("Level complete"), Vector2::new(0.5, 0.35), true);
 
-    let player_x = player_position.0 as f32;
-    let player_y = player_position.1 as f32;
-    let player_width_to_height_ratio = texture_w as f32 / texture_h as f32;
+                let stats_text = format!("Moves: {}   Pushes: {}   Time: {}", stats.n_moves, stats.n_pushes, format_time(stats.time_ms));
+                draw_text(&mut renderer, &fps_font, Color::RGB(255, 255, 255), &stats_text, Vector2::new(0.5, 0.5), true);
+                if stats.is_new_best {
+                    draw_text(&mut renderer, &fps_font, Color::RGB(255, 215, 0), &String::from("New best!"), Vector2::new(0.5, 0.57), true);
+                }
 
-    let player_draw_height = 1.2;
-    let player_draw_width = player_draw_height * player_width_to_height_ratio;
+                let continue_text = String::from("Press Enter to continue");
+                draw_text(&mut renderer, &fps_font, Color::RGB(200, 200, 200), &continue_text, Vector2::new(0.5, 0.7), true);
+            },
+            Scene::PackComplete          => {
+                let pack = &packs[pack_index];
 
-    let player_collision_height = 0.8;
-    let player_colliion_width  = player_draw_width;
+                draw_dim_overlay(&mut renderer);
+                draw_text(&mut renderer, &level_title_font, Color::RGB(0, 167, 208), &String::from("Pack complete"), Vector2::new(0.5, 0.35), true);
 
-    let mut player = Entity::new(player_sprite, Vector2::new(player_x, player_y),
-                        player_colliion_width, player_collision_height,
-                        player_draw_width, player_draw_height);
-    player.center_on_current_tile_rect();
+                let solved_text = format!("{}: {} of {} levels solved", pack.title, progress.n_solved(pack), pack.levels.len());
+                draw_text(&mut renderer, &fps_font, Color::RGB(255, 255, 255), &solved_text, Vector2::new(0.5, 0.5), true);
 
-    player
-}
+                let continue_text = String::from("Press Enter to go back to the title");
+                draw_text(&mut renderer, &fps_font, Color::RGB(200, 200, 200), &continue_text, Vector2::new(0.5, 0.7), true);
+            },
+            Scene::Title(menu)           => {
+                let labels : Vec<String> = TITLE_MENU.iter().map(|item| item.label().to_string()).collect();
+                draw_menu(&mut renderer, &level_title_font, &fps_font, GAME_NAME, &labels, menu.selected);
+            },
+            Scene::PackSelect(menu)      => {
+                let labels : Vec<String> = packs.iter()
+                    .map(|pack| format!("{} ({}/{})", pack.title, progress.n_solved(pack), pack.levels.len()))
+                    .collect();
+                draw_menu(&mut renderer, &level_title_font, &fps_font, "Level packs", &labels, menu.selected);
+            },
+            Scene::LevelSelect(grid)     => {
+                draw_level_grid(&mut renderer, &level_title_font, &fps_font, &packs[grid.pack_index], &progress, grid);
+            },
+            Scene::Pause(menu)           => {
+                draw_dim_overlay(&mut renderer);
+                let labels : Vec<String> = PAUSE_MENU.iter().map(|item| item.label().to_string()).collect();
+                draw_menu(&mut renderer, &level_title_font, &fps_font, "Paused", &labels, menu.selected);
+            },
+            Scene::Options(menu)         => {
+                if game_state.shows_board() {
+                    draw_dim_overlay(&mut renderer);
+                }
 
-fn asset_path_string(asset_type: AssetType, asset_name: &str) -> String {
-    let mut result = String::new();
+                let labels : Vec<String> = OPTIONS_MENU.iter().map(|item| {
+                    match *item {
+                        MenuItem::FrameTime         => format!("{}: {}", item.label(), on_off(game_state.options.show_frame_time)),
+                        MenuItem::DeadlockWarnings  => format!("{}: {}", item.label(), on_off(game_state.options.show_deadlock_warnings)),
+                        MenuItem::Fullscreen        => format!("{}: {}", item.label(), on_off(game_state.options.fullscreen)),
+                        MenuItem::IntegerScale      => format!("{}: {}", item.label(), on_off(game_state.options.integer_scale)),
+                        MenuItem::Theme             => {
+                            let theme_name = game_state.options.theme_index.map_or("Level", |index| themes[index].name.as_str());
+                            format!("{}: {}", item.label(), theme_name)
+                        },
+                        MenuItem::MasterVolume      => format!("{}: {}%", item.label(), audio_settings.master_volume),
+                        MenuItem::MusicVolume       => format!("{}: {}%", item.label(), audio_settings.music_volume),
+                        MenuItem::EffectsVolume     => format!("{}: {}%", item.label(), audio_settings.effects_volume),
+                        MenuItem::Mute              => format!("{}: {}", item.label(), on_off(audio_settings.muted)),
+                        _                           => item.label().to_string(),
+                    }
+                }).collect();
+                draw_menu(&mut renderer, &level_title_font, &fps_font, "Options", &labels, menu.selected);
+            },
+            _                            => {},
+        }
+
+        renderer.present();
 
-    match asset_type {
-        AssetType::Sound    => { result.push_str("assets/") },
-        AssetType::Sprite   => { result.push_str("assets/") },
-        AssetType::Map      => { result.push_str("assets/maps/") },
-        AssetType::Level    => { result.push_str("assets/maps/") },
+        // use std::time::Duration;
+        // std::thread::sleep(Duration::from_millis(100));
     }
-    result.push_str(asset_name);
 
-    result
+    save_progress(&progress, &progress_path);
 }
 
-fn remove_asset_path(asset_type: AssetType, asset_path: &str) -> &str {
-    let offset : usize;
+// NOTE(erick): Levels that didn't come from a .lvl file are saved under their
+// title.
+fn level_file_name(pack_level: &PackLevel) -> String {
+    match pack_level.file_name {
+        Some(ref file_name) => file_name.clone(),
+        None                => pack_level.title.to_lowercase().replace(' ', "-"),
+    }
+}
 
-    match asset_type {
-        AssetType::Sound    => { offset = "assets/".len() },
-        AssetType::Sprite   => { offset = "assets/".len() },
-        AssetType::Map      => { offset = "assets/maps/".len() },
-        AssetType::Level    => { offset = "assets/maps/".len() },
+// NOTE(erick): The theme's sounds win over the level's. A broken .sfx file is
+// reported and plays nothing.
+fn load_sound_registry(map: &Map, theme: Option<&Theme>) -> Option<SoundRegistry> {
+    let sound_effects_path = match theme.and_then(|theme| theme.sound_effects.as_ref()) {
+        Some(path)  => Some(path),
+        None        => map.sound_effects.as_ref(),
     };
 
-    &asset_path[offset..]
+    match sound_effects_path {
+        Some(path)  => {
+            match parse_sound_effects(path.as_str()) {
+                Ok(registry)    => Some(registry),
+                Err(errors)     => {
+                    for error in errors {
+                        println!("Error({}): {}", path, error);
+                    }
+                    None
+                },
+            }
+        },
+        None        => None,
+    }
 }
 
-#[allow(dead_code)]
-fn write_level_file(level_file_name: &str, map: &Map, textures_names: &HashMap<&str, String>, player_position: (u32, u32)) {
-    //
-    // Level file
-    //
-    let mut level_file_with_extension = String::from(level_file_name);
-    level_file_with_extension.push_str(".lvl");
+fn save_audio_settings(audio_settings: &AudioSettings, audio_settings_path: &Option<PathBuf>) {
+    if let Some(ref path) = *audio_settings_path {
+        if let Err(error) = audio_settings.save(path) {
+            println!("Could not save the audio settings to {:?}: {}", path, error);
+        }
+    }
+}
 
-    let level_file_path = asset_path_string(AssetType::Level, level_file_with_extension.as_str());
-    let level_output_path = Path::new(level_file_path.as_str());
+fn format_time(time_ms: u32) -> String {
+    let seconds = time_ms / 1000;
+    format!("{}:{:02}.{}", seconds / 60, seconds % 60, (time_ms % 1000) / 100)
+}
 
-    //
-    // Map file
-    //
-    let mut map_file_with_extension = String::from(level_file_name);
-    map_file_with_extension.push_str(".map");
+// NOTE(erick): Darkens whatever was drawn so far so the text on top of it can
+// be read.
+fn draw_dim_overlay(renderer: &mut Renderer) {
+    let old_color = renderer.draw_color();
+    renderer.set_blend_mode(BlendMode::Blend);
 
-    let map_file_path = asset_path_string(AssetType::Map, map_file_with_extension.as_str());
-    let map_output_path = Path::new(map_file_path.as_str());
+    renderer.set_draw_color(Color::RGBA(0, 0, 0, 176));
+    let (screen_width, screen_height) = renderer.output_size().unwrap();
+    renderer.fill_rect(Rect::new(0, 0, screen_width, screen_height)).unwrap();
 
+    renderer.set_draw_color(old_color);
+}
 
-    if map.boxes.len() == 0 {
-        panic!("Map must have at least one box");
+// NOTE(erick): Paints what is outside the view of the camera. Sprites that
+// stick out of the view get covered too.
+fn draw_letterbox(renderer: &mut Renderer, camera: &Camera) {
+    let (screen_width, screen_height) = renderer.output_size().unwrap();
+    let view_x1 = camera.screen_x0 + camera.screen_width as i32;
+    let view_y1 = camera.screen_y0 + camera.screen_height as i32;
+
+    let bars = [
+        (0, 0, screen_width as i32, camera.screen_y0),
+        (0, view_y1, screen_width as i32, screen_height as i32 - view_y1),
+        (0, camera.screen_y0, camera.screen_x0, camera.screen_height as i32),
+        (view_x1, camera.screen_y0, screen_width as i32 - view_x1, camera.screen_height as i32),
+    ];
+
+    let old_color = renderer.draw_color();
+    renderer.set_draw_color(Color::RGB(0, 0, 0));
+    for &(x, y, width, height) in bars.iter() {
+        if width > 0 && height > 0 {
+            renderer.fill_rect(Rect::new(x, y, width as u32, height as u32)).unwrap();
+        }
     }
+    renderer.set_draw_color(old_color);
+}
 
-    let mut output_file = match File::create(level_output_path) {
-        Ok(file)    => file,
-        Err(_)      => panic!("Could not open file {:?}", level_output_path),
-    };
-
-    output_file.write_all(format!("level_name = {}\n", map.name).as_bytes())
-        .expect("Failed to write");
+fn set_fullscreen(renderer: &mut Renderer, fullscreen: bool) {
+    let fullscreen_type = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
 
-    if !map.level_music.is_none() {
-        output_file.write_all(format!("level_music = {}\n",
-            remove_asset_path(AssetType::Sound, map.level_music.as_ref().unwrap())).as_bytes())
-            .expect("Failed to write");
+    if let Some(window) = renderer.window_mut() {
+        if let Err(error) = window.set_fullscreen(fullscreen_type) {
+            println!("Could not change the fullscreen mode: {}", error);
+        }
     }
+}
 
-    if !map.next_level.is_none() {
-        output_file.write_all(format!("next_level = {}\n\n",
-            remove_asset_path(AssetType::Level, map.next_level.as_ref().unwrap().as_str())).as_bytes())
-            .expect("Failed to write");
-    }
+fn on_off(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
 
-    output_file.write_all(format!("wall_tile = {}\n", textures_names.get("wall_tile").as_ref().unwrap()).as_bytes())
-        .expect("Failed to write");
-    output_file.write_all(format!("floor_tile = {}\n", textures_names.get("floor_tile").as_ref().unwrap()).as_bytes())
-        .expect("Failed to write");
-    output_file.write_all(format!("target_tile = {}\n\n", textures_names.get("target_tile").as_ref().unwrap()).as_bytes())
-        .expect("Failed to write");
+// NOTE(erick): A title and a vertical list of items. Long lists scroll so the
+// selected item is always visible.
+fn draw_menu(renderer: &mut Renderer, title_font: &Font, font: &Font, title: &str, labels: &[String], selected: usize) {
+    const MAX_VISIBLE_ITEMS : usize = 7;
 
-    output_file.write_all(format!("player_position = ({}, {})\n\n", player_position.0, player_position.1).as_bytes())
-        .expect("Failed to write");
+    draw_text(renderer, title_font, Color::RGB(0, 167, 208), &title.to_string(), Vector2::new(0.5, 0.25), true);
 
-    output_file.write_all(format!("box_sprite_sheet = {}\n", textures_names.get("box_sprite_sheet").as_ref().unwrap()).as_bytes())
-        .expect("Failed to write");
-    output_file.write_all(format!("box_sprite_width = {}\n", map.boxes[0].sprite_sheet.sprite_width).as_bytes())
-        .expect("Failed to write");
-    output_file.write_all(format!("box_sprite_height = {}\n", map.boxes[0].sprite_sheet.sprite_height).as_bytes())
-        .expect("Failed to write");
+    let first_item = if selected >= MAX_VISIBLE_ITEMS { selected + 1 - MAX_VISIBLE_ITEMS } else { 0 };
+    for (row, index) in (first_item..labels.len()).take(MAX_VISIBLE_ITEMS).enumerate() {
+        let (color, text) = if index == selected {
+            (Color::RGB(255, 255, 0), format!("> {} <", labels[index]))
+        } else {
+            (Color::RGB(255, 255, 255), labels[index].clone())
+        };
 
-    output_file.write_all("box_positions = {".as_bytes())
-        .expect("Failed to write");
+        draw_text(renderer, font, color, &text, Vector2::new(0.5, 0.45 + 0.07 * row as f32), true);
+    }
+}
 
-    let mut first = true;
-    for _box in &map.boxes {
-        if !first {
-            output_file.write_all(", ".as_bytes())
-                .expect("Failed to write");
+// NOTE(erick): The levels of a pack as numbered cells. Solved levels are green
+// and the best scores of the selected one are shown below the grid.
+fn draw_level_grid(renderer: &mut Renderer, title_font: &Font, font: &Font, pack: &LevelPack, progress: &Progress, grid: LevelGrid) {
+    const CELL_WIDTH        : u32 = 120;
+    const CELL_HEIGHT       : u32 = 56;
+    const CELL_GAP          : u32 = 16;
+    const MAX_VISIBLE_ROWS  : usize = 5;
+
+    draw_text(renderer, title_font, Color::RGB(0, 167, 208), &pack.title, Vector2::new(0.5, 0.1), true);
+
+    let grid_width = grid.n_columns as u32 * (CELL_WIDTH + CELL_GAP) - CELL_GAP;
+    let (screen_width, screen_height) = renderer.output_size().unwrap();
+    let grid_x0 = (screen_width as i32 - grid_width as i32) / 2;
+    let grid_y0 = (screen_height as f32 * 0.2) as i32;
+
+    let first_row = if grid.row() >= MAX_VISIBLE_ROWS { grid.row() + 1 - MAX_VISIBLE_ROWS } else { 0 };
+    let first_level = first_row * grid.n_columns;
+    let last_level = pack.levels.len().min(first_level + MAX_VISIBLE_ROWS * grid.n_columns);
+
+    let old_color = renderer.draw_color();
+    renderer.set_blend_mode(BlendMode::Blend);
+
+    for level_index in first_level..last_level {
+        let column = (level_index % grid.n_columns) as i32;
+        let row = (level_index / grid.n_columns - first_row) as i32;
+        let cell_x = grid_x0 + column * (CELL_WIDTH + CELL_GAP) as i32;
+        let cell_y = grid_y0 + row * (CELL_HEIGHT + CELL_GAP) as i32;
+        let cell_rect = Rect::new(cell_x, cell_y, CELL_WIDTH, CELL_HEIGHT);
+
+        let is_solved = progress.is_solved(pack.title.as_str(), pack.levels[level_index].level.identity_hash());
+        let fill_color = if is_solved { Color::RGBA(0, 160, 64, 192) } else { Color::RGBA(64, 64, 64, 192) };
+        renderer.set_draw_color(fill_color);
+        renderer.fill_rect(cell_rect).unwrap();
+
+        if level_index == grid.selected {
+            renderer.set_draw_color(Color::RGB(255, 255, 0));
+            renderer.draw_rect(cell_rect).unwrap();
+            renderer.draw_rect(Rect::new(cell_x + 1, cell_y + 1, CELL_WIDTH - 2, CELL_HEIGHT - 2)).unwrap();
         }
-        output_file.write_all(format!("({}, {})", _box.position.x as u32, _box.position.y as u32).as_bytes())
-            .expect("Failed to write");
-        first = false;
+
+        let text_position = Vector2::new((cell_x + CELL_WIDTH as i32 / 2) as f32 / screen_width as f32,
+                                         (cell_y + CELL_HEIGHT as i32 / 2) as f32 / screen_height as f32);
+        draw_text(renderer, font, Color::RGB(255, 255, 255), &(level_index + 1).to_string(), text_position, true);
     }
 
-    output_file.write_all("}\n\n".as_bytes())
-        .expect("Failed to write");
+    renderer.set_draw_color(old_color);
 
-    output_file.write_all(format!("tile_map = {}", map_file_with_extension).as_bytes())
-        .expect("Failed to write");
-    write_map_file(map_output_path, map);
-}
+    if grid.selected < pack.levels.len() {
+        let pack_level = &pack.levels[grid.selected];
+        draw_text(renderer, font, Color::RGB(255, 255, 255), &pack_level.title, Vector2::new(0.5, 0.84), true);
 
-#[allow(dead_code)]
-fn write_map_file(map_path: &Path, map: &Map) {
-    let mut map_file = File::create(map_path).expect(format!("Could not open file {:?}", map_path).as_str());
-
-    let mut current_col = 0;
-    for tile_type in &map.tiles {
-        if current_col == map.tiles_stride {
-            current_col = 0;
-            map_file.write_all("\n".as_bytes())
-            .expect("Failed to write");;
-        }
-
-        let tile_code = match tile_type {
-            &TileType::Wall      => 1,
-            &TileType::Floor     => 0,
-            &TileType::Target    => 4,
-            &TileType::Blank     => 5,
+        let record_text = match progress.level_record(pack.title.as_str(), pack_level.level.identity_hash()) {
+            Some(record)    => format!("Best: {} moves   {} pushes   {}", record.best_moves, record.best_pushes, format_time(record.best_time_ms)),
+            None            => String::from("Not solved yet"),
         };
+        draw_text(renderer, font, Color::RGB(200, 200, 200), &record_text, Vector2::new(0.5, 0.9), true);
+    }
+}
 
-        map_file.write_all(format!("{} ", tile_code).as_bytes())
-        .expect("Failed to write");
+// NOTE(erick): The pack given in the command line comes first, then the levels
+// shipped with the game and then every other pack file in assets/packs.
+fn load_packs(pack_path: Option<String>) -> Vec<LevelPack> {
+    let mut packs = Vec::new();
 
-        current_col += 1;
+    if let Some(pack_path) = pack_path {
+        packs.extend(load_pack(Path::new(pack_path.as_str())));
     }
-}
+    packs.extend(load_pack(Path::new(BUILT_IN_PACK)));
 
-fn parse_level(level_name: &str, renderer: &Renderer) -> (Option<(Map, Entity)>) {
-    let mut filename = String::from(level_name);
-    filename.push_str(".lvl");
-
-    let level_full_path_string = asset_path_string(AssetType::Level, filename.as_str());
-    let level_file_path = Path::new(level_full_path_string.as_str());
-
-
-    let mut _level_name         = None;
-    let mut _level_music        = None;
-    let mut _next_level         = None;
-    let mut _wall_tile          = None;
-    let mut _floor_tile         = None;
-    let mut _target_tile        = None;
-    let mut _tile_map           = None;
-    let mut _player_position    = None;
-    let mut _box_sprite_sheet   = None;
-    let mut _box_sprite_width   = None;
-    let mut _box_sprite_height  = None;
-    let mut _box_positions      = None;
-
-    let level_file = match File::open(level_file_path) {
-        Ok(file)    => file,
-        Err(_)      => { return None; }
+    let mut pack_paths : Vec<PathBuf> = match std::fs::read_dir("assets/packs") {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_)      => Vec::new(),
     };
+    pack_paths.sort();
+    for pack_path in pack_paths.iter().filter(|pack_path| pack_path.as_path() != Path::new(BUILT_IN_PACK)) {
+        packs.extend(load_pack(pack_path));
+    }
 
-    let level_data = BufReader::new(&level_file);
-
-    let mut line_number = 0;
-    for line in level_data.lines() {
-        line_number += 1;
+    packs
+}
 
-        let line = line.unwrap();
-        if line == "" {
-            continue;
-        }
-        if line.starts_with("//") {
-            continue;
-        }
+// NOTE(erick): Broken packs are reported and left out.
+fn load_pack(pack_path: &Path) -> Option<LevelPack> {
+    match read_pack_file(pack_path) {
+        Ok(pack)        => Some(pack),
+        Err(errors)     => {
+            for error in errors {
+                println!("Error({:?}): {}", pack_path, error);
+            }
+            None
+        },
+    }
+}
 
-        let attrib_index = line.find('=');
-        if attrib_index.is_none() {
-            println!("Error({:?} : {}): Could not find '=' sign", level_file_path, line_number);
-            return None; // NOTE(erick): Maybe continue?
+fn find_level(packs: &[LevelPack], level_hash: u64) -> Option<(usize, usize)> {
+    for (pack_index, pack) in packs.iter().enumerate() {
+        if let Some(level_index) = pack.levels.iter().position(|pack_level| pack_level.level.identity_hash() == level_hash) {
+            return Some((pack_index, level_index));
         }
+    }
 
-        let _split = line.split_at(attrib_index.unwrap());
-        let lhs = (_split.0).trim();
-        let rhs = (_split.1)[1..].trim();
+    None
+}
 
-        match lhs {
-            "level_name"          => {_level_name           = Some(rhs.to_string())},
-            "level_music"         => {_level_music          = Some(rhs.to_string())},
-            "next_level"          => {_next_level           = Some(rhs.to_string())},
-            "tile_map"            => {_tile_map             = Some(rhs.to_string())},
-            "wall_tile"           => {_wall_tile            = Some(rhs.to_string())},
-            "floor_tile"          => {_floor_tile           = Some(rhs.to_string())},
-            "target_tile"         => {_target_tile          = Some(rhs.to_string())},
-            "box_sprite_sheet"    => {_box_sprite_sheet     = Some(rhs.to_string())},
-            "player_position"     => {_player_position      = parse_position_tuple(rhs)},
-            "box_positions"       => {_box_positions        = parse_position_tuple_vec(rhs)},
-            "box_sprite_width"    => {_box_sprite_width     = parse_or_none::<u32>(rhs)},
-            "box_sprite_height"   => {_box_sprite_height    = parse_or_none::<u32>(rhs)},
-            _                     => {println!("Unknown variable: {}", lhs);}
+// NOTE(erick): Where Continue takes the player: the last level played, or the
+// first level of the first pack.
+fn continue_level(packs: &[LevelPack], progress: &Progress) -> (usize, usize) {
+    for (pack_index, pack) in packs.iter().enumerate() {
+        if let Some(level_index) = progress.last_played_index(pack) {
+            return (pack_index, level_index);
         }
     }
 
-    //
-    // We got all data from the file. Now we need to check if we got all the information that we need.
-    //
-    if _level_name.is_none() {
-        panic!("Error({:?}): The level has no name.", level_file_path);
-    }
-
+    (0, 0)
+}
 
-    if _wall_tile.is_none() {
-        panic!("Error({:?}): A wall tile must be specified", level_file_path);
-    }
-    if _floor_tile.is_none() {
-        panic!("Error({:?}): A floor tile must be specified", level_file_path);
-    }
-    if _target_tile.is_none() {
-        panic!("Error({:?}): A target tile must be specified", level_file_path);
-    }
-    if _box_sprite_sheet.is_none() {
-        panic!("Error({:?}): A box sprite sheet must be specified", level_file_path);
-    }
-    if _box_sprite_width.is_none() || _box_sprite_height.is_none() {
-        panic!("Error({:?}): The box sprite dimensions must be specified", level_file_path);
+fn save_progress(progress: &Progress, progress_path: &Option<PathBuf>) {
+    if let Some(ref path) = *progress_path {
+        if let Err(error) = progress.save(path) {
+            println!("Could not save the progress to {:?}: {}", path, error);
+        }
     }
+}
 
-
-    if _tile_map.is_none() {
-        panic!("Error({:?}): A tile map must be specified", level_file_path);
-    }
-    if _player_position.is_none() {
-        panic!("Error({:?}): No player initial position", level_file_path);
-    }
-    if _box_positions.is_none() {
-        panic!("Error({:?}): No boxes.", level_file_path);
+// NOTE(erick): Replays go to <data dir>/replays/<level hash>-<unix time>.replay.
+fn save_replay(replay: &Replay) -> Option<PathBuf> {
+    if replay.n_steps() == 0 {
+        return None;
     }
 
-    //
-    // Now we create the map and the player
-    //
-    let floor_path  = asset_path_string(AssetType::Sprite, _floor_tile.unwrap().as_str());
-    let wall_path   = asset_path_string(AssetType::Sprite, _wall_tile.unwrap().as_str());
-    let target_path = asset_path_string(AssetType::Sprite, _target_tile.unwrap().as_str());
-    let box_path    = asset_path_string(AssetType::Sprite, _box_sprite_sheet.unwrap().as_str());
-
-    let map_path    = asset_path_string(AssetType::Map, _tile_map.unwrap().as_str());
-
-    let level_music_path = match _level_music {
-        Some(path) => Some(asset_path_string(AssetType::Sound, path.as_str())),
-        None => None,
+    let replays_dir = match data_dir() {
+        Some(dir)   => dir.join("replays"),
+        None        => { return None; }
     };
-    let next_level_path = match _next_level {
-        Some(path) => Some(asset_path_string(AssetType::Level, path.as_str())),
-        None => None,
-    };
-
-    let _map_data = MapData::load(renderer,
-                                    Path::new(floor_path.as_str()),
-                                    Path::new(wall_path.as_str()),
-                                    Path::new(target_path.as_str()),
-                                    Path::new(box_path.as_str()));
 
-    let mut result_map = Map {
-        name        : _level_name.unwrap().to_string(),
-        level_music : level_music_path,
-        next_level  : next_level_path,
+    let unix_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs()).unwrap_or(0);
+    let path = replays_dir.join(format!("{:016x}-{}.replay", replay.level_hash, unix_time));
 
-        tiles: Vec::new(),
-        tiles_stride: -1,
-
-        map_data: _map_data,
-        boxes: Vec::new(),
-    };
-
-    Map::fill_tiles_and_stride(&mut result_map, Path::new(map_path.as_str()));
-
-    for (box_position_x, box_position_y) in _box_positions.unwrap() {
-        Map::add_box(&mut result_map, _box_sprite_width.unwrap(), _box_sprite_height.unwrap(),
-                     box_position_x, box_position_y);
+    match replay.save(&path) {
+        Ok(())      => Some(path),
+        Err(error)  => {
+            println!("Could not save the replay to {:?}: {}", path, error);
+            None
+        }
     }
-
-    let player = create_player(_player_position.unwrap(), renderer);
-    Some((result_map, player))
 }
 
-fn parse_or_none<T> (s: &str) -> Option<T> where T: std::str::FromStr {
-    let result = match s.parse::<T>() {
-        Ok(value)   => Some(value),
-        Err(_)      => {
-            println!("Error: Could parse {}", s);
-            None
-        },
+fn draw_editor_text(renderer: &mut Renderer, font: &Font, editor: &Editor, message: &Option<String>) {
+    let tool_name = match editor.tool {
+        EditorTool::Paint(tile) => format!("{:?}", tile),
+        EditorTool::Box         => String::from("Box"),
+        EditorTool::Player      => String::from("Player"),
     };
+    let unsaved_mark = if editor.has_unsaved_changes { "*" } else { "" };
 
-    result
-}
+    let title_text = format!("EDITOR{} - Tool: {}", unsaved_mark, tool_name);
+    draw_text(renderer, font, Color::RGB(255, 255, 255), &title_text, Vector2::new(0.02, 0.02), false);
+
+    let help_text = String::from("1-4: tiles  B: box  P: player  Arrows: scroll  Tab: fields  Ctrl+S: save  F5: test  F2: play");
+    draw_text(renderer, font, Color::RGB(200, 200, 200), &help_text, Vector2::new(0.02, 0.07), false);
 
-fn tuple_from_strings<T> (v_0_str: &str, v_1_str: &str) -> Option< (T, T) > where T: std::str::FromStr {
-    let v_0 = parse_or_none::<T>(v_0_str);
-    let v_1 = parse_or_none::<T>(v_1_str);
+    let fields = [EditorField::FileName, EditorField::LevelName, EditorField::LevelMusic, EditorField::NextLevel];
+    for (index, field) in fields.iter().enumerate() {
+        let is_editing = editor.editing_field == Some(*field);
+        let field_text = if is_editing {
+            format!("{}: {}_", field.label(), editor.field_text)
+        } else {
+            format!("{}: {}", field.label(), editor.field_value(*field))
+        };
+        let color = if is_editing { Color::RGB(255, 255, 0) } else { Color::RGB(255, 255, 255) };
 
-    if v_0.is_none() || v_1.is_none() {
-        return None
+        draw_text(renderer, font, color, &field_text, Vector2::new(0.02, 0.74 + 0.05 * index as f32), false);
     }
 
-    Some((v_0.unwrap(), v_1.unwrap()))
+    if let Some(ref message) = *message {
+        draw_text(renderer, font, Color::RGB(255, 128, 0), message, Vector2::new(0.02, 0.94), false);
+    }
 }
 
-fn parse_position_tuple(s: &str) -> Option<(u32, u32)> {
-    // NOTE(erick): This regex is almost identical to the one the the parse_position_tuple_vec function. Any modification here should
-    // be reflected there.
-    // NOTE(erick): Matches:
-    // '(' <any number of white spaces> <an integer number> ',' <any number of white spaces> <an integer number> <any number of white spaces> ')'
-    let tuple_re = Regex::new(r"\(\s*(-?[0-9]+),\s*(-?[0-9]+)\s*\)").unwrap();
+// NOTE(erick): A sprite sheet playing the first clip of an .anim file. The
+// errors are reported and give no sheet.
+fn load_animation_sheet(animation_name: &str, renderer: &Renderer) -> Option<SpriteSheet> {
+    let animation_set = match parse_animation_file(animation_name) {
+        Ok(animation_set)   => animation_set,
+        Err(errors)         => {
+            for error in errors {
+                println!("Error({}): {}", animation_name, error);
+            }
+            return None;
+        }
+    };
 
-    let captures = match tuple_re.captures(s) {
-        Some(cap)   => cap,
+    let first_frame = match animation_set.clips.first().and_then(|clip| clip.frames.first()) {
+        Some(frame) => frame.rect,
         None        => {
-            println!("Error: Could parse {} as a position tuple", s);
+            println!("Error({}): {}", animation_name, AnimationError::NoClips);
             return None;
         }
     };
 
-    let v_0_str = captures.get(1).unwrap().as_str();
-    let v_1_str = captures.get(2).unwrap().as_str();
-
-    let result = tuple_from_strings::<u32>(v_0_str, v_1_str);
+    let mut images = HashMap::new();
+    let sprite = match Sprite::from_image(animation_set.image.as_str(), None, renderer, &mut images) {
+        Ok(sprite)  => sprite,
+        Err(errors) => {
+            for error in errors {
+                println!("Error({}): {}", animation_name, error);
+            }
+            return None;
+        }
+    };
 
-    result
+    Some(sprite.to_sprite_sheet(first_frame.width, first_frame.height, AnimationInfo::new(Some(Rc::new(animation_set)))))
 }
 
-fn parse_position_tuple_vec(s: &str) -> Option<Vec< (u32, u32) > > {
-    let mut result = Vec::new();
-    // TODO(erick): Some unit tests for this regexes would be nice!
-    let tuple_vec_re = Regex::new(
-        r"\{\s*((?:\(\s*(?:-?[0-9]+),\s*(?:-?[0-9]+)\s*\)\s*,\s*)*(?:\(\s*(?:-?[0-9]+),\s*(?:-?[0-9]+)\s*\)))\s*\}")
-        .unwrap();
-
-    // NOTE(erick): This regex is almost identical to the one the the parse_position_tuple function. Any modification here should
-    // be reflected there.
-    let tuple_and_rest_re = Regex::new(r"\s*\(\s*(-?[0-9]+),\s*(-?[0-9]+)\s*\)\s*(?:,\s*(.*))?").unwrap();
-
-    let vec_captures = match tuple_vec_re.captures(s) {
-        Some(cap)   => cap,
-        None        => {
-            println!("Error: Could parse a vector of position tuples: {}", s);
-            return None;
-        }
+fn create_player(player_position: (u32, u32), textures: &LevelTextures, renderer: &Renderer) -> Entity {
+    let animation_sheet = textures.player_animation.as_ref()
+        .and_then(|animation_name| load_animation_sheet(animation_name.as_str(), renderer));
+
+    // NOTE(erick): Without an .anim file (or with a broken one) the lanes are
+    // the rows of the sprite sheet.
+    let mut player_sprite = match animation_sheet {
+        Some(sprite_sheet)  => sprite_sheet,
+        None                => {
+            let mut images = HashMap::new();
+            let sprite = Sprite::load_or_default(textures.player_sprite_sheet.as_str(),
+                                                 LevelTextures::default_textures().player_sprite_sheet.as_str(),
+                                                 renderer, &mut images);
+            let (frame_w, frame_h) = textures.player_sprite_size.unwrap_or((sprite.rect.width(), sprite.rect.height()));
+
+            let animation_set = if textures.player_lanes.is_empty() {
+                None
+            } else {
+                Some(Rc::new(AnimationSet::from_lanes(textures.player_sprite_sheet.as_str(), frame_w, frame_h,
+                                                      textures.player_animation_fps, &textures.player_lanes)))
+            };
+            sprite.to_sprite_sheet(frame_w, frame_h, AnimationInfo::new(animation_set))
+        },
     };
 
-    let mut vec_str =  vec_captures.get(1).unwrap().as_str();
+    let player_clip = match player_sprite.animation_info.animation_set {
+        Some(ref animation_set) => find_player_clip(animation_set, PlayerState::Idle, Direction::Down),
+        None                    => None,
+    };
+    if let Some(clip_index) = player_clip {
+        player_sprite.set_animation_lane_index(clip_index);
+    }
+    // NOTE(erick): The player is drawn with the proportions of its idle frame.
+    let (texture_w, texture_h) = (player_sprite.sprite_width, player_sprite.sprite_height);
 
-    // NOTE(erick): It would be interesting to write an iterator for this loop.
-    loop {
-        let tuple_capture = tuple_and_rest_re.captures(vec_str).unwrap();
-        let tuple_v0_str = tuple_capture.get(1).unwrap().as_str();
-        let tuple_v1_str = tuple_capture.get(2).unwrap().as_str();
+    let player_x = player_position.0 as f32;
+    let player_y = player_position.1 as f32;
+    let player_width_to_height_ratio = texture_w as f32 / texture_h as f32;
 
-        let rest = tuple_capture.get(3);
+    let player_draw_height = 1.2;
+    let player_draw_width = player_draw_height * player_width_to_height_ratio;
 
-        let tuple = tuple_from_strings::<u32>(tuple_v0_str, tuple_v1_str);
-        if tuple.is_none() {
-            println!("Failed to parse tuple vector. Could not parse tuple ({:?}, {:?}).\nAborting.",
-                        tuple_v0_str, tuple_v1_str);
-            return None;
-        } else {
-            result.push(tuple.unwrap());
-        }
+    let player_collision_height = 0.8;
+    let player_colliion_width  = player_draw_width;
 
-        if rest.is_none() {
-            break;
-        } else {
-            vec_str = rest.unwrap().as_str();
-        }
-    }
+    let mut player = Entity::new(player_sprite, Vector2::new(player_x, player_y),
+                        player_colliion_width, player_collision_height,
+                        player_draw_width, player_draw_height);
+    player.center_on_current_tile_rect();
 
-    Some(result)
+    player
 }
 
 // TODO(erick): We should eventually create a tait Draw so we can move this
@@ -1193,8 +2185,11 @@ fn draw_text(renderer: &mut Renderer, font: &Font, color: Color, string: &String
         .blended(color).unwrap();
     let mut text_texture = renderer.create_texture_from_surface(&text_surface).unwrap();
 
-    let text_x = (WINDOW_WIDTH as f32 * position.x) as i32;
-    let text_y = (WINDOW_HEIGHT as f32 * position.y) as i32;
+    // NOTE(erick): position is a fraction of the drawable area, so text stays in
+    // place when the window is resized.
+    let (screen_width, screen_height) = renderer.output_size().unwrap();
+    let text_x = (screen_width as f32 * position.x) as i32;
+    let text_y = (screen_height as f32 * position.y) as i32;
 
 
     let TextureQuery { width: text_width, height: text_height, .. } = text_texture.query();
@@ -0,0 +1,489 @@
+// NOTE(erick): A pack is an ordered collection of levels. The order of the
+// levels replaces the next_level chaining of the .lvl files.
+//
+// We can read:
+//  - SLC XML collections (<SokobanLevels> ... <Level><L>###</L></Level>);
+//  - text files with several XSB levels separated by titles or comments;
+//  - .pack files listing .lvl files in order, with the same 'key = value'
+//  lines as the .lvl files (title, author, description and level = x.lvl);
+//  - a chain of .lvl files linked by next_level.
+
+extern crate regex;
+
+use self::regex::Regex;
+
+use std::path::Path;
+
+use std::io;
+use std::io::Read;
+use std::fs::File;
+
+use std::fmt;
+
+use level::*;
+use xsb::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct PackLevel {
+    pub title   : String,
+    pub author  : Option<String>,
+    pub comment : Option<String>,
+    // NOTE(erick): The .lvl name (without the extension) when the level was
+    // loaded from a .lvl chain. Levels from SLC and text packs have no file.
+    pub file_name : Option<String>,
+
+    pub level   : Level,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct LevelPack {
+    pub title       : String,
+    pub author      : Option<String>,
+    pub description : Option<String>,
+
+    pub levels      : Vec<PackLevel>,
+}
+
+#[derive(Debug)]
+pub enum PackError {
+    Io(String, io::Error),
+    NoLevelCollection,
+    NoLevels,
+    // NOTE(erick): The line numbers of these two refer to the .pack file.
+    MissingEqualsSign { line: u32 },
+    UnknownKey { line: u32, key: String },
+    LevelCycle(String),
+    // NOTE(erick): level is the title of the level, or its .lvl name for
+    // .pack files and chains.
+    BadLevel { level: String, error: LevelError },
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &PackError::Io(ref path, ref error)             => write!(f, "Could not read {}: {}", path, error),
+            &PackError::NoLevelCollection                   => write!(f, "No <LevelCollection>"),
+            &PackError::NoLevels                            => write!(f, "The pack has no levels"),
+            &PackError::MissingEqualsSign { line }          => write!(f, "Line {}: Could not find '=' sign", line),
+            &PackError::UnknownKey { line, ref key }        => write!(f, "Line {}: Unknown key: {}", line, key),
+            &PackError::LevelCycle(ref level)               => write!(f, "The level {} was already added to the pack", level),
+            &PackError::BadLevel { ref level, ref error }   => write!(f, "Level {}: {}", level, error),
+        }
+    }
+}
+
+fn bad_level_errors(level: &str, errors: Vec<LevelError>) -> Vec<PackError> {
+    errors.into_iter().map(|error| PackError::BadLevel { level: level.to_string(), error: error }).collect()
+}
+
+impl LevelPack {
+    fn new(title: &str) -> LevelPack {
+        LevelPack {
+            title       : title.to_string(),
+            author      : None,
+            description : None,
+
+            levels      : Vec::new(),
+        }
+    }
+
+    pub fn next_level_index(&self, index: usize) -> Option<usize> {
+        if index + 1 < self.levels.len() {
+            Some(index + 1)
+        } else {
+            None
+        }
+    }
+
+    fn push_level_file(&mut self, level_name: &str, level: Level) {
+        self.levels.push(PackLevel {
+            title   : level.map.name.clone(),
+            author  : None,
+            comment : None,
+            file_name : Some(level_name.to_string()),
+
+            level   : level,
+        });
+    }
+
+    // NOTE(erick): Follows the next_level links starting at first_level_name
+    // (a .lvl name without the extension, like parse_level expects). A broken
+    // level ends the chain, since we can't know what comes after it.
+    pub fn from_level_chain(title: &str, first_level_name: &str) -> Result<LevelPack, Vec<PackError>> {
+        let mut pack = LevelPack::new(title);
+
+        let mut visited = Vec::new();
+        let mut level_name = Some(first_level_name.to_string());
+
+        while let Some(name) = level_name {
+            if visited.contains(&name) {
+                return Err(vec![PackError::LevelCycle(name)]);
+            }
+
+            let mut level = match parse_level(name.as_str()) {
+                Ok(level)   => level,
+                Err(errors) => { return Err(bad_level_errors(name.as_str(), errors)); }
+            };
+
+            level_name = level.map.next_level.take().map(|next_level_path| {
+                let next_level = remove_asset_path(AssetType::Level, next_level_path.as_str());
+                next_level.trim_end_matches(".lvl").to_string()
+            });
+            visited.push(name.clone());
+
+            pack.push_level_file(name.as_str(), level);
+        }
+
+        Ok(pack)
+    }
+}
+
+pub fn read_pack_file(pack_path: &Path) -> Result<LevelPack, Vec<PackError>> {
+    let mut pack_file = match File::open(pack_path) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![PackError::Io(format!("{:?}", pack_path), error)]); }
+    };
+
+    let mut contents = String::new();
+    if let Err(error) = pack_file.read_to_string(&mut contents) {
+        return Err(vec![PackError::Io(format!("{:?}", pack_path), error)]);
+    }
+
+    let default_title = match pack_path.file_stem() {
+        Some(stem)  => stem.to_string_lossy().into_owned(),
+        None        => String::from("Untitled"),
+    };
+
+    if pack_path.extension().map_or(false, |extension| extension == "pack") {
+        pack_from_level_list(default_title.as_str(), contents.as_str())
+    } else if contents.trim_start().starts_with("<") {
+        pack_from_slc(default_title.as_str(), contents.as_str())
+    } else {
+        pack_from_txt(default_title.as_str(), contents.as_str())
+    }
+}
+
+pub fn pack_from_level_list(default_title: &str, text: &str) -> Result<LevelPack, Vec<PackError>> {
+    let mut pack = LevelPack::new(default_title);
+    let mut errors = Vec::new();
+
+    let mut line_number = 0;
+    for line in text.lines() {
+        line_number += 1;
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        let attrib_index = match line.find('=') {
+            Some(index) => index,
+            None        => {
+                errors.push(PackError::MissingEqualsSign { line: line_number });
+                continue;
+            }
+        };
+
+        let _split = line.split_at(attrib_index);
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        match lhs {
+            "title"         => { pack.title = rhs.to_string(); },
+            "author"        => { pack.author = Some(rhs.to_string()); },
+            "description"   => { pack.description = Some(rhs.to_string()); },
+            "level"         => {
+                let level_name = rhs.trim_end_matches(".lvl");
+                match parse_level(level_name) {
+                    Ok(level)           => { pack.push_level_file(level_name, level); },
+                    Err(level_errors)   => { errors.extend(bad_level_errors(level_name, level_errors)); },
+                }
+            },
+            _               => { errors.push(PackError::UnknownKey { line: line_number, key: lhs.to_string() }); },
+        }
+    }
+
+    if pack.levels.is_empty() && errors.is_empty() {
+        errors.push(PackError::NoLevels);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(pack)
+}
+
+fn decode_xml_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+     .replace("&gt;", ">")
+     .replace("&quot;", "\"")
+     .replace("&apos;", "'")
+     .replace("&amp;", "&")
+}
+
+fn xml_attribute(attributes: &str, name: &str) -> Option<String> {
+    let attribute_re = Regex::new(r#"(\w+)\s*=\s*"([^"]*)""#).unwrap();
+
+    for captures in attribute_re.captures_iter(attributes) {
+        if captures.get(1).unwrap().as_str() == name {
+            return Some(decode_xml_entities(captures.get(2).unwrap().as_str()));
+        }
+    }
+
+    None
+}
+
+fn xml_element_text(xml: &str, name: &str) -> Option<String> {
+    let element_re = Regex::new(format!(r"(?s)<{}(?:\s[^>]*)?>(.*?)</{}>", name, name).as_str()).unwrap();
+
+    element_re.captures(xml)
+        .map(|captures| decode_xml_entities(captures.get(1).unwrap().as_str().trim()))
+}
+
+// NOTE(erick): This is not a real XML parser. It only understands the subset
+// of XML used by SLC files.
+pub fn pack_from_slc(default_title: &str, xml: &str) -> Result<LevelPack, Vec<PackError>> {
+    let collection_re = Regex::new(r"(?s)<LevelCollection([^>]*)>(.*?)</LevelCollection>").unwrap();
+    let level_re = Regex::new(r"(?s)<Level(\s[^>]*)?>(.*?)</Level>").unwrap();
+    let line_re = Regex::new(r"(?s)<L>(.*?)</L>").unwrap();
+
+    let collection = match collection_re.captures(xml) {
+        Some(captures)  => captures,
+        None            => { return Err(vec![PackError::NoLevelCollection]); }
+    };
+    let collection_attributes = collection.get(1).unwrap().as_str();
+    let collection_body = collection.get(2).unwrap().as_str();
+
+    // NOTE(erick): The header is everything before the collection.
+    let header = &xml[..collection.get(0).unwrap().start()];
+
+    let mut pack = LevelPack::new(default_title);
+    if let Some(title) = xml_element_text(header, "Title") {
+        pack.title = title;
+    }
+    pack.description = xml_element_text(header, "Description");
+    pack.author = xml_attribute(collection_attributes, "Copyright");
+
+    let mut errors = Vec::new();
+
+    for level_captures in level_re.captures_iter(collection_body) {
+        let attributes = level_captures.get(1).map_or("", |m| m.as_str());
+        let body = level_captures.get(2).unwrap().as_str();
+
+        let title = match xml_attribute(attributes, "Id") {
+            Some(id)    => id,
+            None        => format!("{}", pack.levels.len() + 1),
+        };
+
+        let lines : Vec<String> = line_re.captures_iter(body)
+            .map(|captures| decode_xml_entities(captures.get(1).unwrap().as_str()))
+            .collect();
+
+        let level = match level_from_xsb(title.as_str(), &lines) {
+            Ok(level)           => level,
+            Err(level_errors)   => {
+                errors.extend(bad_level_errors(title.as_str(), level_errors));
+                continue;
+            }
+        };
+
+        let author = match xml_attribute(attributes, "Copyright") {
+            Some(author)    => Some(author),
+            None            => pack.author.clone(),
+        };
+
+        pack.levels.push(PackLevel {
+            title   : title,
+            author  : author,
+            comment : None,
+            file_name : None,
+
+            level   : level,
+        });
+    }
+
+    if pack.levels.is_empty() && errors.is_empty() {
+        errors.push(PackError::NoLevels);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(pack)
+}
+
+// NOTE(erick): Lines that are not part of a level are either 'Key: value'
+// lines or free text (';' comments included).
+//  - Before the first level, 'Title:', 'Author:' and 'Description:' describe
+//  the pack. The first free text line is used as the title if there is no
+//  'Title:' line.
+//  - After a level, 'Title:', 'Author:' and 'Comment:' describe that level.
+//  - The last free text line before a level is used as its title.
+pub fn pack_from_txt(default_title: &str, text: &str) -> Result<LevelPack, Vec<PackError>> {
+    let mut pack = LevelPack::new(default_title);
+    let mut errors = Vec::new();
+    let mut pack_title = None;
+    let mut first_free_text : Option<String> = None;
+
+    let mut level_lines = Vec::new();
+    let mut pending_title : Option<String> = None;
+
+    // NOTE(erick): The extra empty line flushes the last level.
+    for line in text.lines().chain(Some("").into_iter()) {
+        if is_xsb_line(line) {
+            level_lines.push(line.to_string());
+            continue;
+        }
+
+        if !level_lines.is_empty() {
+            // NOTE(erick): A single free text line before the first level is
+            // the title of the level, not the pack.
+            if pack.levels.is_empty() && first_free_text == pending_title {
+                first_free_text = None;
+            }
+
+            let title = match pending_title.take() {
+                Some(title) => title,
+                None        => format!("{}", pack.levels.len() + 1),
+            };
+
+            match level_from_xsb(title.as_str(), &level_lines) {
+                Ok(level)           => {
+                    pack.levels.push(PackLevel {
+                        title   : title,
+                        author  : None,
+                        comment : None,
+                        file_name : None,
+
+                        level   : level,
+                    });
+                },
+                Err(level_errors)   => { errors.extend(bad_level_errors(title.as_str(), level_errors)); },
+            }
+            level_lines.clear();
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let key_value = line.find(':').map(|index| {
+            let (key, value) = line.split_at(index);
+            (key.trim().to_lowercase(), value[1..].trim().to_string())
+        });
+
+        let is_metadata = match key_value {
+            Some((ref key, _)) => key == "title" || key == "author" || key == "comment" || key == "description",
+            None               => false,
+        };
+
+        if is_metadata {
+            let (key, value) = key_value.unwrap();
+
+            if let Some(pack_level) = pack.levels.last_mut() {
+                match key.as_str() {
+                    "title"     => {
+                        pack_level.title = value.clone();
+                        pack_level.level.map.name = value;
+                    },
+                    "author"    => { pack_level.author = Some(value); },
+                    _           => { pack_level.comment = Some(value); },
+                }
+            } else {
+                match key.as_str() {
+                    "title"     => { pack_title = Some(value); },
+                    "author"    => { pack.author = Some(value); },
+                    _           => { pack.description = Some(value); },
+                }
+            }
+        } else {
+            let free_text = line.trim_start_matches(';').trim().to_string();
+            if free_text.is_empty() {
+                continue;
+            }
+
+            if pack.levels.is_empty() && first_free_text.is_none() {
+                first_free_text = Some(free_text.clone());
+            }
+            pending_title = Some(free_text);
+        }
+    }
+
+    if let Some(title) = pack_title.or(first_free_text) {
+        pack.title = title;
+    }
+
+    if pack.levels.is_empty() && errors.is_empty() {
+        errors.push(PackError::NoLevels);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_txt_packs() {
+        let text = "Title: Tiny\nAuthor: Someone\n\n; First\n#####\n#@$.#\n#####\n\n; Second\n######\n#@ $.#\n######\nComment: Longer\n";
+        let pack = pack_from_txt("default", text).unwrap();
+
+        assert_eq!(pack.title, "Tiny");
+        assert_eq!(pack.author, Some(String::from("Someone")));
+        assert_eq!(pack.levels.len(), 2);
+        assert_eq!(pack.levels[0].title, "First");
+        assert_eq!(pack.levels[1].title, "Second");
+        assert_eq!(pack.levels[1].comment, Some(String::from("Longer")));
+        assert_eq!(pack.next_level_index(0), Some(1));
+        assert_eq!(pack.next_level_index(1), None);
+    }
+
+    #[test]
+    fn reads_slc_packs() {
+        let xml = r#"<?xml version="1.0"?>
+<SokobanLevels>
+  <Title>Tiny &amp; small</Title>
+  <LevelCollection Copyright="Someone">
+    <Level Id="One"><L>#####</L><L>#@$.#</L><L>#####</L></Level>
+  </LevelCollection>
+</SokobanLevels>"#;
+        let pack = pack_from_slc("default", xml).unwrap();
+
+        assert_eq!(pack.title, "Tiny & small");
+        assert_eq!(pack.levels.len(), 1);
+        assert_eq!(pack.levels[0].title, "One");
+        assert_eq!(pack.levels[0].author, Some(String::from("Someone")));
+    }
+
+    #[test]
+    fn reports_every_broken_level() {
+        let text = "; First\n#####\n#@$$#\n#####\n\n; Second\n#####\n#@$.#\n#####\n\n; Third\n#####\n#$.##\n#####\n";
+        let errors = pack_from_txt("default", text).unwrap_err();
+
+        let broken_levels : Vec<&str> = errors.iter().filter_map(|error| match error {
+            &PackError::BadLevel { ref level, .. }  => Some(level.as_str()),
+            _                                       => None,
+        }).collect();
+        assert!(broken_levels.contains(&"First"));
+        assert!(broken_levels.contains(&"Third"));
+        assert!(!broken_levels.contains(&"Second"));
+    }
+
+    #[test]
+    fn built_in_pack_has_every_shipped_level() {
+        let pack = read_pack_file(Path::new("assets/packs/sokoban.pack")).unwrap();
+        let file_names : Vec<String> = pack.levels.iter().filter_map(|pack_level| pack_level.file_name.clone()).collect();
+
+        assert_eq!(file_names, vec!["0-tutorial", "1-starting", "2-for-real", "3-zimbrao"]);
+    }
+}
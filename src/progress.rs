@@ -0,0 +1,276 @@
+// NOTE(erick): What the player has done so far: which levels of each pack are
+// solved, the best scores of each level and the last level played.
+//
+// It is kept in a text file under the XDG data directory. One record per line,
+// fields separated by tabs. The pack title goes last so it can contain
+// anything but a new line:
+//  last_played <level hash> <pack title>
+//  solved      <level hash> <best moves> <best pushes> <best time in ms> <pack title>
+// Levels are identified by Level::identity_hash.
+
+use std::collections::hash_map::HashMap;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+
+use pack::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct LevelRecord {
+    // NOTE(erick): Each one is the best on its own, they may come from
+    // different solutions.
+    pub best_moves   : usize,
+    pub best_pushes  : usize,
+    pub best_time_ms : u32,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Progress {
+    // NOTE(erick): Pack title -> level hash -> record. Only solved levels have
+    // a record.
+    packs       : HashMap<String, HashMap<u64, LevelRecord>>,
+    last_played : Option<(String, u64)>,
+}
+
+// NOTE(erick): $XDG_DATA_HOME/sokoban, falling back to ~/.local/share when
+// XDG_DATA_HOME is not set. Everything we save between runs goes here.
+pub fn data_dir() -> Option<PathBuf> {
+    let data_home = match env::var("XDG_DATA_HOME") {
+        Ok(ref dir) if !dir.is_empty()  => PathBuf::from(dir),
+        _                               => {
+            match env::var("HOME") {
+                Ok(home)    => Path::new(home.as_str()).join(".local").join("share"),
+                Err(_)      => { return None; }
+            }
+        }
+    };
+
+    Some(data_home.join("sokoban"))
+}
+
+pub fn progress_file_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("progress.txt"))
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress {
+            packs       : HashMap::new(),
+            last_played : None,
+        }
+    }
+
+    // NOTE(erick): A missing file is a new player. Lines we can't understand are
+    // reported and skipped, losing one record is better than losing all of them.
+    pub fn load(path: &Path) -> Progress {
+        let mut progress = Progress::new();
+
+        let file = match File::open(path) {
+            Ok(file)    => file,
+            Err(_)      => { return progress; }
+        };
+
+        let mut line_number = 0;
+        for line in BufReader::new(file).lines() {
+            line_number += 1;
+
+            let line = match line {
+                Ok(line)    => line,
+                Err(error)  => {
+                    println!("Could not read {:?}: {}", path, error);
+                    break;
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            if !progress.parse_line(line.as_str()) {
+                println!("{:?} line {}: Could not parse progress record. Skipping it", path, line_number);
+            }
+        }
+
+        progress
+    }
+
+    fn parse_line(&mut self, line: &str) -> bool {
+        // NOTE(erick): The pack title is the last field, whatever comes after
+        // the fields before it is part of the title (tabs included).
+        let fields : Vec<&str> = match line.split('\t').next() {
+            Some("last_played") => line.splitn(3, '\t').collect(),
+            Some("solved")      => line.splitn(6, '\t').collect(),
+            _                   => { return false; }
+        };
+
+        let parse_hash = |field: &str| u64::from_str_radix(field, 16).ok();
+
+        match fields[0] {
+            "last_played" if fields.len() == 3 => {
+                match parse_hash(fields[1]) {
+                    Some(hash)  => { self.last_played = Some((fields[2].to_string(), hash)); true },
+                    None        => false,
+                }
+            },
+            "solved" if fields.len() == 6 => {
+                let hash = parse_hash(fields[1]);
+                let moves = fields[2].parse::<usize>().ok();
+                let pushes = fields[3].parse::<usize>().ok();
+                let time_ms = fields[4].parse::<u32>().ok();
+
+                match (hash, moves, pushes, time_ms) {
+                    (Some(hash), Some(moves), Some(pushes), Some(time_ms)) => {
+                        self.packs.entry(fields[5].to_string())
+                            .or_insert_with(HashMap::new)
+                            .insert(hash, LevelRecord {
+                                best_moves   : moves,
+                                best_pushes  : pushes,
+                                best_time_ms : time_ms,
+                            });
+                        true
+                    },
+                    _ => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // NOTE(erick): Write everything to a temporary file first so a crash
+        // while saving doesn't wipe the progress.
+        let temporary_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temporary_path)?;
+
+            if let Some((ref pack_title, hash)) = self.last_played {
+                write!(file, "last_played\t{:016x}\t{}\n", hash, pack_title)?;
+            }
+
+            // NOTE(erick): Sorted so the file doesn't change when nothing did.
+            let mut pack_titles : Vec<&String> = self.packs.keys().collect();
+            pack_titles.sort();
+            for pack_title in pack_titles {
+                let levels = &self.packs[pack_title];
+                let mut hashes : Vec<&u64> = levels.keys().collect();
+                hashes.sort();
+
+                for hash in hashes {
+                    let record = &levels[hash];
+                    write!(file, "solved\t{:016x}\t{}\t{}\t{}\t{}\n", hash,
+                           record.best_moves, record.best_pushes, record.best_time_ms, pack_title)?;
+                }
+            }
+        }
+
+        fs::rename(&temporary_path, path)
+    }
+
+    pub fn level_record(&self, pack_title: &str, level_hash: u64) -> Option<LevelRecord> {
+        self.packs.get(pack_title).and_then(|levels| levels.get(&level_hash)).cloned()
+    }
+
+    pub fn is_solved(&self, pack_title: &str, level_hash: u64) -> bool {
+        !self.level_record(pack_title, level_hash).is_none()
+    }
+
+    pub fn n_solved(&self, pack: &LevelPack) -> usize {
+        pack.levels.iter()
+            .filter(|pack_level| self.is_solved(pack.title.as_str(), pack_level.level.identity_hash()))
+            .count()
+    }
+
+    // NOTE(erick): Returns true if any of the scores is a new best.
+    pub fn record_solve(&mut self, pack_title: &str, level_hash: u64, moves: usize, pushes: usize, time_ms: u32) -> bool {
+        let levels = self.packs.entry(pack_title.to_string()).or_insert_with(HashMap::new);
+
+        match levels.get_mut(&level_hash) {
+            Some(record)    => {
+                let is_new_best = moves < record.best_moves || pushes < record.best_pushes ||
+                    time_ms < record.best_time_ms;
+
+                record.best_moves = record.best_moves.min(moves);
+                record.best_pushes = record.best_pushes.min(pushes);
+                record.best_time_ms = record.best_time_ms.min(time_ms);
+
+                return is_new_best;
+            },
+            None            => {},
+        }
+
+        levels.insert(level_hash, LevelRecord {
+            best_moves   : moves,
+            best_pushes  : pushes,
+            best_time_ms : time_ms,
+        });
+
+        true
+    }
+
+    pub fn set_last_played(&mut self, pack_title: &str, level_hash: u64) {
+        self.last_played = Some((pack_title.to_string(), level_hash));
+    }
+
+    // NOTE(erick): Where to resume the given pack. None if the last level played
+    // belongs to another pack (or isn't in this one anymore).
+    pub fn last_played_index(&self, pack: &LevelPack) -> Option<usize> {
+        match self.last_played {
+            Some((ref pack_title, hash)) if *pack_title == pack.title => {
+                pack.levels.iter().position(|pack_level| pack_level.level.identity_hash() == hash)
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process;
+
+    #[test]
+    fn keeps_the_best_of_each_score() {
+        let mut progress = Progress::new();
+
+        assert!(progress.record_solve("Pack", 1, 40, 10, 5000));
+        assert!(!progress.record_solve("Pack", 1, 50, 12, 6000));
+        assert!(progress.record_solve("Pack", 1, 45, 9, 7000));
+
+        assert_eq!(progress.level_record("Pack", 1), Some(LevelRecord { best_moves: 40, best_pushes: 9, best_time_ms: 5000 }));
+        assert!(!progress.is_solved("Other pack", 1));
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let path = env::temp_dir().join(format!("sokoban-progress-test-{}.txt", process::id()));
+
+        let mut progress = Progress::new();
+        progress.record_solve("Pack", 0x1234, 40, 10, 5000);
+        progress.record_solve("Title\twith a tab", 0xabcd, 7, 2, 900);
+        progress.set_last_played("Title\twith a tab", 0xabcd);
+        progress.save(&path).unwrap();
+
+        let loaded = Progress::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.level_record("Pack", 0x1234), progress.level_record("Pack", 0x1234));
+        assert_eq!(loaded.level_record("Title\twith a tab", 0xabcd), Some(LevelRecord { best_moves: 7, best_pushes: 2, best_time_ms: 900 }));
+        assert_eq!(loaded.last_played, Some((String::from("Title\twith a tab"), 0xabcd)));
+    }
+}
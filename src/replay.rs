@@ -0,0 +1,369 @@
+// NOTE(erick): A replay is everything needed to play an attempt again: which
+// level it was (Level::identity_hash), the moves in LURD notation and when each
+// one happened, in milliseconds since the attempt started.
+//
+// Replay files use the same 'key = value' lines as the .lvl files:
+//  level_hash = 4f1c0a2b9d3e7f10
+//  lurd = llUUrD
+//  timestamps = 0 180 320 510 700 820
+
+use std::path::Path;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+
+use std::fmt;
+
+use game::*;
+use level::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Replay {
+    pub level_hash    : u64,
+    pub lurd          : String,
+    pub timestamps_ms : Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(String, io::Error),
+    MissingEqualsSign { line: u32 },
+    UnknownKey { line: u32, key: String },
+    MissingKey(&'static str),
+    BadHash { line: u32, value: String },
+    BadTimestamp { line: u32, value: String },
+    BadMove { index: usize, character: char },
+    TimestampCountMismatch { moves: usize, timestamps: usize },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ReplayError::Io(ref path, ref error)                       => write!(f, "Could not read {}: {}", path, error),
+            &ReplayError::MissingEqualsSign { line }                    => write!(f, "Line {}: Could not find '=' sign", line),
+            &ReplayError::UnknownKey { line, ref key }                  => write!(f, "Line {}: Unknown variable: {}", line, key),
+            &ReplayError::MissingKey(key)                               => write!(f, "{} must be specified", key),
+            &ReplayError::BadHash { line, ref value }                   => write!(f, "Line {}: Could not parse {} as a level hash", line, value),
+            &ReplayError::BadTimestamp { line, ref value }              => write!(f, "Line {}: Could not parse {} as a timestamp", line, value),
+            &ReplayError::BadMove { index, character }                  => write!(f, "Move {}: {:?} is not a LURD move", index, character),
+            &ReplayError::TimestampCountMismatch { moves, timestamps }  => write!(f, "There are {} moves and {} timestamps", moves, timestamps),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum ReplayResult {
+    // NOTE(erick): The replay is for another level.
+    WrongLevel,
+    // NOTE(erick): The move at index step is blocked, or the replay says it is
+    // a push (or not) and it isn't.
+    IllegalMove { step: usize, character: char },
+    NotSolved { n_moves: usize, n_pushes: usize },
+    Solved { n_moves: usize, n_pushes: usize },
+}
+
+impl Replay {
+    pub fn new(level_hash: u64) -> Replay {
+        Replay {
+            level_hash    : level_hash,
+            lurd          : String::new(),
+            timestamps_ms : Vec::new(),
+        }
+    }
+
+    pub fn n_steps(&self) -> usize {
+        self.timestamps_ms.len()
+    }
+
+    // NOTE(erick): The replay follows the board history, so undone moves are not
+    // part of it. Call it after every change to the board, moves that are
+    // already recorded keep their timestamps and new ones get time_ms.
+    pub fn sync_with_board(&mut self, board: &Board, time_ms: u32) {
+        let n_steps = board.history.len();
+
+        self.timestamps_ms.truncate(n_steps);
+        while self.timestamps_ms.len() < n_steps {
+            self.timestamps_ms.push(time_ms);
+        }
+
+        self.lurd = board.history.iter()
+            .map(|entry| entry.direction.to_lurd(entry.is_push()))
+            .collect();
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let timestamps : Vec<String> = self.timestamps_ms.iter().map(|time| time.to_string()).collect();
+
+        let mut file = File::create(path)?;
+        write!(file, "level_hash = {:016x}\n", self.level_hash)?;
+        write!(file, "lurd = {}\n", self.lurd)?;
+        write!(file, "timestamps = {}\n", timestamps.join(" "))?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, Vec<ReplayError>> {
+        let path_string = path.to_string_lossy().to_string();
+        let file = match File::open(path) {
+            Ok(file)    => file,
+            Err(error)  => { return Err(vec![ReplayError::Io(path_string, error)]); }
+        };
+
+        let mut errors = Vec::new();
+        let mut level_hash = None;
+        let mut lurd = None;
+        let mut timestamps = None;
+
+        let mut line_number = 0;
+        for line in BufReader::new(file).lines() {
+            line_number += 1;
+
+            let line = match line {
+                Ok(line)    => line,
+                Err(error)  => {
+                    errors.push(ReplayError::Io(path_string.clone(), error));
+                    break;
+                }
+            };
+            if line == "" || line.starts_with("//") {
+                continue;
+            }
+
+            let attrib_index = match line.find('=') {
+                Some(index) => index,
+                None        => {
+                    errors.push(ReplayError::MissingEqualsSign { line: line_number });
+                    continue;
+                }
+            };
+
+            let _split = line.split_at(attrib_index);
+            let lhs = (_split.0).trim();
+            let rhs = (_split.1)[1..].trim();
+
+            match lhs {
+                "level_hash"    => {
+                    match u64::from_str_radix(rhs, 16) {
+                        Ok(hash)    => { level_hash = Some(hash); },
+                        Err(_)      => { errors.push(ReplayError::BadHash { line: line_number, value: rhs.to_string() }); },
+                    }
+                },
+                "lurd"          => { lurd = Some(rhs.to_string()); },
+                "timestamps"    => {
+                    let mut values = Vec::new();
+                    for value in rhs.split_whitespace() {
+                        match value.parse::<u32>() {
+                            Ok(time)    => { values.push(time); },
+                            Err(_)      => { errors.push(ReplayError::BadTimestamp { line: line_number, value: value.to_string() }); },
+                        }
+                    }
+                    timestamps = Some(values);
+                },
+                _               => { errors.push(ReplayError::UnknownKey { line: line_number, key: lhs.to_string() }); },
+            }
+        }
+
+        if level_hash.is_none() { errors.push(ReplayError::MissingKey("level_hash")); }
+        if lurd.is_none()       { errors.push(ReplayError::MissingKey("lurd")); }
+        if timestamps.is_none() { errors.push(ReplayError::MissingKey("timestamps")); }
+
+        if let Some(ref lurd) = lurd {
+            for (index, character) in lurd.chars().enumerate() {
+                if Direction::from_lurd(character).is_none() {
+                    errors.push(ReplayError::BadMove { index: index, character: character });
+                }
+            }
+
+            if let Some(ref timestamps) = timestamps {
+                let n_moves = lurd.chars().count();
+                if n_moves != timestamps.len() {
+                    errors.push(ReplayError::TimestampCountMismatch { moves: n_moves, timestamps: timestamps.len() });
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Replay {
+            level_hash    : level_hash.unwrap(),
+            lurd          : lurd.unwrap(),
+            timestamps_ms : timestamps.unwrap(),
+        })
+    }
+}
+
+// NOTE(erick): Plays the replay on a fresh board, no SDL involved. This is what
+// we use to check that a solution still works after the rules change.
+pub fn verify_replay(replay: &Replay, level: &Level) -> ReplayResult {
+    if replay.level_hash != level.identity_hash() {
+        return ReplayResult::WrongLevel;
+    }
+
+    let mut board = Board::new(level.map.clone(), level.player_position);
+
+    for (step, character) in replay.lurd.chars().enumerate() {
+        let (direction, is_push) = match Direction::from_lurd(character) {
+            Some(step)  => step,
+            None        => { return ReplayResult::IllegalMove { step: step, character: character }; }
+        };
+
+        let is_legal = match board.try_move(direction) {
+            MoveResult::Blocked     => false,
+            MoveResult::Moved       => !is_push,
+            MoveResult::Pushed(_)   => is_push,
+        };
+        if !is_legal {
+            return ReplayResult::IllegalMove { step: step, character: character };
+        }
+    }
+
+    if board.is_solved() {
+        ReplayResult::Solved { n_moves: board.n_moves(), n_pushes: board.n_pushes() }
+    } else {
+        ReplayResult::NotSolved { n_moves: board.n_moves(), n_pushes: board.n_pushes() }
+    }
+}
+
+// NOTE(erick): Hands out the moves of a replay as their time comes. speed
+// scales the replay clock, 2.0 plays twice as fast.
+#[derive(Debug)]
+pub struct ReplayPlayback {
+    moves     : Vec<Direction>,
+    timestamps_ms : Vec<u32>,
+
+    next_step : usize,
+    time_ms   : f32,
+
+    pub speed     : f32,
+    pub is_paused : bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: &Replay) -> ReplayPlayback {
+        ReplayPlayback {
+            moves     : replay.lurd.chars()
+                .filter_map(|character| Direction::from_lurd(character))
+                .map(|(direction, _)| direction)
+                .collect(),
+            timestamps_ms : replay.timestamps_ms.clone(),
+
+            next_step : 0,
+            time_ms   : 0.0,
+
+            speed     : 1.0,
+            is_paused : false,
+        }
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if !self.is_paused {
+            self.time_ms += dt * 1000.0 * self.speed;
+        }
+    }
+
+    // NOTE(erick): The next move if its time has come. The caller asks once the
+    // previous move is done, so moves are never skipped, only delayed.
+    pub fn next_move(&mut self) -> Option<Direction> {
+        if self.is_finished() || self.is_paused {
+            return None;
+        }
+
+        if (self.timestamps_ms[self.next_step] as f32) > self.time_ms {
+            return None;
+        }
+
+        let direction = self.moves[self.next_step];
+        self.next_step += 1;
+
+        Some(direction)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_step >= self.moves.len()
+    }
+
+    pub fn restart(&mut self) {
+        self.next_step = 0;
+        self.time_ms = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use xsb::*;
+
+    fn level() -> Level {
+        let lines : Vec<String> = ["######", "#@ $.#", "######"].iter().map(|line| line.to_string()).collect();
+        level_from_xsb("test", &lines).unwrap()
+    }
+
+    #[test]
+    fn recorded_attempts_verify() {
+        let level = level();
+        let mut board = Board::new(level.map.clone(), level.player_position);
+        let mut replay = Replay::new(level.identity_hash());
+
+        board.try_move(Direction::Right);
+        replay.sync_with_board(&board, 100);
+        board.try_move(Direction::Left);
+        replay.sync_with_board(&board, 200);
+        board.undo();
+        replay.sync_with_board(&board, 300);
+        board.try_move(Direction::Right);
+        replay.sync_with_board(&board, 400);
+
+        assert_eq!(replay.lurd, "rR");
+        assert_eq!(replay.timestamps_ms, vec![100, 400]);
+        assert_eq!(verify_replay(&replay, &level), ReplayResult::Solved { n_moves: 2, n_pushes: 1 });
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let path = env::temp_dir().join(format!("sokoban-replay-test-{}.replay", process::id()));
+
+        let mut replay = Replay::new(level().identity_hash());
+        replay.lurd = String::from("rR");
+        replay.timestamps_ms = vec![0, 180];
+        replay.save(&path).unwrap();
+
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), replay);
+    }
+
+    #[test]
+    fn reports_bad_replays() {
+        let level = level();
+
+        let mut replay = Replay::new(level.identity_hash());
+        replay.lurd = String::from("rr");
+        assert_eq!(verify_replay(&replay, &level), ReplayResult::IllegalMove { step: 1, character: 'r' });
+
+        replay.lurd = String::from("r");
+        assert_eq!(verify_replay(&replay, &level), ReplayResult::NotSolved { n_moves: 1, n_pushes: 0 });
+
+        replay.level_hash += 1;
+        assert_eq!(verify_replay(&replay, &level), ReplayResult::WrongLevel);
+    }
+}
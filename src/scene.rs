@@ -0,0 +1,229 @@
+// NOTE(erick): The frontend is a stack of scenes. Only the scene on top gets
+// input, but the ones below it are still drawn, that is how the pause menu
+// shows the level behind it. Menus only keep which item is selected, what the
+// items do is up to the frontend.
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct LevelStats {
+    pub n_moves     : usize,
+    pub n_pushes    : usize,
+    pub time_ms     : u32,
+    pub is_new_best : bool,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum MenuItem {
+    Continue,
+    Levels,
+    Options,
+    Quit,
+
+    Resume,
+    Restart,
+    Undo,
+    QuitToTitle,
+
+    FrameTime,
+    DeadlockWarnings,
+    Fullscreen,
+    IntegerScale,
+    Theme,
+    MasterVolume,
+    MusicVolume,
+    EffectsVolume,
+    Mute,
+    Back,
+}
+
+impl MenuItem {
+    pub fn label(&self) -> &'static str {
+        match *self {
+            MenuItem::Continue          => "Continue",
+            MenuItem::Levels            => "Select level",
+            MenuItem::Options           => "Options",
+            MenuItem::Quit              => "Quit",
+
+            MenuItem::Resume            => "Resume",
+            MenuItem::Restart           => "Restart",
+            MenuItem::Undo              => "Undo",
+            MenuItem::QuitToTitle       => "Quit to title",
+
+            MenuItem::FrameTime         => "Frame time",
+            MenuItem::DeadlockWarnings  => "Deadlock warnings",
+            MenuItem::Fullscreen        => "Fullscreen",
+            MenuItem::IntegerScale      => "Integer scaling",
+            MenuItem::Theme             => "Theme",
+            MenuItem::MasterVolume      => "Volume",
+            MenuItem::MusicVolume       => "Music",
+            MenuItem::EffectsVolume     => "Sound effects",
+            MenuItem::Mute              => "Mute",
+            MenuItem::Back              => "Back",
+        }
+    }
+}
+
+pub const TITLE_MENU   : [MenuItem; 4] = [MenuItem::Continue, MenuItem::Levels, MenuItem::Options, MenuItem::Quit];
+pub const PAUSE_MENU   : [MenuItem; 5] = [MenuItem::Resume, MenuItem::Restart, MenuItem::Undo, MenuItem::Options, MenuItem::QuitToTitle];
+pub const OPTIONS_MENU : [MenuItem; 10] = [MenuItem::FrameTime, MenuItem::DeadlockWarnings, MenuItem::Fullscreen,
+                                           MenuItem::IntegerScale, MenuItem::Theme, MenuItem::MasterVolume,
+                                           MenuItem::MusicVolume, MenuItem::EffectsVolume, MenuItem::Mute, MenuItem::Back];
+
+// NOTE(erick): A vertical list. The selection wraps around.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct Menu {
+    pub selected : usize,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu {
+            selected : 0,
+        }
+    }
+
+    pub fn move_selection(&mut self, delta: i32, n_items: usize) {
+        if n_items == 0 {
+            return;
+        }
+
+        let n_items = n_items as i32;
+        self.selected = ((self.selected as i32 + delta) % n_items + n_items) as usize % n_items as usize;
+    }
+}
+
+// NOTE(erick): The levels of a pack laid out in rows of n_columns. The
+// selection stops at the edges.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct LevelGrid {
+    pub pack_index : usize,
+    pub selected   : usize,
+    pub n_columns  : usize,
+}
+
+impl LevelGrid {
+    pub fn new(pack_index: usize, selected: usize) -> LevelGrid {
+        LevelGrid {
+            pack_index : pack_index,
+            selected   : selected,
+            n_columns  : 5,
+        }
+    }
+
+    // NOTE(erick): dy is positive going down, like the rows on the screen.
+    pub fn move_selection(&mut self, dx: i32, dy: i32, n_levels: usize) {
+        if n_levels == 0 {
+            return;
+        }
+
+        let column = (self.selected % self.n_columns) as i32 + dx;
+        let row = (self.selected / self.n_columns) as i32 + dy;
+        if column < 0 || column >= self.n_columns as i32 || row < 0 {
+            return;
+        }
+
+        let selected = row as usize * self.n_columns + column as usize;
+        if selected < n_levels {
+            self.selected = selected;
+        }
+    }
+
+    pub fn row(&self) -> usize {
+        self.selected / self.n_columns
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Scene {
+    Title(Menu),
+    PackSelect(Menu),
+    LevelSelect(LevelGrid),
+
+    Play,
+    Edit,
+    // NOTE(erick): Playing the level being edited. Winning (or F5) goes back
+    // to the editor.
+    TestPlay,
+    // NOTE(erick): Watching a replay given in the command line.
+    Replay,
+    LevelComplete(LevelStats),
+    // NOTE(erick): The last level of the pack was solved.
+    PackComplete,
+
+    Pause(Menu),
+    Options(Menu),
+}
+
+impl Scene {
+    // NOTE(erick): Whether the board takes input and the sprites move.
+    pub fn is_playing(&self) -> bool {
+        match *self {
+            Scene::Play | Scene::TestPlay | Scene::Replay   => true,
+            _                                               => false,
+        }
+    }
+
+    // NOTE(erick): Whether the level is drawn when this scene is anywhere in
+    // the stack.
+    pub fn shows_board(&self) -> bool {
+        match *self {
+            Scene::Play | Scene::TestPlay | Scene::Replay |
+            Scene::LevelComplete(_) | Scene::PackComplete  => true,
+            _                                               => false,
+        }
+    }
+
+    pub fn menu_items(&self) -> &'static [MenuItem] {
+        match *self {
+            Scene::Title(_)     => &TITLE_MENU,
+            Scene::Pause(_)     => &PAUSE_MENU,
+            Scene::Options(_)   => &OPTIONS_MENU,
+            _                   => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_selection_wraps_around() {
+        let mut menu = Menu::new();
+
+        menu.move_selection(-1, PAUSE_MENU.len());
+        assert_eq!(menu.selected, PAUSE_MENU.len() - 1);
+        menu.move_selection(1, PAUSE_MENU.len());
+        assert_eq!(menu.selected, 0);
+    }
+
+    #[test]
+    fn level_grid_selection_stops_at_the_edges() {
+        // NOTE(erick): 12 levels: two full rows and two levels on the last one.
+        let mut grid = LevelGrid::new(0, 0);
+
+        grid.move_selection(-1, 0, 12);
+        assert_eq!(grid.selected, 0);
+        grid.move_selection(0, 2, 12);
+        assert_eq!(grid.selected, 10);
+        grid.move_selection(4, 0, 12);
+        assert_eq!(grid.selected, 10);
+        grid.move_selection(1, 0, 12);
+        assert_eq!(grid.selected, 11);
+        assert_eq!(grid.row(), 2);
+    }
+}
@@ -5,17 +5,34 @@ use self::sdl2::render::Renderer;
 
 use self::sdl2::keyboard::Keycode;
 
+use self::sdl2::image::LoadSurface;
+
 use self::sdl2::mixer::{INIT_MP3, INIT_FLAC, INIT_MOD, INIT_FLUIDSYNTH, INIT_MODPLUG, INIT_OGG,
                     AUDIO_S16LSB};
-use self::sdl2::mixer::Music;
+use self::sdl2::mixer::{Chunk, Channel, MAX_VOLUME};
 use self::sdl2::Sdl;
 
 use self::sdl2::GameControllerSubsystem;
 use self::sdl2::controller::GameController;
 
 use std::collections::HashSet;
+use std::collections::hash_map::HashMap;
 use std::path::Path;
 
+use sound::*;
+use audio_settings::*;
+
+// NOTE(erick): How many sound effects can play at the same time.
+const SOUND_EFFECT_CHANNELS : i32 = 8;
+
+// NOTE(erick): The first channels are reserved for the music, so sound effects
+// never take them. Two of them so the old track can fade out on one while the
+// new one fades in on the other.
+const MUSIC_CHANNELS : i32 = 2;
+
+// NOTE(erick): How long the crossfade takes when the music changes.
+const MUSIC_FADE_MS : i32 = 800;
+
 
 pub struct AudioMixer {
     pub frequency: i32,
@@ -28,7 +45,8 @@ pub struct AudioMixer {
 }
 
 impl AudioMixer {
-    pub fn new(sdl: &Sdl) -> AudioMixer {
+    // NOTE(erick): Fails when there is no audio device to open.
+    pub fn new(sdl: &Sdl) -> Result<AudioMixer, String> {
         let mut mixer = AudioMixer {
             frequency: 44100,
             format: AUDIO_S16LSB,
@@ -38,30 +56,242 @@ impl AudioMixer {
             mixer_context: None
         };
 
-        let _audio = sdl.audio().unwrap();
+        let _audio = sdl.audio()?;
         mixer.audio = Some(_audio);
-        let _mixer_context = sdl2::mixer::init(INIT_MP3 | INIT_FLAC | INIT_MOD | INIT_FLUIDSYNTH |
-                                               INIT_MODPLUG |
-                                               INIT_OGG)
-                                               .unwrap();
+        // NOTE(erick): Missing codecs only mean some files won't play, WAV
+        // always works.
+        match sdl2::mixer::init(INIT_MP3 | INIT_FLAC | INIT_MOD | INIT_FLUIDSYNTH | INIT_MODPLUG | INIT_OGG) {
+            Ok(_mixer_context)  => { mixer.mixer_context = Some(_mixer_context); },
+            Err(error)          => { println!("Some audio formats are not available: {}", error); },
+        }
+
+        sdl2::mixer::open_audio(mixer.frequency, mixer.format, mixer.channels, mixer.chunk_size)?;
+        sdl2::mixer::allocate_channels(MUSIC_CHANNELS + SOUND_EFFECT_CHANNELS);
+        sdl2::mixer::reserve_channels(MUSIC_CHANNELS);
+
+        Ok(mixer)
+    }
+}
+
+// NOTE(erick): Everything the game plays goes through here. Without an audio
+// device (or with --no-audio) this is the null backend: nothing is heard, but
+// what would have played goes to an AudioLog (see the sound module).
+pub struct Audio {
+    // NOTE(erick): None for the null backend.
+    mixer      : Option<AudioMixer>,
+    sound_bank : SoundBank,
+    music      : MusicManager,
+
+    pub log    : AudioLog,
+}
+
+impl Audio {
+    pub fn new(sdl: &Sdl, enabled: bool) -> Audio {
+        if !enabled {
+            println!("Audio is disabled, using the null audio backend");
+            return Audio::null();
+        }
+
+        match AudioMixer::new(sdl) {
+            Ok(mixer)   => {
+                let mut audio = Audio::null();
+                audio.mixer = Some(mixer);
+                audio
+            },
+            Err(error)  => {
+                println!("Could not open the audio device ({}), using the null audio backend", error);
+                Audio::null()
+            },
+        }
+    }
+
+    pub fn null() -> Audio {
+        Audio {
+            mixer      : None,
+            sound_bank : SoundBank::empty(),
+            music      : MusicManager::new(),
+
+            log        : AudioLog::new(),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.mixer.is_none()
+    }
+
+    pub fn set_sound_effects(&mut self, sound_registry: Option<SoundRegistry>) {
+        self.sound_bank = match (self.is_null(), sound_registry) {
+            (false, Some(ref registry)) => SoundBank::load(registry),
+            _                           => SoundBank::empty(),
+        };
+    }
+
+    pub fn play_effect(&mut self, effect: SoundEffect) {
+        if !self.is_null() {
+            self.sound_bank.play(effect);
+            return;
+        }
+
+        self.log.play_effect(effect);
+    }
 
-        mixer.mixer_context = Some(_mixer_context);
+    // NOTE(erick): music_path has the asset path, like Map::level_music.
+    pub fn play_music(&mut self, music_path: Option<&String>) {
+        if !self.is_null() {
+            self.music.play(music_path);
+            return;
+        }
 
-        sdl2::mixer::open_audio(mixer.frequency, mixer.format, mixer.channels, mixer.chunk_size).unwrap();
-        sdl2::mixer::allocate_channels(0);
+        self.log.play_music(music_path);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !self.is_null() {
+            self.music.update(dt);
+        }
+    }
 
-        mixer
+    pub fn set_volumes(&self, audio_settings: &AudioSettings) {
+        if !self.is_null() {
+            self.music.set_volume(audio_settings.music_gain());
+            set_effects_volume(audio_settings.effects_gain());
+        }
     }
 }
 
-pub fn play_music<'a> (filename: &Path) -> Music<'a> {
-    let music: Music = sdl2::mixer::Music::from_file(filename).unwrap();
-    // NOTE(erick): -1 loops forever.
-    if !music.play(-1).is_ok() {
-        println!("Could not play file: {:?}", filename);
+// NOTE(erick): A track and the music channel it plays on. The chunk has to
+// live for as long as the channel plays it.
+struct MusicTrack {
+    _chunk  : Chunk,
+    channel : Channel,
+}
+
+// NOTE(erick): Owns the music that is playing. Tracks are loaded whole as
+// chunks and looped on the music channels, so when the music changes the old
+// track keeps playing (and fading out) on its channel while the new one fades
+// in on the other. Missing files and codecs are reported and leave the game
+// quiet.
+struct MusicManager {
+    current_path  : Option<String>,
+    current       : Option<MusicTrack>,
+    fading_out    : Option<MusicTrack>,
+    fade_out_left : f32,
+}
+
+impl MusicManager {
+    pub fn new() -> MusicManager {
+        MusicManager {
+            current_path  : None,
+            current       : None,
+            fading_out    : None,
+            fade_out_left : 0.0,
+        }
+    }
+
+    // NOTE(erick): music_path has the asset path, like Map::level_music. The
+    // track that is already playing is not restarted, so levels sharing it
+    // don't interrupt it.
+    pub fn play(&mut self, music_path: Option<&String>) {
+        let music_path = music_path.cloned();
+        if self.current_path == music_path {
+            return;
+        }
+
+        // NOTE(erick): A track that was still fading out is cut short, its
+        // channel is the one the new track gets.
+        if let Some(track) = self.fading_out.take() {
+            track.channel.halt();
+        }
+
+        let mut channel = Channel(0);
+        if let Some(track) = self.current.take() {
+            track.channel.fade_out(MUSIC_FADE_MS);
+            channel = Channel(1 - track.channel.0);
+
+            self.fading_out = Some(track);
+            self.fade_out_left = MUSIC_FADE_MS as f32 / 1000.0;
+        }
+
+        if let Some(ref path) = music_path {
+            match Chunk::from_file(Path::new(path.as_str())) {
+                Ok(chunk)   => {
+                    // NOTE(erick): -1 loops forever.
+                    match channel.fade_in(&chunk, -1, MUSIC_FADE_MS) {
+                        Ok(_)       => {
+                            self.current = Some(MusicTrack {
+                                _chunk  : chunk,
+                                channel : channel,
+                            });
+                        },
+                        Err(error)  => { println!("Could not play {}: {}", path, error); },
+                    }
+                },
+                Err(error)  => { println!("Could not load music {}: {}", path, error); },
+            }
+        }
+        self.current_path = music_path;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.fading_out.is_none() {
+            return;
+        }
+
+        self.fade_out_left -= dt;
+        if self.fade_out_left <= 0.0 {
+            let track = self.fading_out.take().unwrap();
+            track.channel.halt();
+        }
     }
 
-    music
+    // NOTE(erick): gain goes from 0 to 1.
+    pub fn set_volume(&self, gain: f32) {
+        for channel in 0..MUSIC_CHANNELS {
+            Channel(channel).set_volume((gain * MAX_VOLUME as f32) as i32);
+        }
+    }
+}
+
+// NOTE(erick): For every sound effect channel, gain goes from 0 to 1.
+fn set_effects_volume(gain: f32) {
+    for channel in MUSIC_CHANNELS..MUSIC_CHANNELS + SOUND_EFFECT_CHANNELS {
+        Channel(channel).set_volume((gain * MAX_VOLUME as f32) as i32);
+    }
+}
+
+// NOTE(erick): The sounds of a SoundRegistry, loaded. Sounds that can't be
+// loaded are reported and stay silent.
+struct SoundBank {
+    chunks : HashMap<SoundEffect, Chunk>,
+}
+
+impl SoundBank {
+    pub fn empty() -> SoundBank {
+        SoundBank {
+            chunks : HashMap::new(),
+        }
+    }
+
+    pub fn load(registry: &SoundRegistry) -> SoundBank {
+        let mut chunks = HashMap::new();
+        for (effect, sound_path) in &registry.sounds {
+            match Chunk::from_file(Path::new(sound_path.as_str())) {
+                Ok(chunk)   => { chunks.insert(*effect, chunk); },
+                Err(error)  => { println!("Could not load sound {}: {}", sound_path, error); },
+            }
+        }
+
+        SoundBank {
+            chunks : chunks,
+        }
+    }
+
+    pub fn play(&self, effect: SoundEffect) {
+        if let Some(chunk) = self.chunks.get(&effect) {
+            // NOTE(erick): When every channel is busy the sound is skipped.
+            let _ = Channel::all().play(chunk, 0);
+        }
+    }
 }
 
 pub fn find_sdl_gl_driver() -> Option<u32> {
@@ -109,12 +339,20 @@ pub fn init_controller(game_controller_subsystem : &GameControllerSubsystem) ->
     controller
 }
 
-pub fn texture_from_path(path: &Path, renderer: &Renderer) -> (Texture, u32, u32) {
-    let temp_surface = sdl2::surface::Surface::load_bmp(path).unwrap();
+// NOTE(erick): Anything SDL_image can read: BMP, PNG and JPG at least. The
+// image context has to be initialized for PNG and JPG.
+pub fn texture_from_path(path: &Path, renderer: &Renderer) -> Result<(Texture, u32, u32), String> {
+    let temp_surface = match sdl2::surface::Surface::from_file(path) {
+        Ok(surface) => surface,
+        Err(error)  => { return Err(format!("Could not load {:?}: {}", path, error)); }
+    };
 
-    let texture = renderer.create_texture_from_surface(&temp_surface).unwrap();
+    let texture = match renderer.create_texture_from_surface(&temp_surface) {
+        Ok(texture) => texture,
+        Err(error)  => { return Err(format!("Could not create a texture from {:?}: {:?}", path, error)); }
+    };
 
-    (texture, temp_surface.width(), temp_surface.height())
+    Ok((texture, temp_surface.width(), temp_surface.height()))
 }
 
 pub fn pressed_keycode_set(e: &sdl2::EventPump) -> HashSet<Keycode> {
@@ -0,0 +1,591 @@
+// NOTE(erick): A* solver over the logical grid.
+//
+// The search works on pushes: a node is the position of every box plus the
+// area the player can walk to. Walking between two pushes is only
+// reconstructed at the end, when we build the LURD string.
+//
+// The heuristic is the minimum cost matching between boxes and targets where
+// the cost is the number of pushes a box needs to reach a target if it were
+// alone in the map. It never overestimates both the number of pushes and the
+// number of moves.
+
+use std::collections::BinaryHeap;
+use std::collections::hash_map::HashMap;
+use std::cmp::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering as AtomicOrdering;
+
+use game::*;
+
+const INFINITY : u32 = ::std::u32::MAX / 4;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Objective {
+    Pushes,
+    Moves,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SolverOptions {
+    pub objective  : Objective,
+    pub max_nodes  : usize,
+    pub time_limit : Option<Duration>,
+    // NOTE(erick): Lets another thread stop the search.
+    pub cancel     : Option<Arc<AtomicBool>>,
+}
+
+impl SolverOptions {
+    pub fn new(objective: Objective) -> SolverOptions {
+        SolverOptions {
+            objective  : objective,
+            max_nodes  : 1000000,
+            time_limit : Some(Duration::from_secs(10)),
+            cancel     : None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Solution {
+    pub lurd           : String,
+    pub n_moves        : usize,
+    pub n_pushes       : usize,
+    pub nodes_explored : usize,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum SolverResult {
+    Solved(Solution),
+    Unsolvable,
+    // NOTE(erick): We ran out of nodes or time before finding a solution.
+    BudgetExceeded,
+    Cancelled,
+}
+
+// NOTE(erick): The parts of the map that never change during the search.
+// Cells are indexed as y * n_cols + x (right-handed, like TilePosition).
+struct StaticGrid {
+    n_cols      : usize,
+    n_lines     : usize,
+
+    walkable    : Vec<bool>,
+    targets     : Vec<usize>,
+    // NOTE(erick): push_distances[target_index][cell] is the number of pushes a
+    // box alone in the map needs to go from cell to the target.
+    push_distances : Vec<Vec<u32>>,
+}
+
+impl StaticGrid {
+    fn new(map: &Map) -> StaticGrid {
+        let n_cols = map.n_cols() as usize;
+        let n_lines = map.n_lines() as usize;
+
+        let mut walkable = vec![false; n_cols * n_lines];
+        let mut targets = Vec::new();
+
+        for y in 0..n_lines {
+            for x in 0..n_cols {
+                let tile = map.tile_at(x as u32, y as u32);
+                walkable[y * n_cols + x] = tile.is_walkable();
+                if let TileType::Target = tile {
+                    targets.push(y * n_cols + x);
+                }
+            }
+        }
+
+        let mut grid = StaticGrid {
+            n_cols      : n_cols,
+            n_lines     : n_lines,
+
+            walkable    : walkable,
+            targets     : targets,
+            push_distances : Vec::new(),
+        };
+
+        for target_index in 0..grid.targets.len() {
+            let target = grid.targets[target_index];
+            let distances = grid.pull_distances(target);
+            grid.push_distances.push(distances);
+        }
+
+        grid
+    }
+
+    fn cell(&self, position: TilePosition) -> usize {
+        position.1 as usize * self.n_cols + position.0 as usize
+    }
+
+    fn neighbour(&self, cell: usize, direction: Direction) -> Option<usize> {
+        let x = (cell % self.n_cols) as i32;
+        let y = (cell / self.n_cols) as i32;
+        let (dx, dy) = direction.offset();
+        let (next_x, next_y) = (x + dx, y + dy);
+
+        if next_x < 0 || next_y < 0 || next_x >= self.n_cols as i32 || next_y >= self.n_lines as i32 {
+            None
+        } else {
+            Some(next_y as usize * self.n_cols + next_x as usize)
+        }
+    }
+
+    fn walkable_neighbour(&self, cell: usize, direction: Direction) -> Option<usize> {
+        match self.neighbour(cell, direction) {
+            Some(next) if self.walkable[next] => Some(next),
+            _                                 => None,
+        }
+    }
+
+    // NOTE(erick): Breadth-first search pulling a box away from the target.
+    // A box can be pulled from cell to cell + d if the player has room to
+    // stand on cell + 2d.
+    fn pull_distances(&self, target: usize) -> Vec<u32> {
+        let mut distances = vec![INFINITY; self.walkable.len()];
+        let mut queue = ::std::collections::VecDeque::new();
+
+        distances[target] = 0;
+        queue.push_back(target);
+
+        while let Some(cell) = queue.pop_front() {
+            for direction in Direction::all().iter() {
+                let box_cell = match self.walkable_neighbour(cell, *direction) {
+                    Some(next)  => next,
+                    None        => continue,
+                };
+                if self.walkable_neighbour(box_cell, *direction).is_none() {
+                    continue;
+                }
+
+                if distances[box_cell] == INFINITY {
+                    distances[box_cell] = distances[cell] + 1;
+                    queue.push_back(box_cell);
+                }
+            }
+        }
+
+        distances
+    }
+
+    // NOTE(erick): A cell from where a box can never reach any target.
+    fn is_dead(&self, cell: usize) -> bool {
+        self.push_distances.iter().all(|distances| distances[cell] == INFINITY)
+    }
+
+    // NOTE(erick): Minimum cost assignment of boxes to targets (Hungarian
+    // algorithm). Returns INFINITY if some box can't be matched.
+    fn heuristic(&self, boxes: &[usize]) -> u32 {
+        let n = boxes.len();
+        let m = self.targets.len();
+        if n == 0 {
+            return 0;
+        }
+        if n > m {
+            return INFINITY;
+        }
+
+        let cost = |i: usize, j: usize| -> i64 { self.push_distances[j - 1][boxes[i - 1]] as i64 };
+
+        // NOTE(erick): 1-based arrays, p[j] is the box assigned to target j.
+        let mut u = vec![0i64; n + 1];
+        let mut v = vec![0i64; m + 1];
+        let mut p = vec![0usize; m + 1];
+        let mut way = vec![0usize; m + 1];
+
+        for i in 1..(n + 1) {
+            p[0] = i;
+            let mut j0 = 0;
+            let mut min_v = vec![::std::i64::MAX; m + 1];
+            let mut used = vec![false; m + 1];
+
+            loop {
+                used[j0] = true;
+                let i0 = p[j0];
+                let mut delta = ::std::i64::MAX;
+                let mut j1 = 0;
+
+                for j in 1..(m + 1) {
+                    if used[j] {
+                        continue;
+                    }
+
+                    let current = cost(i0, j) - u[i0] - v[j];
+                    if current < min_v[j] {
+                        min_v[j] = current;
+                        way[j] = j0;
+                    }
+                    if min_v[j] < delta {
+                        delta = min_v[j];
+                        j1 = j;
+                    }
+                }
+
+                for j in 0..(m + 1) {
+                    if used[j] {
+                        u[p[j]] += delta;
+                        v[j] -= delta;
+                    } else {
+                        min_v[j] -= delta;
+                    }
+                }
+
+                j0 = j1;
+                if p[j0] == 0 {
+                    break;
+                }
+            }
+
+            loop {
+                let j1 = way[j0];
+                p[j0] = p[j1];
+                j0 = j1;
+                if j0 == 0 {
+                    break;
+                }
+            }
+        }
+
+        let mut total = 0u32;
+        for j in 1..(m + 1) {
+            if p[j] != 0 {
+                let distance = self.push_distances[j - 1][boxes[p[j] - 1]];
+                if distance >= INFINITY {
+                    return INFINITY;
+                }
+                total += distance;
+            }
+        }
+
+        total
+    }
+
+    // NOTE(erick): Walking distances from start, avoiding walls and boxes.
+    fn walk_distances(&self, start: usize, boxes: &[usize]) -> Vec<u32> {
+        let mut distances = vec![INFINITY; self.walkable.len()];
+        let mut queue = ::std::collections::VecDeque::new();
+
+        distances[start] = 0;
+        queue.push_back(start);
+
+        while let Some(cell) = queue.pop_front() {
+            for direction in Direction::all().iter() {
+                if let Some(next) = self.walkable_neighbour(cell, *direction) {
+                    if distances[next] == INFINITY && !boxes.contains(&next) {
+                        distances[next] = distances[cell] + 1;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
+    // NOTE(erick): Shortest walk from start to goal as a lowercase LURD string.
+    fn walk_path(&self, start: usize, goal: usize, boxes: &[usize]) -> Option<String> {
+        let distances = self.walk_distances(start, boxes);
+        if distances[goal] == INFINITY {
+            return None;
+        }
+
+        // NOTE(erick): Walk back from the goal following decreasing distances.
+        let mut path = Vec::new();
+        let mut cell = goal;
+        while cell != start {
+            for direction in Direction::all().iter() {
+                let previous = match self.walkable_neighbour(cell, direction.opposite()) {
+                    Some(previous)  => previous,
+                    None            => continue,
+                };
+
+                if distances[previous] + 1 == distances[cell] {
+                    path.push(direction.to_lurd(false));
+                    cell = previous;
+                    break;
+                }
+            }
+        }
+
+        path.reverse();
+        Some(path.into_iter().collect())
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    boxes   : Vec<usize>,
+    player  : usize,
+    cost    : u32,
+
+    parent  : Option<usize>,
+    // NOTE(erick): The box cell before the push and the push direction.
+    push    : Option<(usize, Direction)>,
+}
+
+#[derive(PartialEq)]
+#[derive(Eq)]
+struct OpenEntry {
+    estimate    : u32,
+    cost        : u32,
+    node_index  : usize,
+}
+
+// NOTE(erick): BinaryHeap is a max-heap, so the comparison is reversed. Ties
+// are broken in favor of the deepest node.
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &OpenEntry) -> Ordering {
+        other.estimate.cmp(&self.estimate)
+            .then_with(|| self.cost.cmp(&other.cost))
+            .then_with(|| other.node_index.cmp(&self.node_index))
+    }
+}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &OpenEntry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// NOTE(erick): When optimizing pushes the player position is normalized to the
+// reachable cell with the lowest index (the bottom-left most one, since cells
+// are right-handed), so states that only differ by walking are merged.
+// Optimizing moves needs the real position.
+fn transposition_key(objective: Objective, boxes: &[usize], player: usize,
+                     walk_distances: &[u32]) -> (Vec<usize>, usize) {
+    let player_key = match objective {
+        Objective::Moves    => player,
+        Objective::Pushes   => walk_distances.iter().position(|distance| *distance != INFINITY).unwrap_or(player),
+    };
+
+    (boxes.to_vec(), player_key)
+}
+
+pub fn solve(board: &Board, options: &SolverOptions) -> SolverResult {
+    let grid = StaticGrid::new(&board.map);
+    let start_time = Instant::now();
+
+    let mut start_boxes : Vec<usize> = board.map.boxes.iter().map(|_box| grid.cell(*_box)).collect();
+    start_boxes.sort();
+    let start_player = grid.cell(board.player);
+
+    let start_heuristic = grid.heuristic(&start_boxes);
+    if start_heuristic >= INFINITY {
+        return SolverResult::Unsolvable;
+    }
+
+    let mut nodes = vec![Node {
+        boxes   : start_boxes.clone(),
+        player  : start_player,
+        cost    : 0,
+
+        parent  : None,
+        push    : None,
+    }];
+
+    let mut open = BinaryHeap::new();
+    open.push(OpenEntry { estimate: start_heuristic, cost: 0, node_index: 0 });
+
+    let mut best_costs = HashMap::new();
+    let start_walk = grid.walk_distances(start_player, &start_boxes);
+    best_costs.insert(transposition_key(options.objective, &start_boxes, start_player, &start_walk), 0);
+
+    let mut nodes_explored = 0;
+
+    while let Some(entry) = open.pop() {
+        let node = nodes[entry.node_index].clone();
+
+        let walk_distances = grid.walk_distances(node.player, &node.boxes);
+        let key = transposition_key(options.objective, &node.boxes, node.player, &walk_distances);
+        if let Some(best_cost) = best_costs.get(&key) {
+            if *best_cost < node.cost {
+                // NOTE(erick): A cheaper way to this state was found after this entry was queued.
+                continue;
+            }
+        }
+
+        if node.boxes.iter().all(|_box| grid.targets.contains(_box)) {
+            return SolverResult::Solved(build_solution(&grid, board, &nodes, entry.node_index, nodes_explored));
+        }
+
+        nodes_explored += 1;
+        if nodes_explored >= options.max_nodes {
+            return SolverResult::BudgetExceeded;
+        }
+        if let Some(ref cancel) = options.cancel {
+            if cancel.load(AtomicOrdering::Relaxed) {
+                return SolverResult::Cancelled;
+            }
+        }
+        if nodes_explored % 1024 == 0 {
+            if let Some(time_limit) = options.time_limit {
+                if start_time.elapsed() > time_limit {
+                    return SolverResult::BudgetExceeded;
+                }
+            }
+        }
+
+        for box_slot in 0..node.boxes.len() {
+            let box_cell = node.boxes[box_slot];
+
+            for direction in Direction::all().iter() {
+                let player_cell = match grid.walkable_neighbour(box_cell, direction.opposite()) {
+                    Some(cell)  => cell,
+                    None        => continue,
+                };
+                if walk_distances[player_cell] == INFINITY {
+                    continue;
+                }
+
+                let new_box_cell = match grid.walkable_neighbour(box_cell, *direction) {
+                    Some(cell)  => cell,
+                    None        => continue,
+                };
+                if node.boxes.contains(&new_box_cell) || grid.is_dead(new_box_cell) {
+                    continue;
+                }
+
+                let mut new_boxes = node.boxes.clone();
+                new_boxes[box_slot] = new_box_cell;
+                new_boxes.sort();
+
+                let heuristic = grid.heuristic(&new_boxes);
+                if heuristic >= INFINITY {
+                    continue;
+                }
+
+                let step_cost = match options.objective {
+                    Objective::Pushes   => 1,
+                    Objective::Moves    => walk_distances[player_cell] + 1,
+                };
+                let new_cost = node.cost + step_cost;
+
+                let new_walk_distances = grid.walk_distances(box_cell, &new_boxes);
+                let new_key = transposition_key(options.objective, &new_boxes, box_cell, &new_walk_distances);
+                if let Some(best_cost) = best_costs.get(&new_key) {
+                    if *best_cost <= new_cost {
+                        continue;
+                    }
+                }
+                best_costs.insert(new_key, new_cost);
+
+                nodes.push(Node {
+                    boxes   : new_boxes,
+                    player  : box_cell,
+                    cost    : new_cost,
+
+                    parent  : Some(entry.node_index),
+                    push    : Some((box_cell, *direction)),
+                });
+                open.push(OpenEntry {
+                    estimate    : new_cost + heuristic,
+                    cost        : new_cost,
+                    node_index  : nodes.len() - 1,
+                });
+            }
+        }
+    }
+
+    SolverResult::Unsolvable
+}
+
+fn build_solution(grid: &StaticGrid, board: &Board, nodes: &Vec<Node>, last_node: usize,
+                  nodes_explored: usize) -> Solution {
+    let mut pushes = Vec::new();
+    let mut node_index = Some(last_node);
+    while let Some(index) = node_index {
+        if let Some(push) = nodes[index].push {
+            pushes.push(push);
+        }
+        node_index = nodes[index].parent;
+    }
+    pushes.reverse();
+
+    // NOTE(erick): Replay the pushes, walking the player to each of them.
+    let mut boxes : Vec<usize> = board.map.boxes.iter().map(|_box| grid.cell(*_box)).collect();
+    let mut player = grid.cell(board.player);
+    let mut lurd = String::new();
+
+    for &(box_cell, direction) in &pushes {
+        let player_cell = grid.neighbour(box_cell, direction.opposite()).unwrap();
+        let walk = grid.walk_path(player, player_cell, &boxes)
+            .expect("The solver produced a push the player can't reach");
+        lurd.push_str(walk.as_str());
+        lurd.push(direction.to_lurd(true));
+
+        let box_slot = boxes.iter().position(|_box| *_box == box_cell).unwrap();
+        boxes[box_slot] = grid.neighbour(box_cell, direction).unwrap();
+        player = box_cell;
+    }
+
+    Solution {
+        n_moves        : lurd.len(),
+        n_pushes       : pushes.len(),
+        lurd           : lurd,
+        nodes_explored : nodes_explored,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xsb::*;
+
+    const MICROBAN_1 : [&'static str; 7] = [
+        "####",
+        "# .#",
+        "#  ###",
+        "#*@  #",
+        "#  $ #",
+        "#  ###",
+        "####",
+    ];
+
+    fn board_from_xsb(lines: &[&str]) -> Board {
+        let lines : Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        let level = level_from_xsb("test", &lines).unwrap();
+        Board::new(level.map, level.player_position)
+    }
+
+    fn play_lurd(board: &mut Board, lurd: &str) {
+        for c in lurd.chars() {
+            let (direction, _) = Direction::from_lurd(c).unwrap();
+            assert!(board.try_move(direction) != MoveResult::Blocked, "blocked at {} in {}", c, lurd);
+        }
+    }
+
+    #[test]
+    fn solves_microban_1_optimally() {
+        for &objective in &[Objective::Pushes, Objective::Moves] {
+            let mut board = board_from_xsb(&MICROBAN_1);
+
+            let solution = match solve(&board, &SolverOptions::new(objective)) {
+                SolverResult::Solved(solution)  => solution,
+                result                          => panic!("{:?}: {:?}", objective, result),
+            };
+            assert_eq!(solution.n_moves, 33, "{:?}", objective);
+            assert_eq!(solution.n_pushes, 8, "{:?}", objective);
+
+            play_lurd(&mut board, solution.lurd.as_str());
+            assert!(board.is_solved());
+            assert_eq!(board.n_pushes(), solution.n_pushes);
+        }
+    }
+
+    #[test]
+    fn finds_unsolvable_levels() {
+        let board = board_from_xsb(&["#####", "#@ .#", "#$  #", "#####"]);
+
+        match solve(&board, &SolverOptions::new(Objective::Pushes)) {
+            SolverResult::Unsolvable    => {},
+            result                      => panic!("{:?}", result),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+// NOTE(erick): Sound effects. Which file plays for each effect comes from a
+// .sfx file, given by the level (sound_effects = default.sfx) or by the theme,
+// with the same 'key = value' lines as the .lvl files:
+//  step = sounds/step.wav
+//  push = sounds/push.wav
+//  box_on_target = sounds/click.ogg
+// The sound files are looked for in the directory of the .sfx file. Effects
+// that are not there are simply not played.
+//
+// Loading and playing the sounds is up to the frontend, this only decides what
+// should be heard.
+
+use std::collections::hash_map::HashMap;
+use std::collections::VecDeque;
+
+use std::path::Path;
+
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+
+use std::fmt;
+
+use game::*;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub enum SoundEffect {
+    Step,
+    Push,
+    BoxOnTarget,
+    BumpWall,
+    Undo,
+    LevelComplete,
+}
+
+impl SoundEffect {
+    pub fn all() -> [SoundEffect; 6] {
+        [SoundEffect::Step, SoundEffect::Push, SoundEffect::BoxOnTarget,
+         SoundEffect::BumpWall, SoundEffect::Undo, SoundEffect::LevelComplete]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SoundEffect::Step           => "step",
+            SoundEffect::Push           => "push",
+            SoundEffect::BoxOnTarget    => "box_on_target",
+            SoundEffect::BumpWall       => "bump_wall",
+            SoundEffect::Undo           => "undo",
+            SoundEffect::LevelComplete  => "level_complete",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SoundEffect> {
+        SoundEffect::all().iter().cloned().find(|effect| effect.name() == name)
+    }
+}
+
+// NOTE(erick): How many events an AudioLog keeps, the oldest ones go first.
+const MAX_LOGGED_AUDIO_EVENTS : usize = 256;
+
+// NOTE(erick): Something the game asked to play. The null audio backend keeps
+// them in an AudioLog so automated tests can check what would have been heard.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum AudioEvent {
+    Effect(SoundEffect),
+    // NOTE(erick): None stops the music.
+    Music(Option<String>),
+}
+
+impl fmt::Display for AudioEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &AudioEvent::Effect(effect)             => write!(f, "effect {}", effect.name()),
+            &AudioEvent::Music(Some(ref path))      => write!(f, "music {}", path),
+            &AudioEvent::Music(None)                => write!(f, "music stopped"),
+        }
+    }
+}
+
+// NOTE(erick): What the null audio backend does instead of playing: every event
+// is kept, quietly. Like SDL_mixer, asking for the music that is already
+// playing does nothing.
+#[derive(Debug)]
+pub struct AudioLog {
+    events     : VecDeque<AudioEvent>,
+    music_path : Option<String>,
+}
+
+impl AudioLog {
+    pub fn new() -> AudioLog {
+        AudioLog {
+            events     : VecDeque::new(),
+            music_path : None,
+        }
+    }
+
+    pub fn play_effect(&mut self, effect: SoundEffect) {
+        self.push(AudioEvent::Effect(effect));
+    }
+
+    pub fn play_music(&mut self, music_path: Option<&String>) {
+        if self.music_path.as_ref() == music_path {
+            return;
+        }
+
+        self.music_path = music_path.cloned();
+        let event = AudioEvent::Music(self.music_path.clone());
+        self.push(event);
+    }
+
+    fn push(&mut self, event: AudioEvent) {
+        if self.events.len() == MAX_LOGGED_AUDIO_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    // NOTE(erick): Everything logged since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<AudioEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+// NOTE(erick): The sound file of each effect, with the asset path.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SoundRegistry {
+    pub sounds : HashMap<SoundEffect, String>,
+}
+
+#[derive(Debug)]
+pub enum SoundError {
+    Io(String, io::Error),
+    MissingEqualsSign { line: u32 },
+    UnknownEffect { line: u32, name: String },
+}
+
+impl fmt::Display for SoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SoundError::Io(ref path, ref error)            => write!(f, "Could not read {}: {}", path, error),
+            &SoundError::MissingEqualsSign { line }         => write!(f, "Line {}: Could not find '=' sign", line),
+            &SoundError::UnknownEffect { line, ref name }   => write!(f, "Line {}: Unknown sound effect: {}", line, name),
+        }
+    }
+}
+
+// NOTE(erick): sound_effects_path has the asset path, like Map::level_music.
+pub fn parse_sound_effects(sound_effects_path: &str) -> Result<SoundRegistry, Vec<SoundError>> {
+    let file = match File::open(Path::new(sound_effects_path)) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![SoundError::Io(sound_effects_path.to_string(), error)]); }
+    };
+
+    let sounds_directory = Path::new(sound_effects_path).parent().unwrap_or(Path::new(""));
+    let mut errors = Vec::new();
+    let mut sounds = HashMap::new();
+
+    let mut line_number = 0;
+    for line in BufReader::new(file).lines() {
+        line_number += 1;
+
+        let line = match line {
+            Ok(line)    => line,
+            Err(error)  => {
+                errors.push(SoundError::Io(sound_effects_path.to_string(), error));
+                break;
+            }
+        };
+        if line == "" || line.starts_with("//") {
+            continue;
+        }
+
+        let attrib_index = match line.find('=') {
+            Some(index) => index,
+            None        => {
+                errors.push(SoundError::MissingEqualsSign { line: line_number });
+                continue;
+            }
+        };
+
+        let _split = line.split_at(attrib_index);
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        match SoundEffect::from_name(lhs) {
+            Some(effect)    => { sounds.insert(effect, sounds_directory.join(rhs).to_string_lossy().to_string()); },
+            None            => { errors.push(SoundError::UnknownEffect { line: line_number, name: lhs.to_string() }); },
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(SoundRegistry {
+        sounds : sounds,
+    })
+}
+
+// NOTE(erick): What a move sounds like. board is the board after the move.
+// Blocked moves bump into something, whether it's a wall or a box that can't
+// go any further.
+pub fn move_sounds(board: &Board, result: MoveResult) -> Vec<SoundEffect> {
+    match result {
+        MoveResult::Blocked             => vec![SoundEffect::BumpWall],
+        MoveResult::Moved               => vec![SoundEffect::Step],
+        MoveResult::Pushed(box_index)   => {
+            let (box_x, box_y) = board.map.boxes[box_index];
+            if board.map.tile_at(box_x, box_y) == TileType::Target {
+                vec![SoundEffect::Push, SoundEffect::BoxOnTarget]
+            } else {
+                vec![SoundEffect::Push]
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xsb::*;
+
+    // NOTE(erick): The sounds of moving right on a board with a single row.
+    fn sounds_moving_right(row: &str) -> Vec<SoundEffect> {
+        let wall : String = row.chars().map(|_| '#').collect();
+        let lines = vec![wall.clone(), row.to_string(), wall];
+        let level = level_from_xsb("test", &lines).unwrap();
+
+        let mut board = Board::new(level.map, level.player_position);
+        let result = board.try_move(Direction::Right);
+
+        move_sounds(&board, result)
+    }
+
+    #[test]
+    fn steps_and_bumps() {
+        assert_eq!(sounds_moving_right("#@ $.#"), vec![SoundEffect::Step]);
+        assert_eq!(sounds_moving_right("#@#$.#"), vec![SoundEffect::BumpWall]);
+        // NOTE(erick): A box that can't move sounds like a wall.
+        assert_eq!(sounds_moving_right("#@$#.#"), vec![SoundEffect::BumpWall]);
+        assert_eq!(sounds_moving_right("#@$$..#"), vec![SoundEffect::BumpWall]);
+    }
+
+    #[test]
+    fn pushes_onto_targets_sound_different() {
+        assert_eq!(sounds_moving_right("#@$ .#"), vec![SoundEffect::Push]);
+        assert_eq!(sounds_moving_right("#@$.#"), vec![SoundEffect::Push, SoundEffect::BoxOnTarget]);
+        // NOTE(erick): Pushing a box off a target is just a push.
+        assert_eq!(sounds_moving_right("#@* #"), vec![SoundEffect::Push]);
+    }
+
+    #[test]
+    fn audio_log_keeps_what_would_have_played() {
+        let mut log = AudioLog::new();
+        let music = String::from("assets/music.ogg");
+
+        log.play_effect(SoundEffect::Step);
+        log.play_music(Some(&music));
+        log.play_music(Some(&music));
+        log.play_music(None);
+
+        assert_eq!(log.take_events(), vec![AudioEvent::Effect(SoundEffect::Step),
+                                           AudioEvent::Music(Some(music.clone())),
+                                           AudioEvent::Music(None)]);
+        assert_eq!(log.take_events(), vec![]);
+    }
+
+    #[test]
+    fn audio_log_forgets_the_oldest_events() {
+        let mut log = AudioLog::new();
+
+        log.play_effect(SoundEffect::Undo);
+        for _ in 0..MAX_LOGGED_AUDIO_EVENTS {
+            log.play_effect(SoundEffect::Step);
+        }
+
+        let events = log.take_events();
+        assert_eq!(events.len(), MAX_LOGGED_AUDIO_EVENTS);
+        assert!(events.iter().all(|event| *event == AudioEvent::Effect(SoundEffect::Step)));
+    }
+}
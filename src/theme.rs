@@ -0,0 +1,451 @@
+// NOTE(erick): A theme replaces the tiles of the level with a tileset of its
+// own. Each theme is a directory in assets/themes/ with a theme.txt file:
+//  name = Dungeon
+//  atlas = tiles.atlas
+//  autotile = 47
+//  blank_color = (8, 8, 16)
+//  sound_effects = dungeon.sfx
+// The atlas (see the atlas module) is in the same directory and has the
+// regions:
+//  wall_<n>   one piece for every neighbour mask, see below;
+//  floor_<n>  any number of floor variations, from floor_0 on;
+//  target;
+//  blank      optional, drawn on Blank tiles. Without it they are filled with
+//             blank_color, the outer darkness.
+// sound_effects is optional, with it the theme's sounds (see the sound module)
+// are played instead of the level's.
+//
+// Autotiling picks the wall piece from the walls around it:
+//  none  a single piece, wall_0.
+//  16    only the four sides count. The piece is N + 2 * E + 4 * S + 8 * W,
+//        each one being 1 if there is a wall that way.
+//  47    the sides and the corners, but a corner only counts when both sides
+//        next to it are walls (otherwise it doesn't change the look). Bits are
+//        N = 1, NE = 2, E = 4, SE = 8, S = 16, SW = 32, W = 64, NW = 128, and
+//        the 47 masks left are numbered in increasing order, so wall_0 is the
+//        lone wall and wall_46 the wall surrounded by walls.
+
+use std::path::Path;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+
+use std::fmt;
+
+use game::*;
+use level::*;
+use atlas::*;
+
+const THEMES_DIRECTORY : &'static str = "themes/";
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Autotile {
+    None,
+    Blob16,
+    Blob47,
+}
+
+impl Autotile {
+    pub fn n_pieces(&self) -> usize {
+        match *self {
+            Autotile::None      => 1,
+            Autotile::Blob16    => 16,
+            Autotile::Blob47    => 47,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Theme {
+    pub name             : String,
+    // NOTE(erick): The atlas name as parse_atlas wants it, with the theme
+    // directory in front.
+    pub atlas_name       : String,
+    pub atlas            : TextureAtlas,
+    pub autotile         : Autotile,
+    pub n_floor_variants : usize,
+    pub has_blank        : bool,
+    pub blank_color      : (u8, u8, u8),
+    // NOTE(erick): With the asset path, like Map::sound_effects.
+    pub sound_effects    : Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(String, io::Error),
+    MissingEqualsSign { line: u32 },
+    UnknownKey { line: u32, key: String },
+    MissingKey(&'static str),
+    BadAutotile { line: u32, value: String },
+    BadColor { line: u32, value: String },
+    Atlas(AtlasError),
+    MissingRegion(String),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ThemeError::Io(ref path, ref error)        => write!(f, "Could not read {}: {}", path, error),
+            &ThemeError::MissingEqualsSign { line }     => write!(f, "Line {}: Could not find '=' sign", line),
+            &ThemeError::UnknownKey { line, ref key }   => write!(f, "Line {}: Unknown variable: {}", line, key),
+            &ThemeError::MissingKey(key)                => write!(f, "{} must be specified", key),
+            &ThemeError::BadAutotile { line, ref value } => write!(f, "Line {}: autotile must be none, 16 or 47, not {}", line, value),
+            &ThemeError::BadColor { line, ref value }   => write!(f, "Line {}: Could not parse {} as (r, g, b)", line, value),
+            &ThemeError::Atlas(ref error)               => write!(f, "{}", error),
+            &ThemeError::MissingRegion(ref name)        => write!(f, "The atlas has no {} region", name),
+        }
+    }
+}
+
+fn is_wall(map: &Map, x: i32, y: i32) -> bool {
+    map.is_inside(x, y) && map.tile_at(x as u32, y as u32) == TileType::Wall
+}
+
+// NOTE(erick): Positions are right-handed, so north is y + 1.
+pub fn wall_mask_16(map: &Map, x: u32, y: u32) -> usize {
+    let (x, y) = (x as i32, y as i32);
+
+    let mut mask = 0;
+    if is_wall(map, x, y + 1) { mask |= 1; }
+    if is_wall(map, x + 1, y) { mask |= 2; }
+    if is_wall(map, x, y - 1) { mask |= 4; }
+    if is_wall(map, x - 1, y) { mask |= 8; }
+
+    mask
+}
+
+// NOTE(erick): The piece for every neighbour mask. Corners whose sides are not
+// both walls are dropped first, and the 47 masks left are numbered in
+// increasing order.
+const BLOB_47_PIECES : [u8; 256] = [
+     0,  1,  0,  1,  2,  3,  2,  4,  0,  1,  0,  1,  2,  3,  2,  4,
+     5,  6,  5,  6,  7,  8,  7,  9,  5,  6,  5,  6, 10, 11, 10, 12,
+     0,  1,  0,  1,  2,  3,  2,  4,  0,  1,  0,  1,  2,  3,  2,  4,
+     5,  6,  5,  6,  7,  8,  7,  9,  5,  6,  5,  6, 10, 11, 10, 12,
+    13, 14, 13, 14, 15, 16, 15, 17, 13, 14, 13, 14, 15, 16, 15, 17,
+    18, 19, 18, 19, 20, 21, 20, 22, 18, 19, 18, 19, 23, 24, 23, 25,
+    13, 14, 13, 14, 15, 16, 15, 17, 13, 14, 13, 14, 15, 16, 15, 17,
+    26, 27, 26, 27, 28, 29, 28, 30, 26, 27, 26, 27, 31, 32, 31, 33,
+     0,  1,  0,  1,  2,  3,  2,  4,  0,  1,  0,  1,  2,  3,  2,  4,
+     5,  6,  5,  6,  7,  8,  7,  9,  5,  6,  5,  6, 10, 11, 10, 12,
+     0,  1,  0,  1,  2,  3,  2,  4,  0,  1,  0,  1,  2,  3,  2,  4,
+     5,  6,  5,  6,  7,  8,  7,  9,  5,  6,  5,  6, 10, 11, 10, 12,
+    13, 34, 13, 34, 15, 35, 15, 36, 13, 34, 13, 34, 15, 35, 15, 36,
+    18, 37, 18, 37, 20, 38, 20, 39, 18, 37, 18, 37, 23, 40, 23, 41,
+    13, 34, 13, 34, 15, 35, 15, 36, 13, 34, 13, 34, 15, 35, 15, 36,
+    26, 42, 26, 42, 28, 43, 28, 44, 26, 42, 26, 42, 31, 45, 31, 46,
+];
+
+pub fn wall_mask_47(map: &Map, x: u32, y: u32) -> usize {
+    let (x, y) = (x as i32, y as i32);
+    let neighbours = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+
+    let mut mask = 0;
+    for (bit, &(dx, dy)) in neighbours.iter().enumerate() {
+        if is_wall(map, x + dx, y + dy) {
+            mask |= 1 << bit;
+        }
+    }
+
+    BLOB_47_PIECES[mask] as usize
+}
+
+pub fn wall_piece_index(map: &Map, x: u32, y: u32, autotile: Autotile) -> usize {
+    match autotile {
+        Autotile::None      => 0,
+        Autotile::Blob16    => wall_mask_16(map, x, y),
+        Autotile::Blob47    => wall_mask_47(map, x, y),
+    }
+}
+
+// NOTE(erick): Which floor variation goes on a tile. A hash of the position so
+// the floor looks the same every time the level is drawn.
+pub fn floor_variant_index(x: u32, y: u32, n_variants: usize) -> usize {
+    if n_variants <= 1 {
+        return 0;
+    }
+
+    let mut hash = (x as u64).wrapping_mul(0x9e3779b97f4a7c15) ^ (y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(0xbf58476d1ce4e5b9);
+    hash ^= hash >> 32;
+
+    (hash % n_variants as u64) as usize
+}
+
+fn parse_color(value: &str) -> Option<(u8, u8, u8)> {
+    let value = value.trim();
+    if !value.starts_with('(') || !value.ends_with(')') {
+        return None;
+    }
+
+    let components : Vec<Option<u8>> = value[1..value.len() - 1].split(',')
+        .map(|component| component.trim().parse::<u8>().ok())
+        .collect();
+    if components.len() != 3 {
+        return None;
+    }
+
+    match (components[0], components[1], components[2]) {
+        (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+        _                           => None,
+    }
+}
+
+// NOTE(erick): theme_directory is the name of the directory in assets/themes/.
+pub fn parse_theme(theme_directory: &str) -> Result<Theme, Vec<ThemeError>> {
+    let theme_path_string = asset_path_string(AssetType::Sprite, format!("{}{}/theme.txt", THEMES_DIRECTORY, theme_directory).as_str());
+    let file = match File::open(Path::new(theme_path_string.as_str())) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![ThemeError::Io(theme_path_string, error)]); }
+    };
+
+    let mut errors = Vec::new();
+    let mut name = None;
+    let mut atlas_name = None;
+    let mut autotile = Autotile::None;
+    let mut blank_color = (0, 0, 0);
+    let mut sound_effects = None;
+
+    let mut line_number = 0;
+    for line in BufReader::new(file).lines() {
+        line_number += 1;
+
+        let line = match line {
+            Ok(line)    => line,
+            Err(error)  => {
+                errors.push(ThemeError::Io(theme_path_string.clone(), error));
+                break;
+            }
+        };
+        if line == "" || line.starts_with("//") {
+            continue;
+        }
+
+        let attrib_index = match line.find('=') {
+            Some(index) => index,
+            None        => {
+                errors.push(ThemeError::MissingEqualsSign { line: line_number });
+                continue;
+            }
+        };
+
+        let _split = line.split_at(attrib_index);
+        let lhs = (_split.0).trim();
+        let rhs = (_split.1)[1..].trim();
+
+        match lhs {
+            "name"          => { name = Some(rhs.to_string()); },
+            "atlas"         => { atlas_name = Some(format!("{}{}/{}", THEMES_DIRECTORY, theme_directory, rhs)); },
+            "sound_effects" => {
+                let sound_effects_name = format!("{}{}/{}", THEMES_DIRECTORY, theme_directory, rhs);
+                sound_effects = Some(asset_path_string(AssetType::Sound, sound_effects_name.as_str()));
+            },
+            "autotile"      => {
+                autotile = match rhs {
+                    "none"  => Autotile::None,
+                    "16"    => Autotile::Blob16,
+                    "47"    => Autotile::Blob47,
+                    _       => {
+                        errors.push(ThemeError::BadAutotile { line: line_number, value: rhs.to_string() });
+                        Autotile::None
+                    }
+                };
+            },
+            "blank_color"   => {
+                match parse_color(rhs) {
+                    Some(color) => { blank_color = color; },
+                    None        => { errors.push(ThemeError::BadColor { line: line_number, value: rhs.to_string() }); },
+                }
+            },
+            _               => { errors.push(ThemeError::UnknownKey { line: line_number, key: lhs.to_string() }); },
+        }
+    }
+
+    if name.is_none()       { errors.push(ThemeError::MissingKey("name")); }
+    if atlas_name.is_none() { errors.push(ThemeError::MissingKey("atlas")); }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let atlas_name = atlas_name.unwrap();
+    let atlas = match parse_atlas(atlas_name.as_str()) {
+        Ok(atlas)   => atlas,
+        Err(atlas_errors) => { return Err(atlas_errors.into_iter().map(ThemeError::Atlas).collect()); }
+    };
+
+    let mut required_regions : Vec<String> = (0..autotile.n_pieces()).map(|index| format!("wall_{}", index)).collect();
+    required_regions.push(String::from("floor_0"));
+    required_regions.push(String::from("target"));
+    for region_name in required_regions {
+        if !atlas.regions.contains_key(&region_name) {
+            errors.push(ThemeError::MissingRegion(region_name));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let n_floor_variants = (0..).take_while(|index| atlas.regions.contains_key(&format!("floor_{}", index))).count();
+    let has_blank = atlas.regions.contains_key("blank");
+
+    Ok(Theme {
+        name             : name.unwrap(),
+        atlas_name       : atlas_name,
+        atlas            : atlas,
+        autotile         : autotile,
+        n_floor_variants : n_floor_variants,
+        has_blank        : has_blank,
+        blank_color      : blank_color,
+        sound_effects    : sound_effects,
+    })
+}
+
+// NOTE(erick): Every theme in assets/themes/, sorted by directory name. Broken
+// themes are reported and left out.
+pub fn load_themes() -> Vec<Theme> {
+    let mut themes = Vec::new();
+
+    let themes_path = asset_path_string(AssetType::Sprite, THEMES_DIRECTORY);
+    let entries = match fs::read_dir(themes_path.as_str()) {
+        Ok(entries) => entries,
+        Err(_)      => { return themes; }
+    };
+
+    let mut theme_directories : Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    theme_directories.sort();
+
+    for theme_directory in theme_directories {
+        match parse_theme(theme_directory.as_str()) {
+            Ok(theme)   => { themes.push(theme); },
+            Err(errors) => {
+                for error in errors {
+                    println!("Error(themes/{}): {}", theme_directory, error);
+                }
+            },
+        }
+    }
+
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    // NOTE(erick): '#' is a wall, anything else is floor. Tiles are stored top
+    // row first.
+    fn map_from_rows(rows: &[&str]) -> Map {
+        let mut map = Map::new("test");
+        map.tiles_stride = rows[0].len() as i32;
+        for row in rows {
+            for c in row.chars() {
+                map.tiles.push(if c == '#' { TileType::Wall } else { TileType::Floor });
+            }
+        }
+
+        map
+    }
+
+    // NOTE(erick): The mask the table is built from, straight from the rules
+    // in the header.
+    fn reduce_blob_mask(mask: usize) -> usize {
+        let mut result = mask & (1 | 4 | 16 | 64);
+        if mask & 2 != 0   && mask & 1 != 0  && mask & 4 != 0  { result |= 2; }
+        if mask & 8 != 0   && mask & 16 != 0 && mask & 4 != 0  { result |= 8; }
+        if mask & 32 != 0  && mask & 16 != 0 && mask & 64 != 0 { result |= 32; }
+        if mask & 128 != 0 && mask & 1 != 0  && mask & 64 != 0 { result |= 128; }
+
+        result
+    }
+
+    #[test]
+    fn the_47_masks_are_numbered_in_order() {
+        let valid_masks : Vec<usize> = (0..256).filter(|&mask| reduce_blob_mask(mask) == mask).collect();
+        assert_eq!(valid_masks.len(), 47);
+
+        for (piece, &mask) in valid_masks.iter().enumerate() {
+            assert_eq!(BLOB_47_PIECES[mask] as usize, piece, "mask {}", mask);
+        }
+        for mask in 0..256 {
+            assert_eq!(BLOB_47_PIECES[mask], BLOB_47_PIECES[reduce_blob_mask(mask)], "mask {}", mask);
+        }
+    }
+
+    #[test]
+    fn wall_masks_look_at_the_neighbours() {
+        // NOTE(erick): Right-handed, so the bottom row is y = 0.
+        let map = map_from_rows(&["#####",
+                                  "#   #",
+                                  "#####"]);
+
+        assert_eq!(wall_mask_16(&map, 0, 0), 1 | 2);
+        assert_eq!(wall_mask_16(&map, 0, 1), 1 | 4);
+        assert_eq!(wall_mask_16(&map, 2, 2), 2 | 8);
+
+        assert_eq!(wall_mask_47(&map, 0, 0), BLOB_47_PIECES[1 | 4] as usize);
+        // NOTE(erick): NE and SE are walls, but E isn't so they don't count.
+        assert_eq!(wall_mask_47(&map, 0, 1), BLOB_47_PIECES[1 | 16] as usize);
+
+        let lone_wall = map_from_rows(&["#"]);
+        assert_eq!(wall_mask_47(&lone_wall, 0, 0), 0);
+
+        let block = map_from_rows(&["###", "###", "###"]);
+        // NOTE(erick): The top-left corner has E, SE and S, all of them count.
+        assert_eq!(wall_mask_47(&block, 0, 2), BLOB_47_PIECES[4 | 8 | 16] as usize);
+        assert_eq!(wall_mask_47(&block, 1, 1), 46);
+        assert_eq!(wall_piece_index(&block, 1, 1, Autotile::Blob16), 15);
+        assert_eq!(wall_piece_index(&block, 1, 1, Autotile::None), 0);
+    }
+
+    #[test]
+    fn reads_the_sample_theme() {
+        let theme = parse_theme("stone").unwrap();
+
+        assert_eq!(theme.name, "Stone");
+        assert_eq!(theme.atlas_name, "themes/stone/tiles.atlas");
+        assert_eq!(theme.atlas.image, "themes/stone/tiles.bmp");
+        assert_eq!(theme.autotile, Autotile::Blob16);
+        assert_eq!(theme.n_floor_variants, 2);
+        assert!(theme.has_blank);
+        assert_eq!(theme.blank_color, (8, 8, 16));
+        assert!(theme.sound_effects.is_none());
+    }
+
+    #[test]
+    fn reports_broken_themes() {
+        let theme_directory = format!("test-broken-{}", process::id());
+        let theme_path = asset_path_string(AssetType::Sprite, format!("{}{}", THEMES_DIRECTORY, theme_directory).as_str());
+
+        fs::create_dir(&theme_path).unwrap();
+        fs::write(format!("{}/theme.txt", theme_path), "autotile = 8\nblank_color = (8, 8)\ncolour = red\n").unwrap();
+        let result = parse_theme(theme_directory.as_str());
+        fs::remove_dir_all(&theme_path).unwrap();
+
+        let errors = match result {
+            Ok(_)       => panic!("A broken theme was accepted"),
+            Err(errors) => errors,
+        };
+        let messages : Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+
+        assert_eq!(messages, vec![
+            "Line 1: autotile must be none, 16 or 47, not 8",
+            "Line 2: Could not parse (8, 8) as (r, g, b)",
+            "Line 3: Unknown variable: colour",
+            "name must be specified",
+            "atlas must be specified",
+        ]);
+    }
+}
@@ -0,0 +1,256 @@
+// NOTE(erick): Reader and writer for the community XSB text format:
+//  '#' wall, ' ' floor, '.' target, '$' box, '*' box on a target,
+//  '@' player, '+' player on a target.
+// '-' and '_' are accepted as floor too. Floor outside the walls becomes
+// TileType::Blank.
+//
+// XSB can't tell blank space from floor, so a round trip only keeps what is
+// played: the blank rows around the level and the blanks at the end of the rows
+// are dropped, and blank tiles enclosed by walls come back as floor. Exporting
+// again gives the same text, and Level::identity_hash doesn't change.
+
+use std::path::Path;
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+
+use game::*;
+use level::*;
+
+pub fn is_xsb_line(line: &str) -> bool {
+    let line = line.trim_end();
+    if line.is_empty() {
+        return false;
+    }
+
+    line.contains('#') && line.chars().all(|c| "#@+$*. -_".contains(c))
+}
+
+pub fn read_xsb_file(xsb_path: &Path) -> Result<Level, Vec<LevelError>> {
+    let mut xsb_file = match File::open(xsb_path) {
+        Ok(file)    => file,
+        Err(error)  => { return Err(vec![LevelError::Io(format!("{:?}", xsb_path), error)]); }
+    };
+
+    let mut contents = String::new();
+    if let Err(error) = xsb_file.read_to_string(&mut contents) {
+        return Err(vec![LevelError::Io(format!("{:?}", xsb_path), error)]);
+    }
+
+    // NOTE(erick): Use the 'Title:' line if there is one, the file name otherwise.
+    let mut level_name = match xsb_path.file_stem() {
+        Some(stem)  => stem.to_string_lossy().into_owned(),
+        None        => String::from("Untitled"),
+    };
+
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        if is_xsb_line(line) {
+            lines.push(line.to_string());
+        } else if line.starts_with("Title:") {
+            level_name = line["Title:".len()..].trim().to_string();
+        }
+    }
+
+    level_from_xsb(level_name.as_str(), &lines)
+}
+
+pub fn level_from_xsb(level_name: &str, lines: &[String]) -> Result<Level, Vec<LevelError>> {
+    let n_lines = lines.len() as u32;
+    let n_cols = lines.iter().map(|line| line.trim_end().chars().count()).max().unwrap_or(0) as u32;
+
+    if n_lines == 0 || n_cols == 0 {
+        return Err(vec![LevelError::EmptyMap]);
+    }
+
+    let mut map = Map::new(level_name);
+    map.tiles_stride = n_cols as i32;
+
+    let mut player_position = None;
+    // NOTE(erick): Tiles that are neither walls nor targets. We don't know yet
+    // whether they are inside the walls.
+    let mut open_tiles = Vec::new();
+    let mut errors = Vec::new();
+
+    for (row, line) in lines.iter().enumerate() {
+        let mut chars = line.trim_end().chars();
+
+        for col in 0..n_cols {
+            let c = chars.next().unwrap_or(' ');
+            // NOTE(erick): XSB rows go from top to bottom, just like our .map files.
+            let position = Map::from_left_to_right_handed((col, row as u32), n_lines);
+
+            let tile = match c {
+                '#'                         => TileType::Wall,
+                '.' | '*' | '+'             => TileType::Target,
+                ' ' | '-' | '_' | '$' | '@' => TileType::Floor,
+                _                           => {
+                    errors.push(LevelError::UnknownCharacter { line: row as u32 + 1, character: c });
+                    TileType::Blank
+                }
+            };
+            map.tiles.push(tile);
+
+            if c == ' ' || c == '-' || c == '_' {
+                open_tiles.push(map.tiles.len() - 1);
+            }
+
+            if c == '$' || c == '*' {
+                map.boxes.push(position);
+            }
+
+            if c == '@' || c == '+' {
+                if !player_position.is_none() {
+                    errors.push(LevelError::MoreThanOnePlayer { line: row as u32 + 1 });
+                }
+                player_position = Some(position);
+            }
+        }
+    }
+
+    if player_position.is_none() {
+        errors.push(LevelError::NoPlayer);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    mark_outside_as_blank(&mut map, &open_tiles);
+
+    let errors = validate_level(&map, player_position.unwrap());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(Level {
+        map             : map,
+        player_position : player_position.unwrap(),
+        textures        : LevelTextures::default_textures(),
+    })
+}
+
+// NOTE(erick): Flood fills (over empty floor) from every empty floor tile on the
+// border of the map.
+fn mark_outside_as_blank(map: &mut Map, open_tiles: &[usize]) {
+    let stride = map.tiles_stride as usize;
+    let n_lines = map.n_lines() as usize;
+
+    let mut is_open = vec![false; map.tiles.len()];
+    for &index in open_tiles {
+        is_open[index] = true;
+    }
+
+    let mut stack = Vec::new();
+    for &index in open_tiles {
+        let (col, row) = (index % stride, index / stride);
+        if col == 0 || row == 0 || col == stride - 1 || row == n_lines - 1 {
+            stack.push(index);
+        }
+    }
+
+    while let Some(index) = stack.pop() {
+        if !is_open[index] {
+            continue;
+        }
+        is_open[index] = false;
+        map.tiles[index] = TileType::Blank;
+
+        let (col, row) = (index % stride, index / stride);
+        if col > 0               { stack.push(index - 1); }
+        if col + 1 < stride      { stack.push(index + 1); }
+        if row > 0               { stack.push(index - stride); }
+        if row + 1 < n_lines     { stack.push(index + stride); }
+    }
+}
+
+// NOTE(erick): Rows with nothing but Blank tiles at the top and at the bottom
+// of the map are left out since XSB uses empty lines to separate levels. So are
+// the Blank tiles at the end of each row.
+pub fn xsb_string(map: &Map, player_position: TilePosition) -> String {
+    let mut lines = Vec::new();
+
+    for row in 0..map.n_lines() {
+        let mut line = String::new();
+
+        for col in 0..map.n_cols() {
+            let position = Map::from_left_to_right_handed((col, row), map.n_lines());
+            let has_box = !map.box_index_at(position).is_none();
+            let has_player = position == player_position;
+
+            let c = match map.tile_at(position.0, position.1) {
+                TileType::Wall      => '#',
+                TileType::Blank     => ' ',
+                TileType::Floor     => {
+                    if has_box { '$' } else if has_player { '@' } else { ' ' }
+                },
+                TileType::Target    => {
+                    if has_box { '*' } else if has_player { '+' } else { '.' }
+                },
+            };
+            line.push(c);
+        }
+
+        lines.push(line.trim_end().to_string());
+    }
+
+    while lines.last().map_or(false, |line| line.is_empty()) {
+        lines.pop();
+    }
+
+    let mut result = String::new();
+    for line in lines.iter().skip_while(|line| line.is_empty()) {
+        result.push_str(line);
+        result.push('\n');
+    }
+
+    result
+}
+
+pub fn write_xsb_file(xsb_path: &Path, map: &Map, player_position: TilePosition) -> io::Result<()> {
+    let mut xsb_file = File::create(xsb_path)?;
+
+    xsb_file.write_all(xsb_string(map, player_position).as_bytes())?;
+    xsb_file.write_all(format!("Title: {}\n", map.name).as_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(level: &Level) -> Level {
+        let text = xsb_string(&level.map, level.player_position);
+        let lines : Vec<String> = text.lines().map(|line| line.to_string()).collect();
+        level_from_xsb(level.map.name.as_str(), &lines).unwrap()
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let lines : Vec<String> = vec!["#####", "#@$.#", "#####"].iter().map(|line| line.to_string()).collect();
+        let level = level_from_xsb("test", &lines).unwrap();
+
+        assert_eq!(level.map.n_cols(), 5);
+        assert_eq!(level.map.n_lines(), 3);
+        assert_eq!(level.player_position, (1, 1));
+        assert_eq!(level.map.boxes, vec![(2, 1)]);
+        assert_eq!(level.map.tile_at(3, 1), TileType::Target);
+        assert_eq!(xsb_string(&level.map, level.player_position), "#####\n#@$.#\n#####\n");
+    }
+
+    #[test]
+    fn shipped_levels_survive_a_round_trip() {
+        for level_name in &["0-tutorial", "1-starting", "2-for-real", "3-zimbrao"] {
+            let level = parse_level(level_name).unwrap();
+            let xsb_level = round_trip(&level);
+
+            assert_eq!(xsb_string(&xsb_level.map, xsb_level.player_position),
+                       xsb_string(&level.map, level.player_position), "{}", level_name);
+            assert_eq!(xsb_level.map.boxes.len(), level.map.boxes.len(), "{}", level_name);
+            assert_eq!(xsb_level.identity_hash(), level.identity_hash(), "{}", level_name);
+        }
+    }
+}